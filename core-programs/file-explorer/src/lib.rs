@@ -1,25 +1,34 @@
 use std::{
+    collections::HashMap,
     ffi::OsStr,
     fs,
-    path::{Component, PathBuf},
+    path::{Component, Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::{anyhow, Error};
 use tooltrain_data::{CommanderCoder, CommanderPathDataType};
 use tooltrain_rust_guest::{
-    add_tree_output,
+    add_tree_output, export_guest, named_inputs,
     tooltrain::base::{
         inputs::ArgumentSpec,
         streaming_inputs::Input,
-        streaming_outputs::{TreeNode, TreeOutput, TreeOutputRequest},
+        streaming_outputs::{TreeOutput, TreeOutputRequest},
     },
-    export_guest, Guest, Schema,
+    Guest, OutputSpec, Schema, TreeNodeBuilder,
 };
 use parking_lot::RwLock;
 use tokio::{runtime, task::JoinHandle};
 use tokio_stream::StreamExt;
 
+fn tree_output_spec() -> OutputSpec {
+    OutputSpec::tree(
+        "Tree",
+        "A tree of files and directories starting at the specified root",
+        &CommanderPathDataType {},
+    )
+}
+
 struct FileExplorerProgram;
 
 impl Guest for FileExplorerProgram {
@@ -33,7 +42,10 @@ impl Guest for FileExplorerProgram {
                 data_type: CommanderPathDataType {}.type_string(),
                 supports_updates: true,
             }],
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            category: Some("Files".to_string()),
             performs_state_change: false,
+            declared_outputs: vec![tree_output_spec()],
         }
     }
 
@@ -42,20 +54,22 @@ impl Guest for FileExplorerProgram {
             .enable_time()
             .build()
             .map_err(|e| e.to_string())?;
+        let inputs = named_inputs(&Self::get_schema(), inputs).map_err(|e| e.to_string())?;
         let result = runtime.block_on(run_internal(inputs));
         result.map_err(|e| e.to_string())
     }
 }
 
-async fn run_internal(inputs: Vec<Input>) -> Result<String, Error> {
-    let Input::ValueInput(path_input) = &inputs[0] else {
-        return Err(anyhow!("First input is not a value"));
+async fn run_internal(inputs: HashMap<String, Input>) -> Result<String, Error> {
+    let Some(Input::ValueInput(path_input)) = inputs.get("root") else {
+        return Err(anyhow!("The root input is not a value"));
     };
 
+    let spec = tree_output_spec();
     let tree_output = Arc::new(RwLock::new(add_tree_output(
-        "Tree",
-        "A tree of files and directories starting at the specified root",
-        "path",
+        &spec.name,
+        &spec.description,
+        &spec.data_type,
     )));
 
     let mut running_job: Option<JoinHandle<()>> = None;
@@ -85,33 +99,116 @@ struct FileExplorer {
     output: Arc<RwLock<TreeOutput>>,
 }
 
+/// Most symlinks followed while resolving a single path, matching Linux's `ELOOP` limit.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Returns true if the node at `relative_path` under `root` resolves to the same directory as the
+/// root or one of the node's ancestors, meaning a symlink points back up the tree and expanding it
+/// would never end. Symlinks are resolved one at a time with `read_link` rather than with
+/// `fs::canonicalize`, which WASI does not support. Paths that can't be resolved are not cycles;
+/// reading them fails on its own.
+fn is_symlink_cycle(root: &Path, relative_path: &[&str]) -> bool {
+    let mut resolved = root.to_path_buf();
+    let mut ancestors = vec![resolved.clone()];
+    let mut hops = 0;
+    for component in relative_path {
+        if resolve_component(&mut resolved, OsStr::new(component), &mut hops).is_err() {
+            return false;
+        }
+        if ancestors.contains(&resolved) {
+            return true;
+        }
+        ancestors.push(resolved.clone());
+    }
+    false
+}
+
+/// Appends `component` to the already resolved path `resolved`, following it if it is a symlink.
+fn resolve_component(
+    resolved: &mut PathBuf,
+    component: &OsStr,
+    hops: &mut usize,
+) -> Result<(), Error> {
+    if component == ".." {
+        resolved.pop();
+        return Ok(());
+    }
+    if component == "." || component.is_empty() {
+        return Ok(());
+    }
+
+    let candidate = resolved.join(component);
+    if !fs::symlink_metadata(&candidate)?.file_type().is_symlink() {
+        *resolved = candidate;
+        return Ok(());
+    }
+
+    *hops += 1;
+    if *hops > MAX_SYMLINK_HOPS {
+        return Err(anyhow!("Too many symlinks in {}", candidate.display()));
+    }
+    for target_component in fs::read_link(&candidate)?.components() {
+        match target_component {
+            Component::Prefix(_) | Component::RootDir => {
+                resolved.push(target_component.as_os_str())
+            }
+            _ => resolve_component(resolved, target_component.as_os_str(), hops)?,
+        }
+    }
+    Ok(())
+}
+
 impl FileExplorer {
     async fn run(&self) {
-        self.add_paths(vec![]).await;
+        self.load(None).await;
         let stream = self.output.read().get_request_stream();
 
         while let TreeOutputRequest::LoadChildren(parent_id) = stream.poll_request_blocking() {
-            let relative_path: Vec<&str> = parent_id.split('/').collect();
-            self.add_paths(relative_path).await;
+            self.load(Some(parent_id)).await;
         }
     }
 
-    async fn add_paths(&self, relative_path: Vec<&str>) {
-        if !FileExplorer::validate_relative_path(&relative_path) {
-            eprintln!("Invalid relative path: {}", relative_path.join("/"));
+    /// Loads the children of `parent_id`, or the root when it is `None`. A failed load is
+    /// reported to the host, which would otherwise wait for the children forever.
+    async fn load(&self, parent_id: Option<String>) {
+        let relative_path: Vec<&str> = match &parent_id {
+            Some(parent_id) => parent_id.split('/').collect(),
+            None => vec![],
+        };
+        let Err(error) = self.add_paths(relative_path).await else {
             return;
+        };
+        eprintln!("{}", error);
+        if let Some(parent_id) = &parent_id {
+            self.output.read().fail_load(parent_id, &error.to_string());
+        }
+    }
+
+    async fn add_paths(&self, relative_path: Vec<&str>) -> Result<(), Error> {
+        if !FileExplorer::validate_relative_path(&relative_path) {
+            return Err(anyhow!(
+                "Invalid relative path: {}",
+                relative_path.join("/")
+            ));
+        }
+
+        if is_symlink_cycle(&self.root, &relative_path) {
+            return Err(anyhow!(
+                "Refusing to expand a symlink back up the tree: {}",
+                relative_path.join("/")
+            ));
         }
 
         let relative_pathbuf = PathBuf::from_iter(relative_path.clone());
         let full_pathbuf = self.root.join(relative_pathbuf.clone());
 
-        let Ok(dir) = fs::read_dir(full_pathbuf.clone()) else {
-            eprintln!(
-                "Directory does not exist: {}",
-                full_pathbuf.to_string_lossy()
-            );
-            return;
-        };
+        let dir = fs::read_dir(full_pathbuf.clone()).map_err(|error| {
+            anyhow!(
+                "Could not read directory {}: {}",
+                full_pathbuf.to_string_lossy(),
+                error
+            )
+        })?;
 
         let parent_node_id = if relative_path.is_empty() {
             None
@@ -119,40 +216,100 @@ impl FileExplorer {
             Some(relative_pathbuf.clone().to_string_lossy().to_string())
         };
 
-        let children: Vec<TreeNode> = dir
+        let children: Vec<TreeNodeBuilder> = dir
             .filter_map(Result::ok)
-            .map(|entry| TreeNode {
-                id: relative_pathbuf
-                    .clone()
-                    .join(entry.file_name())
-                    .to_string_lossy()
-                    .to_string(),
-                has_children: entry.file_type().map(|t| t.is_dir()).unwrap_or(false),
-                value: CommanderPathDataType {}
-                    .encode(
-                        full_pathbuf
-                            .clone()
-                            .join(entry.file_name())
-                            .components()
-                            .map(Component::as_os_str)
-                            .map(OsStr::to_string_lossy)
-                            .map(String::from)
-                            .collect(),
-                    )
-                    .unwrap(),
+            .map(|entry| {
+                TreeNodeBuilder::new(
+                    relative_pathbuf
+                        .clone()
+                        .join(entry.file_name())
+                        .to_string_lossy(),
+                )
+                .has_children(entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                .label(entry.file_name().to_string_lossy())
+                .value(
+                    &CommanderPathDataType {},
+                    full_pathbuf
+                        .clone()
+                        .join(entry.file_name())
+                        .components()
+                        .map(Component::as_os_str)
+                        .map(OsStr::to_string_lossy)
+                        .map(String::from)
+                        .collect(),
+                )
             })
             .collect();
 
+        // The host may have collapsed the node while its directory was being read.
+        if let Some(parent_node_id) = &parent_node_id {
+            if self.output.read().is_load_cancelled(parent_node_id) {
+                return Ok(());
+            }
+        }
+
         self.output
             .write()
-            .add(parent_node_id.as_deref(), &children);
+            .reconcile_children(parent_node_id.as_deref(), children)
+            .map_err(|error| anyhow!("Could not update children: {}", error))
     }
 
     fn validate_relative_path(relative_path: &[&str]) -> bool {
-        relative_path
-            .iter()
-            .all(|component| *component != ".." && !component.contains('/'))
+        relative_path.iter().all(|component| {
+            *component != ".."
+                && !component.contains(['/', '\\'])
+                && !FileExplorer::has_drive_prefix(component)
+        })
+    }
+
+    fn has_drive_prefix(component: &str) -> bool {
+        let bytes = component.as_bytes();
+        bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
     }
 }
 
 export_guest!(FileExplorerProgram);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_escaping_components() {
+        assert!(FileExplorer::validate_relative_path(&["a", "b"]));
+        assert!(!FileExplorer::validate_relative_path(&["a", ".."]));
+        assert!(!FileExplorer::validate_relative_path(&["a/b"]));
+        assert!(!FileExplorer::validate_relative_path(&["a\\b"]));
+        assert!(!FileExplorer::validate_relative_path(&["C:"]));
+        assert!(!FileExplorer::validate_relative_path(&["c:Windows"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_to_expand_symlink_cycles() {
+        use std::os::unix::fs::symlink;
+
+        let root = std::env::temp_dir().join(format!("file-explorer-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir/nested")).unwrap();
+        fs::create_dir_all(root.join("other")).unwrap();
+        symlink(&root, root.join("dir/loop")).unwrap();
+        symlink("..", root.join("dir/nested/up")).unwrap();
+        symlink("../other", root.join("dir/sibling")).unwrap();
+        symlink("sibling", root.join("dir/chained")).unwrap();
+
+        assert!(!is_symlink_cycle(&root, &[]));
+        assert!(!is_symlink_cycle(&root, &["dir"]));
+        assert!(!is_symlink_cycle(&root, &["dir", "nested"]));
+        assert!(is_symlink_cycle(&root, &["dir", "loop"]));
+        assert!(is_symlink_cycle(&root, &["dir", "loop", "dir"]));
+        assert!(is_symlink_cycle(&root, &["dir", "nested", "up"]));
+        // Links to directories elsewhere in the tree are not cycles, however many hops they take.
+        assert!(!is_symlink_cycle(&root, &["dir", "sibling"]));
+        assert!(!is_symlink_cycle(&root, &["dir", "chained"]));
+        // Missing paths can't be resolved, and are left for `read_dir` to reject.
+        assert!(!is_symlink_cycle(&root, &["missing"]));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,21 +1,31 @@
+use std::collections::HashMap;
+
+use maplit::btreemap;
+use once_cell::sync::Lazy;
 use tooltrain_data::{
-    CommanderCoder, CommanderEnumDataType, CommanderNumberDataType, CommanderPathDataType,
-    CommanderStringDataType, CommanderStructDataType, CommanderStructTypeBuilder, CommanderValue,
+    CommanderBooleanDataType, CommanderCoder, CommanderEnumDataType, CommanderNumberDataType,
+    CommanderPathDataType, CommanderStringDataType, CommanderStructDataType,
+    CommanderStructTypeBuilder, CommanderValue,
 };
 use tooltrain_rust_guest::{
-    add_list_output,
+    add_list_output, export_guest, named_inputs,
     tooltrain::base::{inputs::ArgumentSpec, streaming_inputs::Input},
-    export_guest,
     wasi::{
         self,
         filesystem::types::{
-            Descriptor, DescriptorFlags, DescriptorStat, DescriptorType, OpenFlags, PathFlags,
+            Descriptor, DescriptorFlags, DescriptorStat, DescriptorType, ErrorCode, OpenFlags,
+            PathFlags,
         },
     },
-    Guest, ListOutput, Schema,
+    Guest, ListOutput, OutputSpec, Schema,
 };
-use maplit::btreemap;
-use once_cell::sync::Lazy;
+
+// How many levels of subdirectories are walked when computing a directory's size.
+const MAX_SIZE_DEPTH: u32 = 64;
+
+// The most directories a path may descend through. Paths are decoded from untrusted input, so
+// this bounds the work done for a pathological path.
+const MAX_PATH_DEPTH: usize = 1024;
 
 static FILE_ENTITY_TYPE: Lazy<CommanderEnumDataType> = Lazy::new(|| {
     CommanderEnumDataType::new(
@@ -29,11 +39,27 @@ static FILE_ENTITY_TYPE: Lazy<CommanderEnumDataType> = Lazy::new(|| {
     )
 });
 
+static SORT_BY: Lazy<CommanderEnumDataType> = Lazy::new(|| {
+    CommanderEnumDataType::new(
+        "SortBy".to_string(),
+        vec![
+            "DIRECTORY_ORDER".to_string(),
+            "NAME".to_string(),
+            "SIZE".to_string(),
+            "MODIFIED".to_string(),
+        ],
+    )
+});
+
 static FILE_STRUCT: Lazy<CommanderStructDataType> = Lazy::new(|| {
     CommanderStructTypeBuilder::new("File")
-        .add_field("name", CommanderStringDataType {})
-        .add_field("size", CommanderNumberDataType {})
-        .add_field("type", FILE_ENTITY_TYPE.clone())
+        .add_field_with_description("name", "The name of the file", CommanderStringDataType {})
+        .add_field_with_description("size", "The size in bytes", CommanderNumberDataType {})
+        .add_field_with_description(
+            "type",
+            "Whether this is a file, directory or symlink",
+            FILE_ENTITY_TYPE.clone(),
+        )
         .build()
 });
 
@@ -55,6 +81,217 @@ impl FileEntityType {
     }
 }
 
+struct DirectoryEntry {
+    name: String,
+    size: u64,
+    is_directory: bool,
+}
+
+/// The directory operations needed to compute the total size of a directory tree.
+trait Directory: Sized {
+    /// Lists the entries of this directory without following symlinks.
+    fn entries(&self) -> Result<Vec<DirectoryEntry>, String>;
+
+    fn open(&self, name: &str) -> Result<Self, String>;
+}
+
+impl Directory for Descriptor {
+    fn entries(&self) -> Result<Vec<DirectoryEntry>, String> {
+        let entry_stream = self
+            .read_directory()
+            .map_err(|code| format!("Error opening directory: {:?}", code))?;
+        let mut entries = vec![];
+        while let Some(entry) = entry_stream
+            .read_directory_entry()
+            .map_err(|code| format!("Error reading directory: {:?}", code))?
+        {
+            let stat = self
+                .stat_at(PathFlags::empty(), &entry.name)
+                .map_err(|code| format!("Error reading {} (code: {code})", entry.name))?;
+            entries.push(DirectoryEntry {
+                name: entry.name,
+                size: stat.size,
+                is_directory: stat.type_ == DescriptorType::Directory,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn open(&self, name: &str) -> Result<Self, String> {
+        self.open_at(
+            PathFlags::SYMLINK_FOLLOW,
+            name,
+            OpenFlags::DIRECTORY,
+            DescriptorFlags::READ,
+        )
+        .map_err(|code| open_error_message(name, code))
+    }
+}
+
+/// Drops `.` and empty components, which paths decoded from arbitrary component lists may
+/// contain, so that each remaining component names a directory to open. `..` is rejected rather
+/// than resolved, as it could escape the preopened directory, and paths deeper than
+/// `MAX_PATH_DEPTH` are rejected.
+fn normalize_path_components(components: &[String]) -> Result<Vec<&str>, String> {
+    let normalized = components
+        .iter()
+        .map(String::as_str)
+        .filter(|component| !component.is_empty() && *component != ".")
+        .map(|component| {
+            if component == ".." {
+                Err("Paths cannot contain parent directory (..) components".to_string())
+            } else {
+                Ok(component)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if normalized.len() > MAX_PATH_DEPTH {
+        return Err(format!(
+            "Path is {} directories deep, but at most {} are supported",
+            normalized.len(),
+            MAX_PATH_DEPTH
+        ));
+    }
+    Ok(normalized)
+}
+
+/// Describes why the directory `name` could not be opened in terms a user can act on.
+fn open_error_message(name: &str, code: ErrorCode) -> String {
+    match code {
+        ErrorCode::NoEntry => format!("Directory {} does not exist", name),
+        ErrorCode::NotDirectory => format!("{} is not a directory", name),
+        ErrorCode::Access | ErrorCode::NotPermitted => {
+            format!("Permission denied opening directory {}", name)
+        }
+        ErrorCode::Loop => format!("Too many symlinks while opening directory {}", name),
+        ErrorCode::NameTooLong => format!("The name of directory {} is too long", name),
+        code => format!("Could not open directory {} (code {code})", name),
+    }
+}
+
+/// Sums the sizes of all files in `directory`, descending at most `max_depth` levels into its
+/// subdirectories. Symlinks are not followed, and subdirectories that cannot be opened or read
+/// are skipped, so one unreadable subdirectory does not fail the whole listing.
+fn directory_size<D: Directory>(directory: &D, max_depth: u32) -> Result<u64, String> {
+    let mut total = 0;
+    for entry in directory.entries()? {
+        if !entry.is_directory {
+            total += entry.size;
+        } else if max_depth > 0 {
+            let child_size = directory
+                .open(&entry.name)
+                .and_then(|child| directory_size(&child, max_depth - 1));
+            match child_size {
+                Ok(size) => total += size,
+                Err(error) => {
+                    eprintln!("Skipping {} when computing its size: {}", entry.name, error)
+                }
+            }
+        }
+    }
+    Ok(total)
+}
+
+/// Matches `name` against a glob `pattern`, where `*` matches any run of characters and `?`
+/// matches exactly one. An empty pattern matches everything.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    if pattern.is_empty() {
+        return true;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` seen, and the name position it was tried against.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_n)) => {
+                    p = star + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// A directory entry as it is reported in the output list.
+struct ListedFile {
+    name: String,
+    size: u64,
+    // Seconds and nanoseconds since the Unix epoch, when known.
+    modified: Option<(u64, u32)>,
+    file_type: CommanderValue,
+}
+
+impl ListedFile {
+    fn encode(self) -> Vec<u8> {
+        FILE_STRUCT
+            .encode(btreemap! {
+                "name".to_string() => self.name.into(),
+                "size".to_string() => (self.size as f64).into(),
+                "type".to_string() => self.file_type,
+            })
+            .unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    /// Returns `None` for `DIRECTORY_ORDER`, which leaves entries unsorted.
+    fn from_variant(variant: &str) -> Option<SortKey> {
+        match variant {
+            "NAME" => Some(SortKey::Name),
+            "SIZE" => Some(SortKey::Size),
+            "MODIFIED" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+}
+
+fn sort_files(files: &mut [ListedFile], key: SortKey, descending: bool) {
+    files.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        };
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+struct ListOptions {
+    recursive_sizes: bool,
+    pattern: String,
+    sort_by: Option<SortKey>,
+    descending: bool,
+}
+
+fn files_output_spec() -> OutputSpec {
+    OutputSpec::list("Files", "The list of files", &*FILE_STRUCT)
+}
+
 struct ListProgram;
 
 impl Guest for ListProgram {
@@ -62,18 +299,51 @@ impl Guest for ListProgram {
         Schema {
             name: "List Files".to_string(),
             description: "List files in a directory".to_string(),
-            arguments: vec![ArgumentSpec {
-                name: "directory".to_string(),
-                description: "The top-level directory to list files in".to_string(),
-                data_type: CommanderPathDataType {}.type_string(),
-                supports_updates: false,
-            }],
+            arguments: vec![
+                ArgumentSpec {
+                    name: "directory".to_string(),
+                    description: "The top-level directory to list files in".to_string(),
+                    data_type: CommanderPathDataType {}.type_string(),
+                    supports_updates: false,
+                },
+                ArgumentSpec {
+                    name: "recursive_sizes".to_string(),
+                    description: "Report the total size of the files in each directory".to_string(),
+                    data_type: CommanderBooleanDataType {}.type_string(),
+                    supports_updates: false,
+                },
+                ArgumentSpec {
+                    name: "pattern".to_string(),
+                    description: "Only list files whose names match this glob, like *.rs"
+                        .to_string(),
+                    data_type: CommanderStringDataType {}.type_string(),
+                    supports_updates: false,
+                },
+                ArgumentSpec {
+                    name: "sort_by".to_string(),
+                    description: "The order to list files in. Sorted files are only listed once \
+                        the whole directory has been read"
+                        .to_string(),
+                    data_type: SORT_BY.type_string(),
+                    supports_updates: false,
+                },
+                ArgumentSpec {
+                    name: "descending".to_string(),
+                    description: "Reverse the sort order".to_string(),
+                    data_type: CommanderBooleanDataType {}.type_string(),
+                    supports_updates: false,
+                },
+            ],
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            category: Some("Files".to_string()),
             performs_state_change: false,
+            declared_outputs: vec![files_output_spec()],
         }
     }
 
     fn run(inputs: Vec<Input>) -> Result<String, String> {
-        let Some(Input::ValueInput(path)) = &inputs.first() else {
+        let inputs = named_inputs(&Self::get_schema(), inputs).map_err(|e| e.to_string())?;
+        let Some(Input::ValueInput(path)) = inputs.get("directory") else {
             return Err("Invalid input".to_string());
         };
         let pathbuf = CommanderPathDataType {}
@@ -84,19 +354,66 @@ impl Guest for ListProgram {
             .map(|c| c.as_os_str().to_string_lossy().to_string())
             .collect();
 
+        let options = ListOptions {
+            recursive_sizes: ListProgram::optional_argument(
+                &inputs,
+                "recursive_sizes",
+                CommanderBooleanDataType {},
+            )?
+            .unwrap_or(false),
+            pattern: ListProgram::optional_argument(
+                &inputs,
+                "pattern",
+                CommanderStringDataType {},
+            )?
+            .unwrap_or_default(),
+            sort_by: ListProgram::optional_argument(&inputs, "sort_by", SORT_BY.clone())?
+                .and_then(|variant| SortKey::from_variant(variant.get_name())),
+            descending: ListProgram::optional_argument(
+                &inputs,
+                "descending",
+                CommanderBooleanDataType {},
+            )?
+            .unwrap_or(false),
+        };
+
         let (base, _) = wasi::filesystem::preopens::get_directories().pop().unwrap();
         let descriptor = ListProgram::navigate_to_dir(base, &path_components)?;
 
-        let list_output_handle =
-            add_list_output("Files", "The list of files", &FILE_STRUCT.type_string());
-        ListProgram::list_files_in_dir(descriptor, list_output_handle)
+        let spec = files_output_spec();
+        let list_output_handle = add_list_output(&spec.name, &spec.description, &spec.data_type);
+        ListProgram::list_files_in_dir(descriptor, list_output_handle, &options)
     }
 }
 
 impl ListProgram {
-    fn list_files_in_dir(descriptor: Descriptor, output: ListOutput) -> Result<String, String> {
+    /// Decodes the argument named `name`, which is `None` if the argument was not passed or has
+    /// no value.
+    fn optional_argument<DT: CommanderCoder>(
+        inputs: &HashMap<String, Input>,
+        name: &str,
+        data_type: DT,
+    ) -> Result<Option<DT::Value>, String> {
+        match inputs.get(name) {
+            Some(Input::ValueInput(input)) => input
+                .get()
+                .map(|value| data_type.decode(&value))
+                .transpose()
+                .map_err(|_| format!("Could not read {}", name)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Streams each matching entry to `output` as it is read, unless the entries need to be
+    /// sorted, in which case the whole directory is read before anything is added.
+    fn list_files_in_dir(
+        descriptor: Descriptor,
+        output: ListOutput,
+        options: &ListOptions,
+    ) -> Result<String, String> {
         let entry_stream = wasi::filesystem::types::Descriptor::read_directory(&descriptor)
             .map_err(|code| format!("Error opening directory: {:?}", code))?;
+        let mut sorted_files = vec![];
         loop {
             let maybe_entry =
                 wasi::filesystem::types::DirectoryEntryStream::read_directory_entry(&entry_stream)
@@ -105,39 +422,58 @@ impl ListProgram {
                 break;
             }
             let file_entry = maybe_entry.unwrap();
+            if !glob_matches(&options.pattern, &file_entry.name) {
+                continue;
+            }
             let file_stat = wasi::filesystem::types::Descriptor::stat_at(
                 &descriptor,
                 PathFlags::SYMLINK_FOLLOW,
                 &file_entry.name,
             )
             .map_err(|code| format!("Error reading {} (code: {code})", file_entry.name))?;
+            let size = if options.recursive_sizes && file_stat.type_ == DescriptorType::Directory {
+                directory_size(&descriptor.open(&file_entry.name)?, MAX_SIZE_DEPTH)?
+            } else {
+                file_stat.size
+            };
 
-            output.add(
-                &FILE_STRUCT
-                    .encode(btreemap! {
-                        "name".to_string() => file_entry.name.into(),
-                        "size".to_string() => (file_stat.size as f64).into(),
-                        "type".to_string() => ListProgram::file_stat_to_type_enum(&file_stat),
-                    })
-                    .unwrap(),
-            );
+            let file = ListedFile {
+                name: file_entry.name,
+                size,
+                modified: file_stat
+                    .data_modification_timestamp
+                    .map(|time| (time.seconds, time.nanoseconds)),
+                file_type: ListProgram::file_stat_to_type_enum(&file_stat),
+            };
+            if options.sort_by.is_some() {
+                sorted_files.push(file);
+            } else {
+                output.add(&file.encode());
+            }
+        }
+
+        if let Some(sort_by) = options.sort_by {
+            sort_files(&mut sorted_files, sort_by, options.descending);
+            for file in sorted_files {
+                output.add(&file.encode());
+            }
         }
         Ok("Done".to_string())
     }
 
     fn navigate_to_dir(base: Descriptor, path: &[String]) -> Result<Descriptor, String> {
-        if path.is_empty() {
-            return Ok(base);
+        let mut directory = base;
+        for component in normalize_path_components(path)? {
+            directory = directory
+                .open_at(
+                    PathFlags::SYMLINK_FOLLOW,
+                    component,
+                    OpenFlags::DIRECTORY,
+                    DescriptorFlags::READ,
+                )
+                .map_err(|code| open_error_message(component, code))?;
         }
-        let next_dir = wasi::filesystem::types::Descriptor::open_at(
-            &base,
-            PathFlags::SYMLINK_FOLLOW,
-            &path[0],
-            OpenFlags::DIRECTORY,
-            DescriptorFlags::READ,
-        )
-        .map_err(|code| format!("Could not open directory {} (code {code})", path[0]))?;
-        ListProgram::navigate_to_dir(next_dir, &path[1..])
+        Ok(directory)
     }
 
     fn file_stat_to_type_enum(stat: &DescriptorStat) -> CommanderValue {
@@ -151,3 +487,222 @@ impl ListProgram {
 }
 
 export_guest!(ListProgram);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tooltrain_rust_guest::OutputKind;
+
+    /// An in-memory directory tree. Directories whose entries are `None` cannot be read.
+    #[derive(Clone)]
+    enum MemoryEntry {
+        File(u64),
+        Directory(Option<BTreeMap<&'static str, MemoryEntry>>),
+    }
+
+    fn directory(entries: impl IntoIterator<Item = (&'static str, MemoryEntry)>) -> MemoryEntry {
+        MemoryEntry::Directory(Some(entries.into_iter().collect()))
+    }
+
+    impl Directory for MemoryEntry {
+        fn entries(&self) -> Result<Vec<DirectoryEntry>, String> {
+            let MemoryEntry::Directory(Some(entries)) = self else {
+                return Err("Error reading directory: Access".to_string());
+            };
+            Ok(entries
+                .iter()
+                .map(|(name, entry)| DirectoryEntry {
+                    name: name.to_string(),
+                    size: match entry {
+                        MemoryEntry::File(size) => *size,
+                        MemoryEntry::Directory(_) => 4096,
+                    },
+                    is_directory: matches!(entry, MemoryEntry::Directory(_)),
+                })
+                .collect())
+        }
+
+        fn open(&self, name: &str) -> Result<Self, String> {
+            let MemoryEntry::Directory(Some(entries)) = self else {
+                return Err(open_error_message(name, ErrorCode::Access));
+            };
+            match entries.get(name) {
+                Some(entry @ MemoryEntry::Directory(_)) => Ok(entry.clone()),
+                Some(MemoryEntry::File(_)) => {
+                    Err(open_error_message(name, ErrorCode::NotDirectory))
+                }
+                None => Err(open_error_message(name, ErrorCode::NoEntry)),
+            }
+        }
+    }
+
+    #[test]
+    fn normalizes_dot_and_empty_path_components() {
+        let components = |path: &[&str]| path.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            normalize_path_components(&components(&["Users", ".", "Documents"])).unwrap(),
+            vec!["Users", "Documents"]
+        );
+        assert_eq!(
+            normalize_path_components(&components(&["", "Users", "", "Documents", ""])).unwrap(),
+            vec!["Users", "Documents"]
+        );
+        assert!(normalize_path_components(&components(&[".", ""]))
+            .unwrap()
+            .is_empty());
+        assert!(normalize_path_components(&components(&["Users", "..", "etc"])).is_err());
+    }
+
+    #[test]
+    fn rejects_very_deep_paths() {
+        let deep = vec!["nested".to_string(); MAX_PATH_DEPTH];
+        assert_eq!(
+            normalize_path_components(&deep).unwrap().len(),
+            MAX_PATH_DEPTH
+        );
+
+        let too_deep = vec!["nested".to_string(); 100_000];
+        assert!(normalize_path_components(&too_deep)
+            .unwrap_err()
+            .contains("100000 directories deep"));
+
+        // Components that are dropped do not count towards the limit.
+        let mut padded = vec![".".to_string(); 100_000];
+        padded.push("nested".to_string());
+        assert_eq!(normalize_path_components(&padded).unwrap(), vec!["nested"]);
+    }
+
+    #[test]
+    fn explains_why_directories_cannot_be_opened() {
+        assert_eq!(
+            open_error_message("missing", ErrorCode::NoEntry),
+            "Directory missing does not exist"
+        );
+        assert_eq!(
+            open_error_message("file.txt", ErrorCode::NotDirectory),
+            "file.txt is not a directory"
+        );
+        assert_eq!(
+            open_error_message("secrets", ErrorCode::Access),
+            "Permission denied opening directory secrets"
+        );
+        assert_eq!(
+            open_error_message("secrets", ErrorCode::NotPermitted),
+            "Permission denied opening directory secrets"
+        );
+        assert_eq!(
+            open_error_message("link", ErrorCode::Loop),
+            "Too many symlinks while opening directory link"
+        );
+        assert_eq!(
+            open_error_message("long", ErrorCode::NameTooLong),
+            "The name of directory long is too long"
+        );
+        assert_eq!(
+            open_error_message("busy", ErrorCode::Busy),
+            format!("Could not open directory busy (code {})", ErrorCode::Busy)
+        );
+    }
+
+    #[test]
+    fn sums_file_sizes_below_a_directory() {
+        let root = directory([
+            ("top.txt", MemoryEntry::File(7)),
+            (
+                "a",
+                directory([
+                    ("one.txt", MemoryEntry::File(10)),
+                    ("b", directory([("two.txt", MemoryEntry::File(5))])),
+                ]),
+            ),
+        ]);
+
+        assert_eq!(directory_size(&root, MAX_SIZE_DEPTH).unwrap(), 22);
+        assert_eq!(
+            directory_size(&root.open("a").unwrap(), MAX_SIZE_DEPTH).unwrap(),
+            15
+        );
+        assert_eq!(directory_size(&root, 1).unwrap(), 17);
+        assert_eq!(directory_size(&root, 0).unwrap(), 7);
+    }
+
+    #[test]
+    fn skips_subdirectories_that_cannot_be_read() {
+        let root = directory([
+            ("top.txt", MemoryEntry::File(7)),
+            ("locked", MemoryEntry::Directory(None)),
+            ("a", directory([("one.txt", MemoryEntry::File(10))])),
+        ]);
+        assert_eq!(directory_size(&root, MAX_SIZE_DEPTH).unwrap(), 17);
+
+        // Only the directory being sized itself has to be readable.
+        assert!(directory_size(&MemoryEntry::Directory(None), MAX_SIZE_DEPTH).is_err());
+    }
+
+    #[test]
+    fn matches_glob_patterns() {
+        assert!(glob_matches("", "anything"));
+        assert!(glob_matches("*.rs", "lib.rs"));
+        assert!(!glob_matches("*.rs", "lib.rs.bak"));
+        assert!(glob_matches("*.txt", ".txt"));
+        assert!(!glob_matches("*.txt", "txt"));
+        assert!(!glob_matches("*.txt", "notes.txt.d"));
+        assert!(glob_matches("lib.?s", "lib.rs"));
+        assert!(glob_matches("*a*b*", "xxaxxbxx"));
+        assert!(!glob_matches("*a*b", "xxbxxa"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("?", ""));
+    }
+
+    #[test]
+    fn sorts_files_by_size_descending() {
+        let file = |name: &str, size: u64, modified: Option<(u64, u32)>| ListedFile {
+            name: name.to_string(),
+            size,
+            modified,
+            file_type: FileEntityType::File.to_tooltrain_value(),
+        };
+        let mut files = vec![
+            file("small", 1, Some((20, 0))),
+            file("large", 30, None),
+            file("medium", 12, Some((10, 5))),
+        ];
+        let names = |files: &[ListedFile]| {
+            files
+                .iter()
+                .map(|file| file.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        sort_files(&mut files, SortKey::Size, true);
+        assert_eq!(names(&files), vec!["large", "medium", "small"]);
+
+        sort_files(&mut files, SortKey::Name, true);
+        assert_eq!(names(&files), vec!["small", "medium", "large"]);
+
+        // Files without a modification time sort first.
+        sort_files(&mut files, SortKey::Modified, false);
+        assert_eq!(names(&files), vec!["large", "medium", "small"]);
+
+        assert_eq!(SortKey::from_variant("DIRECTORY_ORDER"), None);
+    }
+
+    #[test]
+    fn declares_files_output_in_schema() {
+        let schema = ListProgram::get_schema();
+        let [output] = schema.declared_outputs.as_slice() else {
+            panic!("Expected exactly one declared output");
+        };
+        assert_eq!(output.name, "Files");
+        assert_eq!(output.kind, OutputKind::List);
+        assert_eq!(output.data_type, FILE_STRUCT.type_string());
+    }
+
+    #[test]
+    fn reports_package_version_in_schema() {
+        let schema = ListProgram::get_schema();
+        assert_eq!(schema.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(schema.category.as_deref(), Some("Files"));
+    }
+}
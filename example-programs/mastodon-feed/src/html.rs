@@ -0,0 +1,106 @@
+/// Converts the HTML Mastodon uses for status content into plain text. Tags are stripped,
+/// entities are decoded, and line breaks and paragraphs become newlines.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut chars = html.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                let tag: String = chars.by_ref().take_while(|c| *c != '>').collect();
+                let name = tag
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                match name.as_str() {
+                    "br" => text.push('\n'),
+                    "p" if tag.starts_with('/') => text.push_str("\n\n"),
+                    _ => {}
+                }
+            }
+            '&' => {
+                let mut entity = String::new();
+                let mut terminated = false;
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        terminated = true;
+                        break;
+                    }
+                    entity.push(c);
+                    if entity.len() > 10 {
+                        break;
+                    }
+                }
+                match decode_entity(&entity).filter(|_| terminated) {
+                    Some(decoded) => text.push(decoded),
+                    None => {
+                        text.push('&');
+                        text.push_str(&entity);
+                        if terminated {
+                            text.push(';');
+                        }
+                    }
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    text.trim_end().to_string()
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok()?
+            } else {
+                entity.strip_prefix('#')?.parse().ok()?
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_tags_and_splits_paragraphs() {
+        assert_eq!(
+            html_to_text("<p>Hello <strong>world</strong></p><p>Second paragraph</p>"),
+            "Hello world\n\nSecond paragraph"
+        );
+        assert_eq!(
+            html_to_text("<p>One<br>Two<br />Three</p>"),
+            "One\nTwo\nThree"
+        );
+    }
+
+    #[test]
+    fn flattens_mentions_and_links() {
+        let content = "<p><span class=\"h-card\"><a href=\"https://mastodon.social/@user\" \
+                       class=\"u-url mention\">@<span>user</span></a></span> see \
+                       <a href=\"https://example.com\" rel=\"nofollow\">example.com</a></p>";
+        assert_eq!(html_to_text(content), "@user see example.com");
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            html_to_text("<p>Fish &amp; chips &lt;3 &quot;yum&quot; &#39;ok&#39; &#x1F600;</p>"),
+            "Fish & chips <3 \"yum\" 'ok' \u{1F600}"
+        );
+        assert_eq!(html_to_text("AT&T & friends"), "AT&T & friends");
+    }
+}
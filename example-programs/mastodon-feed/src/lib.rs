@@ -1,66 +1,127 @@
-use tooltrain_data::{CommanderCoder, CommanderPathDataType};
-use tooltrain_rust_guest::tooltrain::base::inputs::ArgumentSpec;
-use tooltrain_rust_guest::tooltrain::base::streaming_outputs::ListOutputRequest;
-use tooltrain_rust_guest::wasi::{
-    http::{
-        self,
-        types::{Fields, IncomingBody, OutgoingRequest, Scheme},
+use std::time::SystemTime;
+
+use tooltrain_data::{CommanderCoder, CommanderStringDataType};
+use tooltrain_rust_guest::{
+    add_list_output, export_guest, named_inputs,
+    tooltrain::base::{
+        inputs::ArgumentSpec, streaming_inputs::Input, streaming_outputs::ListOutputRequest,
+    },
+    wasi::{
+        http::{
+            self,
+            types::{Fields, IncomingBody, OutgoingRequest, Scheme},
+        },
+        io::streams::StreamError,
     },
-    io::streams::StreamError,
+    Guest, ListOutput, OutputSpec, Schema,
 };
-use tooltrain_rust_guest::{Guest, Schema};
 
+mod html;
 mod parse;
+mod retry;
+
+use retry::Attempt;
+
+const PUBLIC_TIMELINE_PATH: &str = "/api/v1/timelines/public";
+const HOME_TIMELINE_PATH: &str = "/api/v1/timelines/home";
+
+/// The timeline being read: the public timeline of an instance, or the home timeline of the
+/// account an access token belongs to.
+struct Feed {
+    instance: String,
+    access_token: Option<String>,
+}
+
+impl Feed {
+    fn timeline_path(&self) -> &'static str {
+        if self.access_token.is_some() {
+            HOME_TIMELINE_PATH
+        } else {
+            PUBLIC_TIMELINE_PATH
+        }
+    }
+
+    fn request_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![
+            ("User-Agent".to_string(), "tooltrain/0.1.0".to_string()),
+            ("Accept".to_string(), "application/json".to_string()),
+        ];
+        if let Some(token) = &self.access_token {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+        headers
+    }
+}
+
+fn feed_output_spec() -> OutputSpec {
+    OutputSpec::list(
+        "Feed",
+        "The feed from the Mastodon instance",
+        &*parse::STATUS_STRUCT,
+    )
+}
 
 struct MastodonFeedProgram;
 
 impl Guest for MastodonFeedProgram {
     fn get_schema() -> Schema {
         Schema {
-            name: "Mastodon Public Feed".to_string(),
-            description: "Returns the public timeline from a Mastodon instance".to_string(),
-            arguments: vec![ArgumentSpec {
-                name: "instance".to_string(),
-                description: "The Mastodon instance to fetch the public feed from".to_string(),
-                data_type: CommanderPathDataType {}.type_string(),
-                supports_updates: false,
-            }],
+            name: "Mastodon Feed".to_string(),
+            description: "Returns the public timeline from a Mastodon instance, or your home \
+                          timeline if an access token is provided"
+                .to_string(),
+            arguments: vec![
+                ArgumentSpec {
+                    name: "instance".to_string(),
+                    description: "The Mastodon instance to fetch the feed from".to_string(),
+                    data_type: CommanderStringDataType {}.type_string(),
+                    supports_updates: false,
+                },
+                ArgumentSpec {
+                    name: "access_token".to_string(),
+                    description: "An optional access token for reading your home timeline"
+                        .to_string(),
+                    data_type: CommanderStringDataType {}.type_string(),
+                    supports_updates: false,
+                },
+            ],
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            category: Some("Social".to_string()),
             performs_state_change: false,
+            declared_outputs: vec![feed_output_spec()],
         }
     }
 
-    fn run(mut inputs: Vec<Value>) -> Result<String, String> {
-        let Some(Value::PrimitiveValue(PrimitiveValue::StringValue(instance))) = inputs.pop()
-        else {
-            return Err("No instance name provided".to_string());
+    fn run(inputs: Vec<Input>) -> Result<String, String> {
+        let inputs = named_inputs(&Self::get_schema(), inputs).map_err(|e| e.to_string())?;
+        let instance = MastodonFeedProgram::read_string_input(inputs.get("instance"))?
+            .ok_or_else(|| "No instance name provided".to_string())?;
+        let access_token = MastodonFeedProgram::read_string_input(inputs.get("access_token"))?
+            .filter(|token| !token.is_empty());
+        let feed = Feed {
+            instance,
+            access_token,
         };
 
-        let list_output = add_list_output(
-            "Feed",
-            "The public feed from the Mastodon instance",
-            &parse::OUTPUT_TABLE_COLUMNS,
-        );
-
-        let first_page = MastodonFeedProgram::request_page(&instance, None)?;
-        let first_page_values: Vec<Vec<PrimitiveValue>> =
-            first_page.iter().map(|v| v.as_output_value()).collect();
-        for value in first_page_values {
-            list_output.add(&Value::CompoundValue(value));
-        }
+        let spec = feed_output_spec();
+        let list_output = add_list_output(&spec.name, &spec.description, &spec.data_type);
+
+        let first_page = MastodonFeedProgram::request_page(&feed, feed.timeline_path())?;
+        MastodonFeedProgram::add_statuses(&list_output, &first_page.statuses)?;
         list_output.set_has_more_rows(true);
 
+        let request_stream = list_output.get_request_stream();
         let mut prev_page = first_page;
         loop {
-            match list_output.poll_request() {
+            match request_stream.poll_request_blocking() {
                 ListOutputRequest::Close => break,
                 ListOutputRequest::LoadMore(_) => {
-                    let max_id = prev_page.last().map(|s| s.id.clone());
-                    let next_page = MastodonFeedProgram::request_page(&instance, max_id)?;
-                    let next_page_values: Vec<Vec<PrimitiveValue>> =
-                        next_page.iter().map(|v| v.as_output_value()).collect();
-                    for value in next_page_values {
-                        list_output.add(&Value::CompoundValue(value));
-                    }
+                    let Some(next_path) = prev_page.next_path(feed.timeline_path()) else {
+                        list_output.set_has_more_rows(false);
+                        continue;
+                    };
+                    let next_page = MastodonFeedProgram::request_page(&feed, &next_path)?;
+                    MastodonFeedProgram::add_statuses(&list_output, &next_page.statuses)?;
                     prev_page = next_page;
                 }
             }
@@ -71,33 +132,49 @@ impl Guest for MastodonFeedProgram {
 }
 
 impl MastodonFeedProgram {
-    fn request_page(
-        mastodon_instance: &str,
-        newest_id: Option<String>,
-    ) -> Result<Vec<parse::Status>, String> {
+    fn read_string_input(input: Option<&Input>) -> Result<Option<String>, String> {
+        let Some(input) = input else {
+            return Ok(None);
+        };
+        let Input::ValueInput(value_input) = input else {
+            return Err("Expected a value input".to_string());
+        };
+        value_input
+            .get()
+            .map(|bytes| CommanderStringDataType {}.decode(&bytes))
+            .transpose()
+            .map_err(|e| format!("Could not read input: {}", e))
+    }
+
+    fn add_statuses(output: &ListOutput, statuses: &[parse::Status]) -> Result<(), String> {
+        for status in statuses {
+            let encoded = parse::STATUS_STRUCT
+                .encode(status.as_output_value())
+                .map_err(|e| format!("Error encoding status {}: {}", status.id, e))?;
+            output.add(&encoded);
+        }
+        Ok(())
+    }
+
+    fn request_page(feed: &Feed, path: &str) -> Result<parse::Page, String> {
+        retry::with_rate_limit_retry(
+            || MastodonFeedProgram::attempt_request_page(feed, path),
+            std::thread::sleep,
+            SystemTime::now,
+        )
+    }
+
+    fn attempt_request_page(feed: &Feed, path: &str) -> Result<Attempt<parse::Page>, String> {
         let headers = Fields::new();
-        headers
-            .set(
-                &"User-Agent".to_string(),
-                vec!["tooltrain/0.1.0".as_bytes().to_vec()].as_slice(),
-            )
-            .unwrap();
-        headers
-            .set(
-                &"Accept".to_string(),
-                vec!["application/json".as_bytes().to_vec()].as_slice(),
-            )
-            .unwrap();
-        let request = OutgoingRequest::new(Fields::new());
-        request.set_authority(Some(mastodon_instance)).unwrap();
+        for (name, value) in feed.request_headers() {
+            headers
+                .set(&name, vec![value.into_bytes()].as_slice())
+                .map_err(|e| format!("Invalid {} header: {:?}", name, e))?;
+        }
+        let request = OutgoingRequest::new(headers);
+        request.set_authority(Some(&feed.instance)).unwrap();
         request.set_scheme(Some(&Scheme::Https)).unwrap();
-        let query_string = if let Some(id) = newest_id {
-            format!("?max_id={}", id)
-        } else {
-            "".to_string()
-        };
-        let path = format!("/api/v1/timelines/public{}", query_string);
-        request.set_path_with_query(Some(&path)).unwrap();
+        request.set_path_with_query(Some(path)).unwrap();
         let response_feed = http::outgoing_handler::handle(request, None)
             .map_err(|code| format!("Error constructing request: {:?}", code))?;
         response_feed.subscribe().block();
@@ -105,10 +182,38 @@ impl MastodonFeedProgram {
             .get()
             .unwrap()
             .unwrap()
-            .map_err(|e| format!("Error fetching public feed: {:?}", e))?;
+            .map_err(|e| format!("Error fetching feed: {:?}", e))?;
+        let headers = response.headers();
+        let read_header = |name: &str| {
+            let values = headers.get(&name.to_string());
+            (!values.is_empty()).then(|| {
+                values
+                    .iter()
+                    .map(|value| String::from_utf8_lossy(value).to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            })
+        };
+
+        match response.status() {
+            429 => {
+                return Ok(Attempt::RateLimited {
+                    reset_at: read_header("X-RateLimit-Reset"),
+                })
+            }
+            200..=299 => {}
+            status => return Err(format!("Mastodon instance responded with HTTP {}", status)),
+        }
+
+        let link_header = read_header("Link").unwrap_or_default();
         let incoming_body = response.consume().map_err(|_| "Empty body")?;
         let body = MastodonFeedProgram::read_incoming_body(incoming_body)?;
-        serde_json::from_str(&body).map_err(|p| format!("Error parsing JSON: {:?}", p))
+        let statuses =
+            serde_json::from_str(&body).map_err(|p| format!("Error parsing JSON: {:?}", p))?;
+        Ok(Attempt::Done(parse::Page {
+            statuses,
+            next_link: parse::next_page_path(&link_header, &feed.instance),
+        }))
     }
 
     fn read_incoming_body(body: IncomingBody) -> Result<String, String> {
@@ -130,4 +235,34 @@ impl MastodonFeedProgram {
     }
 }
 
-export!(MastodonFeedProgram);
+export_guest!(MastodonFeedProgram);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_public_timeline_without_token() {
+        let feed = Feed {
+            instance: "mastodon.social".to_string(),
+            access_token: None,
+        };
+        assert_eq!(feed.timeline_path(), PUBLIC_TIMELINE_PATH);
+        assert!(feed
+            .request_headers()
+            .iter()
+            .all(|(name, _)| name != "Authorization"));
+    }
+
+    #[test]
+    fn authenticates_with_token() {
+        let feed = Feed {
+            instance: "mastodon.social".to_string(),
+            access_token: Some("secret".to_string()),
+        };
+        assert_eq!(feed.timeline_path(), HOME_TIMELINE_PATH);
+        assert!(feed
+            .request_headers()
+            .contains(&("Authorization".to_string(), "Bearer secret".to_string())));
+    }
+}
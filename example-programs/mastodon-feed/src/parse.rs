@@ -1,94 +1,167 @@
-use crate::{
-    tooltrain::base::types::{Column, PrimitiveValue},
-    Primitive,
-};
-use lazy_static::lazy_static;
+use maplit::btreemap;
+use once_cell::sync::Lazy;
 use serde::Deserialize;
 
+use crate::html::html_to_text;
+use tooltrain_data::{
+    CommanderCoder, CommanderNumberDataType, CommanderStringDataType, CommanderStructDataType,
+    CommanderStructTypeBuilder,
+};
+
+/// The parts of the Mastodon API's accounts and statuses that the feed shows. Other fields in
+/// the responses are ignored.
 #[derive(Clone, Debug, Deserialize)]
 pub struct Account {
-    pub id: String,
-    username: String,
-    acct: String,
     display_name: String,
-    #[serde(default)]
-    locked: bool,
-    #[serde(default)]
-    bot: bool,
-    discoverable: bool,
-    created_at: String,
-    note: String,
-    url: String,
-    avatar: String,
-    avatar_static: String,
-    header: String,
-    header_static: String,
-    #[serde(default)]
-    followers_count: u32,
-    #[serde(default)]
-    following_count: u32,
-    #[serde(default)]
-    statuses_count: u32,
-    last_status_at: String,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Status {
     pub id: String,
     created_at: String,
-    url: String,
     replies_count: u32,
-    reblogs_count: u32,
     favourites_count: u32,
     content: String,
     account: Account,
     text: Option<String>,
 }
 
-lazy_static! {
-    pub static ref OUTPUT_TABLE_COLUMNS: Vec<Column> = vec![
-        Column {
-            name: "id".to_string(),
-            description: "The ID of the status".to_string(),
-            data_type: Primitive::StringType,
-        },
-        Column {
-            name: "content".to_string(),
-            description: "The content of the status".to_string(),
-            data_type: Primitive::StringType,
-        },
-        Column {
-            name: "created_at".to_string(),
-            description: "The time the status was created".to_string(),
-            data_type: Primitive::StringType,
-        },
-        Column {
-            name: "account".to_string(),
-            description: "The account that created the status".to_string(),
-            data_type: Primitive::StringType,
-        },
-        Column {
-            name: "likes_count".to_string(),
-            description: "The number of likes on the status".to_string(),
-            data_type: Primitive::NumberType,
-        },
-        Column {
-            name: "replies_count".to_string(),
-            description: "The number of replies to the status".to_string(),
-            data_type: Primitive::NumberType,
-        },
-    ];
-}
+pub static STATUS_STRUCT: Lazy<CommanderStructDataType> = Lazy::new(|| {
+    CommanderStructTypeBuilder::new("Status")
+        .add_field("id", CommanderStringDataType {})
+        .add_field("content", CommanderStringDataType {})
+        .add_field("created_at", CommanderStringDataType {})
+        .add_field("account", CommanderStringDataType {})
+        .add_field("likes_count", CommanderNumberDataType {})
+        .add_field("replies_count", CommanderNumberDataType {})
+        .build()
+});
 
 impl Status {
-    pub fn as_output_value(&self) -> Vec<PrimitiveValue> {
-        vec![
-            PrimitiveValue::StringValue(self.id.clone()),
-            PrimitiveValue::StringValue(self.text.clone().unwrap_or_else(|| self.content.clone())),
-            PrimitiveValue::StringValue(self.created_at.clone()),
-            PrimitiveValue::StringValue(self.account.display_name.clone()),
-            PrimitiveValue::NumberValue(self.favourites_count as f64),
-            PrimitiveValue::NumberValue(self.replies_count as f64),
-        ]
+    pub fn as_output_value(&self) -> <CommanderStructDataType as CommanderCoder>::Value {
+        let content = self
+            .text
+            .clone()
+            .unwrap_or_else(|| html_to_text(&self.content));
+        btreemap! {
+            "id".to_string() => self.id.clone().into(),
+            "content".to_string() => content.into(),
+            "created_at".to_string() => self.created_at.clone().into(),
+            "account".to_string() => self.account.display_name.clone().into(),
+            "likes_count".to_string() => (self.favourites_count as f64).into(),
+            "replies_count".to_string() => (self.replies_count as f64).into(),
+        }
+    }
+}
+
+/// A page of statuses along with the path of the following page, if the server provided one.
+pub struct Page {
+    pub statuses: Vec<Status>,
+    pub next_link: Option<String>,
+}
+
+impl Page {
+    /// The path and query of the page after this one. Prefers the server's `Link` header and
+    /// falls back to paging by the id of the last status on this page.
+    pub fn next_path(&self, timeline_path: &str) -> Option<String> {
+        self.next_link.clone().or_else(|| {
+            self.statuses
+                .last()
+                .map(|status| format!("{}?max_id={}", timeline_path, status.id))
+        })
+    }
+}
+
+/// Finds the `rel="next"` URL in a `Link` header and returns its path and query, as long as it
+/// points at the same instance the feed is being read from.
+pub fn next_page_path(link_header: &str, instance: &str) -> Option<String> {
+    link_header.split(',').find_map(|link| {
+        let (url, params) = link.trim().split_once(';')?;
+        let is_next = params
+            .split(';')
+            .any(|param| matches!(param.trim(), "rel=\"next\"" | "rel=next"));
+        if !is_next {
+            return None;
+        }
+
+        let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+        let (_, after_scheme) = url.split_once("://")?;
+        let path_start = after_scheme.find('/')?;
+        let (authority, path) = after_scheme.split_at(path_start);
+        (authority == instance).then(|| path.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_next_link() {
+        let header = "<https://mastodon.social/api/v1/timelines/public?max_id=7>; rel=\"next\", \
+                      <https://mastodon.social/api/v1/timelines/public?min_id=9>; rel=\"prev\"";
+        assert_eq!(
+            next_page_path(header, "mastodon.social"),
+            Some("/api/v1/timelines/public?max_id=7".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_missing_or_foreign_next_links() {
+        let prev_only = "<https://mastodon.social/api/v1/timelines/public?min_id=9>; rel=\"prev\"";
+        assert_eq!(next_page_path(prev_only, "mastodon.social"), None);
+        assert_eq!(next_page_path("", "mastodon.social"), None);
+
+        let foreign = "<https://evil.example/api/v1/timelines/public?max_id=7>; rel=\"next\"";
+        assert_eq!(next_page_path(foreign, "mastodon.social"), None);
+    }
+
+    fn status(id: &str) -> Status {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "created_at": "2024-01-01T00:00:00.000Z",
+            "url": format!("https://mastodon.social/@user/{}", id),
+            "replies_count": 0,
+            "reblogs_count": 0,
+            "favourites_count": 0,
+            "content": "<p>Hello</p>",
+            "account": {
+                "id": "1",
+                "username": "user",
+                "acct": "user",
+                "display_name": "User",
+                "discoverable": true,
+                "created_at": "2024-01-01T00:00:00.000Z",
+                "note": "",
+                "url": "https://mastodon.social/@user",
+                "avatar": "",
+                "avatar_static": "",
+                "header": "",
+                "header_static": "",
+                "last_status_at": "2024-01-01"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn prefers_link_header_then_falls_back_to_max_id() {
+        let mut page = Page {
+            statuses: vec![status("5"), status("3")],
+            next_link: Some("/api/v1/timelines/public?max_id=4".to_string()),
+        };
+        assert_eq!(
+            page.next_path("/api/v1/timelines/public"),
+            Some("/api/v1/timelines/public?max_id=4".to_string())
+        );
+
+        page.next_link = None;
+        assert_eq!(
+            page.next_path("/api/v1/timelines/public"),
+            Some("/api/v1/timelines/public?max_id=3".to_string())
+        );
+
+        page.statuses.clear();
+        assert_eq!(page.next_path("/api/v1/timelines/public"), None);
     }
 }
@@ -0,0 +1,169 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many times a rate-limited request is attempted before giving up.
+pub const MAX_ATTEMPTS: u32 = 4;
+/// The longest the feed will wait for a rate limit to reset before retrying.
+pub const MAX_WAIT: Duration = Duration::from_secs(60);
+/// How long to wait when the server does not say when its rate limit resets.
+const DEFAULT_WAIT: Duration = Duration::from_secs(5);
+
+/// The outcome of a single request attempt.
+pub enum Attempt<T> {
+    Done(T),
+    /// The server responded with `429 Too Many Requests`, along with its `X-RateLimit-Reset`
+    /// header if present.
+    RateLimited {
+        reset_at: Option<String>,
+    },
+}
+
+/// Runs `attempt` until it succeeds, sleeping until the rate limit resets whenever the server
+/// reports that it has been exceeded.
+pub fn with_rate_limit_retry<T>(
+    mut attempt: impl FnMut() -> Result<Attempt<T>, String>,
+    mut sleep: impl FnMut(Duration),
+    now: impl Fn() -> SystemTime,
+) -> Result<T, String> {
+    for attempt_number in 1..=MAX_ATTEMPTS {
+        match attempt()? {
+            Attempt::Done(value) => return Ok(value),
+            Attempt::RateLimited { reset_at } => {
+                if attempt_number < MAX_ATTEMPTS {
+                    sleep(wait_duration(reset_at.as_deref(), now()));
+                }
+            }
+        }
+    }
+    Err(format!(
+        "Still rate limited by the Mastodon instance after {} attempts",
+        MAX_ATTEMPTS
+    ))
+}
+
+fn wait_duration(reset_at: Option<&str>, now: SystemTime) -> Duration {
+    reset_at
+        .and_then(parse_timestamp)
+        .map(|reset| reset.duration_since(now).unwrap_or_default())
+        .unwrap_or(DEFAULT_WAIT)
+        .min(MAX_WAIT)
+}
+
+/// Parses the ISO 8601 timestamps Mastodon uses, e.g. `2024-01-01T12:30:00.000Z`. Timestamps
+/// with a UTC offset, e.g. `2024-01-01T14:30:00+02:00`, are converted to UTC; timestamps without
+/// a zone are rejected, as they could be in any time zone.
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.trim().split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (
+        date_parts.next()?.ok()?,
+        date_parts.next()?.ok()?,
+        date_parts.next()?.ok()?,
+    );
+
+    let (time, offset_seconds) = split_utc_offset(time)?;
+    let time = time.split('.').next()?;
+    let mut time_parts = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hours, minutes, seconds) = (
+        time_parts.next()?.ok()?,
+        time_parts.next()?.ok()?,
+        time_parts.next()?.ok()?,
+    );
+
+    let local_seconds =
+        days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds;
+    let seconds_since_epoch = local_seconds - offset_seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds_since_epoch).ok()?))
+}
+
+/// Splits the zone off the end of a time, returning the time and its offset east of UTC in
+/// seconds. The zone is either `Z` or an offset like `+02:00` or `-05:30`.
+fn split_utc_offset(time: &str) -> Option<(&str, i64)> {
+    if let Some(time) = time.strip_suffix('Z') {
+        return Some((time, 0));
+    }
+    let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+    let (sign, offset) = offset.split_at(1);
+    let sign = if sign == "-" { -1 } else { 1 };
+    let (hours, minutes) = offset.split_once(':')?;
+    let offset_seconds = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+    Some((time, sign * offset_seconds))
+}
+
+/// Days between 1970-01-01 and the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn parses_mastodon_timestamps() {
+        assert_eq!(
+            parse_timestamp("1970-01-01T00:00:00.000Z"),
+            Some(UNIX_EPOCH)
+        );
+        assert_eq!(
+            parse_timestamp("2024-03-01T12:30:15.123Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(1709296215))
+        );
+        assert_eq!(parse_timestamp("not a timestamp"), None);
+    }
+
+    #[test]
+    fn applies_utc_offsets() {
+        let utc = Some(UNIX_EPOCH + Duration::from_secs(1709296215));
+        assert_eq!(parse_timestamp("2024-03-01T14:30:15+02:00"), utc);
+        assert_eq!(parse_timestamp("2024-03-01T07:00:15.500-05:30"), utc);
+        assert_eq!(parse_timestamp("2024-03-01T12:30:15+00:00"), utc);
+        assert_eq!(parse_timestamp("2024-03-01T12:30:15"), None);
+        assert_eq!(parse_timestamp("2024-03-01T12:30:15+02"), None);
+    }
+
+    #[test]
+    fn retries_after_rate_limit_resets() {
+        let now = UNIX_EPOCH + Duration::from_secs(1709296200);
+        let mut responses = vec![
+            Attempt::Done("statuses"),
+            Attempt::RateLimited {
+                reset_at: Some("2024-03-01T12:30:15.000Z".to_string()),
+            },
+        ];
+        let sleeps = RefCell::new(vec![]);
+
+        let result = with_rate_limit_retry(
+            || Ok(responses.pop().unwrap()),
+            |duration| sleeps.borrow_mut().push(duration),
+            || now,
+        );
+        assert_eq!(result, Ok("statuses"));
+        assert_eq!(sleeps.into_inner(), vec![Duration::from_secs(15)]);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut attempts = 0;
+        let mut sleeps = vec![];
+        let result: Result<(), String> = with_rate_limit_retry(
+            || {
+                attempts += 1;
+                Ok(Attempt::RateLimited {
+                    reset_at: Some("2999-01-01T00:00:00.000Z".to_string()),
+                })
+            },
+            |duration| sleeps.push(duration),
+            SystemTime::now,
+        );
+        assert!(result.unwrap_err().contains("Still rate limited"));
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        assert_eq!(sleeps, vec![MAX_WAIT; MAX_ATTEMPTS as usize - 1]);
+    }
+}
@@ -3,7 +3,7 @@ use std::{path::PathBuf, str::FromStr};
 use anyhow::{anyhow, Error};
 use tooltrain_data::CommanderPathDataType;
 use tooltrain_engine::{
-    streaming::{OutputChange, OutputHandle, Outputs, TreeOutputHandle},
+    streaming::{OutputHandle, Outputs, TreeOutputHandle},
     CommanderEngine, CommanderStreamingProgramRun, ProgramSource,
 };
 
@@ -39,7 +39,9 @@ async fn main() -> Result<(), Error> {
 
     while let Some(path) = input_stream.next().await {
         println!("Opening {}", path);
-        tree_output.load(run.outputs()).request_children(path)?;
+        if let Err(error) = tree_output.load(run.outputs()).request_children(path) {
+            println!("Could not open directory: {}", error);
+        }
     }
 
     let result = run.get_result().await;
@@ -49,18 +51,13 @@ async fn main() -> Result<(), Error> {
 }
 
 async fn get_tree_output(outputs: &Outputs<'_>) -> Result<TreeOutputHandle, Error> {
-    let mut stream = outputs.updates();
-    while let Some(output_change) = stream.next().await {
-        println!("Received an output change: {:?}", output_change);
-        match output_change {
-            OutputChange::Added(handle) => match handle {
-                OutputHandle::Tree(t) => return Ok(t),
-                _ => println!("Unsupported output type: {:?}", handle.metadata().data_type),
-            },
-            OutputChange::Removed(_) => todo!(),
-        }
+    match outputs.wait_for_handle("Tree").await? {
+        OutputHandle::Tree(t) => Ok(t),
+        handle => Err(anyhow!(
+            "Unsupported output type: {:?}",
+            handle.metadata().data_type
+        )),
     }
-    Err(anyhow!("Tree output was never added"))
 }
 
 async fn listen_for_tree_changes(
@@ -0,0 +1,73 @@
+use std::path::Component;
+
+use crate::types::{CommanderDataType, CommanderValue};
+
+impl CommanderValue {
+    /// Renders the value for logs and debugging. Enums are shown by variant name, paths as
+    /// slash-joined strings, colors as hex and structs as `{field: value}` in the order
+    /// `data_type` declares their fields. Falls back to the value's own structure where it does
+    /// not match `data_type`.
+    pub fn display(&self, data_type: &CommanderDataType) -> String {
+        match self {
+            CommanderValue::Trigger(_) => "trigger".to_string(),
+            CommanderValue::Boolean(b) => b.to_string(),
+            CommanderValue::Number(n) => n.to_string(),
+            CommanderValue::String(s) => format!("{:?}", s),
+            CommanderValue::Bytes(bytes) => format!("<{} bytes>", bytes.len()),
+            CommanderValue::Color([r, g, b, a]) => {
+                format!("#{:04x}{:04x}{:04x}{:04x}", r, g, b, a)
+            }
+            CommanderValue::Json(json) => json.to_string(),
+            CommanderValue::Svg(svg) => svg.to_string(),
+            CommanderValue::Path(path) => path
+                .components()
+                .map(|component| match component {
+                    Component::RootDir => String::new(),
+                    component => component.as_os_str().to_string_lossy().to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join("/"),
+            CommanderValue::Enum(variant) => variant.get_name().to_string(),
+            CommanderValue::Struct(fields) => {
+                let struct_type = match data_type {
+                    CommanderDataType::Struct(struct_type) => Some(struct_type),
+                    _ => None,
+                };
+                // Declared fields first, then any the type does not know about.
+                let mut names: Vec<&String> = struct_type
+                    .map(|struct_type| {
+                        struct_type
+                            .field_names()
+                            .iter()
+                            .filter(|name| fields.contains_key(*name))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let undeclared: Vec<&String> =
+                    fields.keys().filter(|name| !names.contains(name)).collect();
+                names.extend(undeclared);
+                let rendered: Vec<String> = names
+                    .into_iter()
+                    .map(|name| {
+                        let field_type = struct_type
+                            .and_then(|struct_type| struct_type.get_field_type(name))
+                            .unwrap_or(data_type);
+                        format!("{}: {}", name, fields[name].display(field_type))
+                    })
+                    .collect();
+                format!("{{{}}}", rendered.join(", "))
+            }
+            CommanderValue::List(values) => {
+                let element_type = match data_type {
+                    CommanderDataType::List(list_type) => list_type.element_type(),
+                    _ => data_type.clone(),
+                };
+                let rendered: Vec<String> = values
+                    .iter()
+                    .map(|value| value.display(&element_type))
+                    .collect();
+                format!("[{}]", rendered.join(", "))
+            }
+        }
+    }
+}
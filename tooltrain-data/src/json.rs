@@ -0,0 +1,37 @@
+use serde_json::{Map, Number, Value};
+
+use crate::types::CommanderValue;
+
+impl CommanderValue {
+    /// Converts the value into plain JSON, e.g. for hosts that expose program state over HTTP.
+    /// Enums become their variant name, paths become strings and JSON values are embedded as-is.
+    pub fn to_json(&self) -> Value {
+        match self {
+            CommanderValue::Trigger(_) => Value::Null,
+            CommanderValue::Boolean(b) => Value::Bool(*b),
+            CommanderValue::Number(n) => Number::from_f64(*n).map_or(Value::Null, Value::Number),
+            CommanderValue::String(s) => Value::String(s.clone()),
+            CommanderValue::Bytes(bytes) => {
+                Value::Array(bytes.iter().map(|b| Value::from(*b)).collect())
+            }
+            CommanderValue::Color(channels) => {
+                Value::Array(channels.iter().map(|c| Value::from(*c)).collect())
+            }
+            CommanderValue::Json(json) => {
+                serde_json::from_str(json).unwrap_or_else(|_| Value::String(json.to_string()))
+            }
+            CommanderValue::Svg(svg) => Value::String(svg.to_string()),
+            CommanderValue::Path(path) => Value::String(path.to_string_lossy().to_string()),
+            CommanderValue::Enum(variant) => Value::String(variant.get_name().to_string()),
+            CommanderValue::Struct(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_json()))
+                    .collect::<Map<String, Value>>(),
+            ),
+            CommanderValue::List(values) => {
+                Value::Array(values.iter().map(CommanderValue::to_json).collect())
+            }
+        }
+    }
+}
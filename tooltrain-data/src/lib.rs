@@ -2,8 +2,11 @@ use anyhow::{anyhow, Error};
 use pest::{iterators::Pairs, Parser};
 use pest_derive::Parser;
 
+mod display;
 mod flexbuffer_coders;
+mod json;
 pub mod types;
+mod untyped;
 
 pub use flexbuffer_coders::CommanderCoder;
 pub use types::*;
@@ -17,6 +20,12 @@ pub fn parse(input: &str) -> Result<CommanderDataType, Error> {
     expand_type(pairs)
 }
 
+/// Parses `type_str` and decodes `bytes` with the resulting type. Intended as a fuzzing entry
+/// point: malformed type strings or buffers must produce an `Err`, never a panic.
+pub fn fuzz_decode(type_str: &str, bytes: &[u8]) -> Result<CommanderValue, Error> {
+    parse(type_str)?.decode(bytes)
+}
+
 fn expand_type(mut pairs: Pairs<'_, Rule>) -> Result<CommanderDataType, Error> {
     match pairs.peek().ok_or(anyhow!("No type found"))?.as_rule() {
         Rule::trigger => {
@@ -34,11 +43,11 @@ fn expand_static_type(mut pairs: Pairs<'_, Rule>) -> Result<CommanderDataType, E
         .as_rule()
     {
         Rule::list => Ok(expand_list_type(pairs.next().unwrap().into_inner())?.into()),
-        Rule::set => todo!(),
-        Rule::map => todo!(),
+        Rule::set => Err(anyhow!("set types are not supported yet")),
+        Rule::map => Err(anyhow!("map types are not supported yet")),
         Rule::r#enum => Ok(expand_enum_type(pairs.next().unwrap().into_inner())?.into()),
-        Rule::tuple => todo!(),
-        Rule::r#struct => todo!(),
+        Rule::tuple => Err(anyhow!("tuple types are not supported yet")),
+        Rule::r#struct => Ok(expand_struct_type(pairs.next().unwrap().into_inner())?.into()),
         _ => expand_primitive_type(pairs),
     }
 }
@@ -55,7 +64,7 @@ fn expand_primitive_type(mut pairs: Pairs<'_, Rule>) -> Result<CommanderDataType
         Rule::bytes => Ok(CommanderBytesDataType {}.into()),
         Rule::color => Ok(CommanderColorDataType {}.into()),
         Rule::path => Ok(CommanderPathDataType {}.into()),
-        Rule::url => todo!(),
+        Rule::url => Err(anyhow!("url types are not supported yet")),
         Rule::json => Ok(CommanderJsonDataType {}.into()),
         Rule::svg => Ok(CommanderSvgDataType {}.into()),
         _ => unreachable!(),
@@ -75,6 +84,45 @@ fn expand_enum_type(mut pairs: Pairs<'_, Rule>) -> Result<CommanderEnumDataType,
     Ok(CommanderEnumDataType::new(type_name, variants))
 }
 
+fn expand_struct_type(mut pairs: Pairs<'_, Rule>) -> Result<CommanderStructDataType, Error> {
+    let type_name_pair = pairs.next().unwrap();
+    assert_eq!(Rule::type_name, type_name_pair.as_rule());
+    let mut builder = CommanderStructTypeBuilder::new(type_name_pair.as_str());
+
+    while let Some(field_name_pair) = pairs.next() {
+        assert_eq!(Rule::type_arg_name, field_name_pair.as_rule());
+        // Every static type is a single pair, so the type is parsed from a copy of the
+        // iterator and then skipped over.
+        let field_type = expand_static_type(pairs.clone())?;
+        pairs.next();
+        let description = match pairs.peek().map(|pair| pair.as_rule()) {
+            Some(Rule::field_description) => Some(unescape_description(
+                pairs.next().unwrap().into_inner().as_str(),
+            )),
+            _ => None,
+        };
+        builder = builder.add_field_with_optional_description(
+            field_name_pair.as_str(),
+            description,
+            field_type,
+        );
+    }
+
+    Ok(builder.build())
+}
+
+fn unescape_description(escaped: &str) -> String {
+    let mut description = String::new();
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => description.extend(chars.next()),
+            c => description.push(c),
+        }
+    }
+    description
+}
+
 fn expand_list_type(pairs: Pairs<'_, Rule>) -> Result<CommanderListDataType, Error> {
     let child_type = expand_static_type(pairs)?;
     match child_type {
@@ -117,6 +165,7 @@ fn expand_list_type(pairs: Pairs<'_, Rule>) -> Result<CommanderListDataType, Err
 #[cfg(test)]
 mod tests {
     use crate::{flexbuffer_coders::CommanderCoder, parse, types::*};
+    use std::collections::BTreeMap;
 
     #[test]
     fn parses_enum() {
@@ -130,6 +179,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn round_trips_struct_field_descriptions() {
+        let file_type = CommanderStructTypeBuilder::new("File")
+            .add_field_with_description("name", "The \"file\" name", CommanderStringDataType {})
+            .add_field("size", CommanderNumberDataType {})
+            .add_field_with_description(
+                "tags",
+                "Labels, like a\\b",
+                CommanderListDataType::String(CommanderTypedListDataType::new(
+                    CommanderStringDataType {},
+                )),
+            )
+            .build();
+        let type_string = file_type.type_string();
+        assert_eq!(
+            type_string,
+            r#"struct File<name: string "The \"file\" name", size: number, tags: list<string> "Labels, like a\\b">"#
+        );
+
+        let parsed: CommanderStructDataType = parse(&type_string).unwrap().try_into().unwrap();
+        assert_eq!(parsed.type_string(), type_string);
+        assert_eq!(parsed.field_names(), ["name", "size", "tags"]);
+        assert_eq!(parsed.field_description("name"), Some("The \"file\" name"));
+        assert_eq!(parsed.field_description("size"), None);
+        assert_eq!(parsed.field_description("tags"), Some("Labels, like a\\b"));
+    }
+
+    #[test]
+    fn encodes_struct_fields_in_declaration_order() {
+        // The fields are not declared in alphabetical order, unlike the keys of a struct value.
+        let entry_type = CommanderStructTypeBuilder::new("Entry")
+            .add_field("size", CommanderNumberDataType {})
+            .add_field("name", CommanderStringDataType {})
+            .build();
+        let entry = BTreeMap::from([
+            (
+                "name".to_string(),
+                CommanderValue::String("a.txt".to_string()),
+            ),
+            ("size".to_string(), CommanderValue::Number(2.0)),
+        ]);
+        let encoded = entry_type.encode(entry.clone()).unwrap();
+        assert_eq!(entry_type.decode(&encoded).unwrap(), entry);
+
+        let list_type = CommanderListDataType::Struct(CommanderTypedListDataType::new(entry_type));
+        let rows = vec![CommanderValue::Struct(entry.clone())];
+        let encoded = list_type.encode(rows.clone()).unwrap();
+        assert_eq!(list_type.decode(&encoded).unwrap(), rows);
+    }
+
+    #[test]
+    fn rejects_struct_values_with_mismatched_fields() {
+        let entry_type = CommanderStructTypeBuilder::new("Entry")
+            .add_field("name", CommanderStringDataType {})
+            .add_field("size", CommanderNumberDataType {})
+            .build();
+        let missing = BTreeMap::from([(
+            "name".to_string(),
+            CommanderValue::String("a.txt".to_string()),
+        )]);
+        assert!(entry_type.encode(missing).is_err());
+        let renamed = BTreeMap::from([
+            (
+                "name".to_string(),
+                CommanderValue::String("a.txt".to_string()),
+            ),
+            ("bytes".to_string(), CommanderValue::Number(2.0)),
+        ]);
+        assert!(entry_type.encode(renamed).is_err());
+
+        let name_only = CommanderStructTypeBuilder::new("Entry")
+            .add_field("name", CommanderStringDataType {})
+            .build()
+            .encode(BTreeMap::from([(
+                "name".to_string(),
+                CommanderValue::String("a.txt".to_string()),
+            )]))
+            .unwrap();
+        assert!(entry_type.decode(&name_only).is_err());
+    }
+
+    #[test]
+    fn looks_up_struct_field_types() {
+        let file_type = CommanderStructTypeBuilder::new("File")
+            .add_field("name", CommanderStringDataType {})
+            .add_field("size", CommanderNumberDataType {})
+            .build();
+        assert_eq!(file_type.field_names(), ["name", "size"]);
+        assert_eq!(
+            file_type.get_field_type("size").map(|t| t.type_string()),
+            Some("number".to_string())
+        );
+        assert!(file_type.get_field_type("modified").is_none());
+    }
+
     #[test]
     fn parses_boolean_list() {
         let result = parse("list<boolean>").unwrap();
@@ -144,4 +288,420 @@ mod tests {
         let decoded = boolean_list_data_type.decode(&encoded).unwrap();
         assert_eq!(decoded, vec![true, false, true]);
     }
+
+    #[test]
+    fn exposes_list_child_types() {
+        let number_list: CommanderTypedListDataType<CommanderNumberDataType> =
+            CommanderListDataType::try_from(parse("list<number>").unwrap())
+                .unwrap()
+                .try_into()
+                .unwrap();
+        assert_eq!(number_list.child_type().type_string(), "number");
+
+        let nested_list: CommanderGenericListDataType =
+            *CommanderListDataType::try_from(parse("list<list<string>>").unwrap())
+                .unwrap()
+                .unwrap_generic();
+        assert_eq!(nested_list.child_type().type_string(), "list<string>");
+    }
+
+    #[test]
+    fn displays_nested_values() {
+        let file_type = CommanderEnumDataType::new(
+            "FileType".to_string(),
+            vec!["FILE".to_string(), "DIRECTORY".to_string()],
+        );
+        let file_struct = CommanderStructTypeBuilder::new("File")
+            .add_field("path", CommanderPathDataType {})
+            .add_field("type", file_type.clone())
+            .add_field("color", CommanderColorDataType {})
+            .add_field("sizes", parse("list<number>").unwrap())
+            .build();
+        let file = |path: &str, variant: &str| {
+            CommanderValue::Struct(BTreeMap::from([
+                ("path".to_string(), CommanderValue::Path(path.into())),
+                (
+                    "type".to_string(),
+                    file_type.get_variant(variant).unwrap().into(),
+                ),
+                (
+                    "color".to_string(),
+                    CommanderValue::Color([65535, 0, 255, 65535]),
+                ),
+                (
+                    "sizes".to_string(),
+                    CommanderValue::List(vec![CommanderValue::Number(1.5)]),
+                ),
+            ]))
+        };
+        let files =
+            CommanderValue::List(vec![file("/home/a.txt", "FILE"), file("src", "DIRECTORY")]);
+        let list_type: CommanderDataType =
+            CommanderListDataType::from(CommanderTypedListDataType::new(file_struct)).into();
+
+        assert_eq!(
+            files.display(&list_type),
+            "[{path: /home/a.txt, type: FILE, color: #ffff000000ffffff, sizes: [1.5]}, \
+             {path: src, type: DIRECTORY, color: #ffff000000ffffff, sizes: [1.5]}]"
+        );
+
+        // Without a struct type, fields are shown in name order.
+        let names = CommanderValue::List(vec![CommanderValue::Struct(BTreeMap::from([
+            ("b".to_string(), CommanderValue::String("x".to_string())),
+            ("a".to_string(), CommanderValue::Boolean(true)),
+        ]))]);
+        assert_eq!(
+            names.display(&parse("list<json>").unwrap()),
+            "[{a: true, b: \"x\"}]"
+        );
+    }
+
+    #[test]
+    fn converts_values_to_json() {
+        let file_type = CommanderEnumDataType::new(
+            "FileType".to_string(),
+            vec!["File".to_string(), "Directory".to_string()],
+        );
+        let value = CommanderValue::Struct(
+            [
+                (
+                    "path".to_string(),
+                    CommanderValue::Path(std::path::PathBuf::from("/tmp/a.txt")),
+                ),
+                (
+                    "type".to_string(),
+                    CommanderValue::Enum(file_type.get_variant("File").unwrap()),
+                ),
+                (
+                    "sizes".to_string(),
+                    CommanderValue::List(vec![
+                        CommanderValue::Number(1.5),
+                        CommanderValue::Number(f64::NAN),
+                    ]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            value.to_json().to_string(),
+            r#"{"path":"/tmp/a.txt","sizes":[1.5,null],"type":"File"}"#
+        );
+        assert_eq!(
+            CommanderValue::Color([1, 2, 3, 4]).to_json().to_string(),
+            "[1,2,3,4]"
+        );
+    }
+
+    #[test]
+    fn infers_types_of_primitive_values() {
+        let values = [
+            CommanderValue::Boolean(true),
+            CommanderValue::Number(1.5),
+            CommanderValue::String("hello".to_string()),
+            CommanderValue::Bytes(vec![1, 2, 3]),
+            CommanderValue::Color([1, 2, 3, 4]),
+            CommanderValue::Path(std::path::PathBuf::from("/tmp")),
+        ];
+        for value in values {
+            let data_type = value.type_of().unwrap();
+            let encoded = data_type.encode(value.clone()).unwrap();
+            assert_eq!(data_type.decode(&encoded).unwrap(), value);
+        }
+        assert_eq!(
+            CommanderValue::Number(1.0).type_of().unwrap().type_string(),
+            "number"
+        );
+
+        let file_type =
+            CommanderEnumDataType::new("FileType".to_string(), vec!["File".to_string()]);
+        assert!(CommanderValue::Enum(file_type.get_variant("File").unwrap())
+            .type_of()
+            .is_none());
+        assert!(CommanderValue::List(vec![]).type_of().is_none());
+    }
+
+    #[test]
+    fn compares_struct_values_by_field() {
+        let file = |name: &str, size: f64| {
+            CommanderValue::Struct(
+                [
+                    ("name".to_string(), CommanderValue::String(name.to_string())),
+                    ("size".to_string(), CommanderValue::Number(size)),
+                ]
+                .into(),
+            )
+        };
+        let mut files = vec![
+            file("b.txt", 30.0),
+            file("c.txt", 10.0),
+            file("a.txt", 20.0),
+        ];
+
+        files.sort_by(|a, b| CommanderValue::compare_by_field(a, b, "size").unwrap());
+        assert_eq!(
+            files,
+            vec![
+                file("c.txt", 10.0),
+                file("a.txt", 20.0),
+                file("b.txt", 30.0)
+            ]
+        );
+        files.sort_by(|a, b| CommanderValue::compare_by_field(a, b, "name").unwrap());
+        assert_eq!(
+            files,
+            vec![
+                file("a.txt", 20.0),
+                file("b.txt", 30.0),
+                file("c.txt", 10.0)
+            ]
+        );
+
+        assert!(CommanderValue::compare_by_field(&files[0], &files[1], "modified").is_err());
+        assert!(
+            CommanderValue::compare_by_field(&CommanderValue::Number(1.0), &files[0], "size")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn orders_values_totally() {
+        use std::cmp::Ordering;
+
+        let nan = CommanderValue::Number(f64::NAN);
+        assert_eq!(nan.total_cmp(&nan), Ordering::Equal);
+        assert_eq!(
+            CommanderValue::Number(1.0).total_cmp(&CommanderValue::String("1".to_string())),
+            Ordering::Less
+        );
+        assert_eq!(
+            CommanderValue::List(vec![CommanderValue::Number(1.0)])
+                .total_cmp(&CommanderValue::List(vec![])),
+            Ordering::Greater
+        );
+        let size = CommanderEnumDataType::new(
+            "Size".to_string(),
+            vec!["SMALL".to_string(), "LARGE".to_string()],
+        );
+        assert_eq!(
+            CommanderValue::Enum(size.get_variant("SMALL").unwrap())
+                .total_cmp(&CommanderValue::Enum(size.get_variant("LARGE").unwrap())),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn round_trips_nested_generic_lists() {
+        let list_type: CommanderListDataType =
+            parse("list<list<number>>").unwrap().try_into().unwrap();
+        assert!(list_type.is_generic());
+        assert_eq!(list_type.type_string(), "list<list<number>>");
+
+        let value = vec![
+            CommanderValue::List(vec![
+                CommanderValue::Number(1.0),
+                CommanderValue::Number(2.0),
+            ]),
+            CommanderValue::List(vec![]),
+            CommanderValue::List(vec![CommanderValue::Number(3.0)]),
+        ];
+        let encoded = list_type.encode(value.clone()).unwrap();
+        assert_eq!(list_type.decode(&encoded).unwrap(), value);
+
+        let deeper_type = parse("list<list<list<string>>>").unwrap();
+        let innermost = CommanderValue::List(vec![CommanderValue::String("a".to_string())]);
+        let deeper_value = CommanderValue::List(vec![CommanderValue::List(vec![innermost])]);
+        let encoded = deeper_type.encode(deeper_value.clone()).unwrap();
+        assert_eq!(deeper_type.decode(&encoded).unwrap(), deeper_value);
+    }
+
+    #[test]
+    fn round_trips_colors() {
+        let colors = [
+            [0, 0, 0, 0],
+            [65535, 65535, 65535, 65535],
+            [0, 65535, 0, 65535],
+            [1, 256, 4096, 65534],
+        ];
+
+        let color_type = CommanderColorDataType {};
+        for color in colors {
+            let encoded = color_type.encode(color).unwrap();
+            assert_eq!(color_type.decode(&encoded).unwrap(), color);
+        }
+
+        let data_type = parse("color").unwrap();
+        for color in colors {
+            let encoded = data_type.encode(CommanderValue::Color(color)).unwrap();
+            assert_eq!(
+                data_type.decode(&encoded).unwrap(),
+                CommanderValue::Color(color)
+            );
+        }
+
+        let list_type = parse("list<color>").unwrap();
+        let list = CommanderValue::List(colors.into_iter().map(CommanderValue::Color).collect());
+        let encoded = list_type.encode(list.clone()).unwrap();
+        assert_eq!(list_type.decode(&encoded).unwrap(), list);
+
+        // Colors are encoded as plain vectors, so a plugin sending too few channels is rejected.
+        let three_channels = flexbuffers::to_vec(vec![1u16, 2, 3]).unwrap();
+        assert!(color_type.decode(&three_channels).is_err());
+    }
+
+    #[test]
+    fn rejects_short_fixed_shape_buffers() {
+        let color_type = CommanderColorDataType {};
+        for channels in [vec![], vec![1u16], vec![1, 2, 3], vec![1, 2, 3, 4, 5]] {
+            let encoded = flexbuffers::to_vec(&channels).unwrap();
+            let error = color_type.decode(&encoded).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Expected a color with 4 channels, got {}", channels.len())
+            );
+        }
+
+        // Colors written before the wire format was made explicit still decode.
+        let legacy = flexbuffers::to_vec([1u16, 2, 3, 4]).unwrap();
+        assert_eq!(color_type.decode(&legacy).unwrap(), [1, 2, 3, 4]);
+
+        let encoded = color_type.encode([0, 65535, 0, 65535]).unwrap();
+        for length in 0..encoded.len() {
+            assert!(color_type.decode(&encoded[..length]).is_err());
+        }
+
+        let point_type = parse("struct Point<x: number, y: number>").unwrap();
+        let one_field = flexbuffers::to_vec(vec![1.0]).unwrap();
+        assert!(point_type.decode(&one_field).is_err());
+    }
+
+    #[test]
+    fn rejects_mixed_list_rows() {
+        let nested_type = parse("list<list<number>>").unwrap();
+        let mixed = CommanderValue::List(vec![
+            CommanderValue::List(vec![CommanderValue::Number(1.0)]),
+            CommanderValue::List(vec![CommanderValue::String("two".to_string())]),
+        ]);
+        let error = nested_type.encode(mixed).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Expected a list of number values"));
+
+        let flat_type = parse("list<number>").unwrap();
+        assert!(flat_type
+            .encode(CommanderValue::List(vec![CommanderValue::Boolean(true)]))
+            .is_err());
+    }
+
+    #[test]
+    fn decodes_untyped_flexbuffers() {
+        let decode = |bytes: Vec<u8>| CommanderValue::from_flexbuffer_untyped(&bytes).unwrap();
+        assert_eq!(
+            decode(flexbuffers::to_vec(4.5).unwrap()),
+            CommanderValue::Number(4.5)
+        );
+        assert_eq!(
+            decode(flexbuffers::to_vec(-3i32).unwrap()),
+            CommanderValue::Number(-3.0)
+        );
+        assert_eq!(
+            decode(flexbuffers::to_vec("hello").unwrap()),
+            CommanderValue::String("hello".to_string())
+        );
+        assert_eq!(
+            decode(flexbuffers::to_vec(true).unwrap()),
+            CommanderValue::Boolean(true)
+        );
+        assert_eq!(
+            decode(flexbuffers::to_vec(BTreeMap::from([("count", 2u8)])).unwrap()),
+            CommanderValue::Struct(BTreeMap::from([(
+                "count".to_string(),
+                CommanderValue::Number(2.0)
+            )]))
+        );
+
+        // Typed encodings decode to their closest untyped shape.
+        let struct_type = CommanderStructTypeBuilder::new("Entry")
+            .add_field("name", CommanderStringDataType {})
+            .add_field("sizes", parse("list<number>").unwrap())
+            .build();
+        let encoded = struct_type
+            .encode(BTreeMap::from([
+                ("name".to_string(), CommanderValue::String("a".to_string())),
+                (
+                    "sizes".to_string(),
+                    CommanderValue::List(vec![CommanderValue::Number(1.0)]),
+                ),
+            ]))
+            .unwrap();
+        assert_eq!(
+            decode(encoded),
+            CommanderValue::List(vec![
+                CommanderValue::String("a".to_string()),
+                CommanderValue::List(vec![CommanderValue::Number(1.0)]),
+            ])
+        );
+        let variant = CommanderEnumDataType::new(
+            "Number".to_string(),
+            vec!["ONE".to_string(), "TWO".to_string()],
+        )
+        .get_variant("TWO")
+        .unwrap();
+        let encoded = parse("enum Number<ONE, TWO>")
+            .unwrap()
+            .encode(variant.into())
+            .unwrap();
+        assert_eq!(decode(encoded), CommanderValue::Number(1.0));
+
+        assert!(CommanderValue::from_flexbuffer_untyped(&[]).is_err());
+    }
+
+    #[test]
+    fn fuzz_decode_never_panics() {
+        let type_strings = [
+            "boolean",
+            "number",
+            "string",
+            "bytes",
+            "color",
+            "path",
+            "json",
+            "svg",
+            "trigger",
+            "url",
+            "enum Number<ONE, TWO>",
+            "list<number>",
+            "list<enum Number<ONE, TWO>>",
+            "list<list<string>>",
+            "not a type",
+        ];
+
+        // Simple xorshift so the test is deterministic without pulling in a rand dependency.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next_byte = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        };
+
+        for type_string in type_strings {
+            assert!(crate::fuzz_decode(type_string, &[]).is_err());
+            for length in 1..64 {
+                let bytes: Vec<u8> = (0..length).map(|_| next_byte()).collect();
+                let _ = crate::fuzz_decode(type_string, &bytes);
+                let _ = CommanderValue::from_flexbuffer_untyped(&bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_decode_accepts_valid_buffers() {
+        let encoded = CommanderNumberDataType {}.encode(4.5).unwrap();
+        assert_eq!(
+            crate::fuzz_decode("number", &encoded).unwrap(),
+            CommanderValue::Number(4.5)
+        );
+        assert!(crate::fuzz_decode("url", &encoded).is_err());
+    }
 }
@@ -3,7 +3,7 @@ use anyhow::{anyhow, Error};
 use derive_more::{Deref, From, IsVariant, TryInto, Unwrap};
 use flexbuffers::{FlexbufferSerializer, Reader};
 use serde::{ser::SerializeSeq, Deserialize, Serialize, Serializer};
-use std::{collections::BTreeMap, marker::PhantomData, path::PathBuf};
+use std::{cmp::Ordering, collections::BTreeMap, marker::PhantomData, path::PathBuf};
 
 #[derive(Clone, Copy, Default, Debug)]
 pub struct CommanderTriggerDataType {}
@@ -58,10 +58,22 @@ impl CommanderPrimitiveCoder for CommanderBytesDataType {
 #[derive(Clone, Copy, Default, Debug)]
 pub struct CommanderColorDataType {}
 
-impl CommanderPrimitiveCoder for CommanderColorDataType {
+impl CommanderWireFormatCoder for CommanderColorDataType {
     type Value = [u16; 4];
-    fn type_string__(&self) -> &'static str {
-        "color"
+    type WireFormat = Vec<u16>;
+
+    fn type_string_(&self) -> String {
+        "color".to_string()
+    }
+
+    fn encode_to_wire_format(&self, value: Self::Value) -> Result<Self::WireFormat, Error> {
+        Ok(value.to_vec())
+    }
+
+    fn decode_from_wire_format(&self, wire_format: Self::WireFormat) -> Result<Self::Value, Error> {
+        wire_format.try_into().map_err(|channels: Vec<u16>| {
+            anyhow!("Expected a color with 4 channels, got {}", channels.len())
+        })
     }
 }
 
@@ -217,6 +229,111 @@ pub enum CommanderValue {
     List(<CommanderListDataType as CommanderCoder>::Value),
 }
 
+impl CommanderValue {
+    /// Returns the data type that can encode this value. Enums, structs and lists return `None`
+    /// because their type also depends on schema information the value does not carry, such as
+    /// the enum's name and variants or the list's element type.
+    pub fn type_of(&self) -> Option<CommanderDataType> {
+        match self {
+            CommanderValue::Trigger(_) => Some(CommanderTriggerDataType {}.into()),
+            CommanderValue::Boolean(_) => Some(CommanderBooleanDataType {}.into()),
+            CommanderValue::Number(_) => Some(CommanderNumberDataType {}.into()),
+            CommanderValue::String(_) => Some(CommanderStringDataType {}.into()),
+            CommanderValue::Bytes(_) => Some(CommanderBytesDataType {}.into()),
+            CommanderValue::Color(_) => Some(CommanderColorDataType {}.into()),
+            CommanderValue::Json(_) => Some(CommanderJsonDataType {}.into()),
+            CommanderValue::Svg(_) => Some(CommanderSvgDataType {}.into()),
+            CommanderValue::Path(_) => Some(CommanderPathDataType {}.into()),
+            CommanderValue::Enum(_) | CommanderValue::Struct(_) | CommanderValue::List(_) => None,
+        }
+    }
+
+    /// A total order over values, for sorting. Numbers are ordered by `f64::total_cmp`, enum
+    /// variants in the order they were declared, and structs and lists element by element. Values
+    /// of different kinds are ordered by kind rather than being incomparable.
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (CommanderValue::Trigger(_), CommanderValue::Trigger(_)) => Ordering::Equal,
+            (CommanderValue::Boolean(a), CommanderValue::Boolean(b)) => a.cmp(b),
+            (CommanderValue::Number(a), CommanderValue::Number(b)) => a.total_cmp(b),
+            (CommanderValue::String(a), CommanderValue::String(b)) => a.cmp(b),
+            (CommanderValue::Bytes(a), CommanderValue::Bytes(b)) => a.cmp(b),
+            (CommanderValue::Color(a), CommanderValue::Color(b)) => a.cmp(b),
+            (CommanderValue::Json(a), CommanderValue::Json(b)) => a.cmp(b),
+            (CommanderValue::Svg(a), CommanderValue::Svg(b)) => a.cmp(b),
+            (CommanderValue::Path(a), CommanderValue::Path(b)) => a.cmp(b),
+            (CommanderValue::Enum(a), CommanderValue::Enum(b)) => a.ordinal.cmp(&b.ordinal),
+            (CommanderValue::Struct(a), CommanderValue::Struct(b)) => {
+                lexicographic_cmp(a, b, |(a_name, a_value), (b_name, b_value)| {
+                    a_name.cmp(b_name).then_with(|| a_value.total_cmp(b_value))
+                })
+            }
+            (CommanderValue::List(a), CommanderValue::List(b)) => {
+                lexicographic_cmp(a, b, CommanderValue::total_cmp)
+            }
+            _ => self.kind_index().cmp(&other.kind_index()),
+        }
+    }
+
+    /// Compares two struct values by the field named `field_name`, e.g. to sort the rows of a
+    /// list of structs by one column. Fails if either value is not a struct with that field.
+    pub fn compare_by_field(a: &Self, b: &Self, field_name: &str) -> Result<Ordering, Error> {
+        fn field<'a>(
+            value: &'a CommanderValue,
+            field_name: &str,
+        ) -> Result<&'a CommanderValue, Error> {
+            match value {
+                CommanderValue::Struct(fields) => fields
+                    .get(field_name)
+                    .ok_or_else(|| anyhow!("Struct has no field named {}", field_name)),
+                _ => Err(anyhow!(
+                    "Expected a struct value to compare by {}",
+                    field_name
+                )),
+            }
+        }
+        Ok(field(a, field_name)?.total_cmp(field(b, field_name)?))
+    }
+
+    fn kind_index(&self) -> u8 {
+        match self {
+            CommanderValue::Trigger(_) => 0,
+            CommanderValue::Boolean(_) => 1,
+            CommanderValue::Number(_) => 2,
+            CommanderValue::String(_) => 3,
+            CommanderValue::Bytes(_) => 4,
+            CommanderValue::Color(_) => 5,
+            CommanderValue::Json(_) => 6,
+            CommanderValue::Svg(_) => 7,
+            CommanderValue::Path(_) => 8,
+            CommanderValue::Enum(_) => 9,
+            CommanderValue::Struct(_) => 10,
+            CommanderValue::List(_) => 11,
+        }
+    }
+}
+
+/// Compares two sequences element by element, with a shorter sequence ordered before any longer
+/// sequence it is a prefix of.
+fn lexicographic_cmp<T>(
+    a: impl IntoIterator<Item = T>,
+    b: impl IntoIterator<Item = T>,
+    cmp: impl Fn(T, T) -> Ordering,
+) -> Ordering {
+    let (mut a, mut b) = (a.into_iter(), b.into_iter());
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match cmp(a, b) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+        }
+    }
+}
+
 impl CommanderCoder for CommanderDataType {
     type Value = CommanderValue;
 
@@ -365,9 +482,27 @@ pub struct CommanderStructDataType {
     pub name: String,
     field_names: Vec<String>,
     field_types: Vec<CommanderDataType>,
+    field_descriptions: Vec<Option<String>>,
 }
 
 impl CommanderStructDataType {
+    pub fn field_names(&self) -> &[String] {
+        &self.field_names
+    }
+
+    /// Returns the data type of the field called `name`, e.g. to decode just that field of a
+    /// struct value.
+    pub fn get_field_type(&self, name: &str) -> Option<&CommanderDataType> {
+        let index = self.field_names.iter().position(|field| field == name)?;
+        self.field_types.get(index)
+    }
+
+    /// Returns the description of the field called `name`, if it has one.
+    pub fn field_description(&self, name: &str) -> Option<&str> {
+        let index = self.field_names.iter().position(|field| field == name)?;
+        self.field_descriptions[index].as_deref()
+    }
+
     pub fn column_types(&self) -> Vec<String> {
         self.field_types.iter().map(|t| t.type_string()).collect()
     }
@@ -378,6 +513,7 @@ pub struct CommanderStructTypeBuilder {
     pub name: String,
     field_names: Vec<String>,
     field_types: Vec<CommanderDataType>,
+    field_descriptions: Vec<Option<String>>,
 }
 
 impl CommanderStructTypeBuilder {
@@ -386,17 +522,43 @@ impl CommanderStructTypeBuilder {
             name: name.to_string(),
             field_names: vec![],
             field_types: vec![],
+            field_descriptions: vec![],
         }
     }
 
-    pub fn add_field<D>(mut self, name: &str, data_type: D) -> Self
+    pub fn add_field<D>(self, name: &str, data_type: D) -> Self
     where
         D: 'static,
         D: CommanderCoder,
         D: Into<CommanderDataType>,
     {
+        self.add_field_with_optional_description(name, None, data_type.into())
+    }
+
+    /// Like `add_field`, but the field carries a description that is included in the type
+    /// string, so that a UI can explain what each column of a list holds.
+    pub fn add_field_with_description<D>(self, name: &str, description: &str, data_type: D) -> Self
+    where
+        D: 'static,
+        D: CommanderCoder,
+        D: Into<CommanderDataType>,
+    {
+        self.add_field_with_optional_description(
+            name,
+            Some(description.to_string()),
+            data_type.into(),
+        )
+    }
+
+    pub(crate) fn add_field_with_optional_description(
+        mut self,
+        name: &str,
+        description: Option<String>,
+        data_type: CommanderDataType,
+    ) -> Self {
         self.field_names.push(name.to_string());
-        self.field_types.push(data_type.into());
+        self.field_types.push(data_type);
+        self.field_descriptions.push(description);
         self
     }
 
@@ -405,6 +567,7 @@ impl CommanderStructTypeBuilder {
             name: self.name,
             field_names: self.field_names,
             field_types: self.field_types,
+            field_descriptions: self.field_descriptions,
         }
     }
 }
@@ -417,7 +580,16 @@ impl CommanderCoder for CommanderStructDataType {
             .field_names
             .iter()
             .zip(self.field_types.iter())
-            .map(|(name, type_box)| format!("{}: {}", name, type_box.type_string()))
+            .zip(self.field_descriptions.iter())
+            .map(|((name, type_box), description)| match description {
+                Some(description) => format!(
+                    "{}: {} \"{}\"",
+                    name,
+                    type_box.type_string(),
+                    description.replace('\\', "\\\\").replace('"', "\\\"")
+                ),
+                None => format!("{}: {}", name, type_box.type_string()),
+            })
             .collect::<Vec<String>>()
             .join(", ");
         format!("struct {}<{}>", self.name, type_args)
@@ -426,12 +598,24 @@ impl CommanderCoder for CommanderStructDataType {
     fn encode_to_serializer(
         &self,
         serializer: &mut FlexbufferSerializer,
-        value: Self::Value,
+        mut value: Self::Value,
     ) -> Result<(), Error> {
+        if value.len() != self.field_names.len() {
+            return Err(anyhow!(
+                "Struct {} has {} fields, but the value has {}",
+                self.name,
+                self.field_names.len(),
+                value.len()
+            ));
+        }
         let seq_serializer = serializer.serialize_seq(Some(self.field_names.len()))?;
 
-        for ((_, value), type_box) in value.into_iter().zip(self.field_types.iter()) {
-            type_box.encode_to_serializer(seq_serializer, value)?;
+        // Fields are encoded in the order they were declared, not the value's (sorted) order.
+        for (name, type_box) in self.field_names.iter().zip(self.field_types.iter()) {
+            let field_value = value
+                .remove(name)
+                .ok_or_else(|| anyhow!("Struct {} value is missing field {}", self.name, name))?;
+            type_box.encode_to_serializer(seq_serializer, field_value)?;
         }
 
         seq_serializer.end()?;
@@ -440,6 +624,14 @@ impl CommanderCoder for CommanderStructDataType {
 
     fn decode_from_reader(&self, reader: Reader<&[u8]>) -> Result<Self::Value, Error> {
         let vector_reader = reader.get_vector()?;
+        if vector_reader.len() != self.field_types.len() {
+            return Err(anyhow!(
+                "Struct {} has {} fields, but the encoded value has {}",
+                self.name,
+                self.field_types.len(),
+                vector_reader.len()
+            ));
+        }
         let mut values: Vec<CommanderValue> = vec![];
         for (reader, type_box) in vector_reader.iter().zip(self.field_types.iter()) {
             values.push(type_box.decode_from_reader(reader)?);
@@ -457,6 +649,11 @@ impl<V: CommanderCoder + 'static> CommanderTypedListDataType<V> {
     pub fn new(child_type: V) -> Self {
         CommanderTypedListDataType::<V> { child_type }
     }
+
+    /// The data type of the list's elements.
+    pub fn child_type(&self) -> &V {
+        &self.child_type
+    }
 }
 
 impl<V: CommanderCoder + 'static> CommanderCoder for CommanderTypedListDataType<V> {
@@ -493,7 +690,7 @@ impl<V: CommanderCoder + 'static> CommanderCoder for CommanderTypedListDataType<
 
 pub type CommanderGenericListDataType = CommanderTypedListDataType<CommanderDataType>;
 
-#[derive(Clone, Debug, TryInto, IsVariant, Unwrap)]
+#[derive(Clone, Debug, From, TryInto, IsVariant, Unwrap)]
 pub enum CommanderListDataType {
     Boolean(CommanderTypedListDataType<CommanderBooleanDataType>),
     Number(CommanderTypedListDataType<CommanderNumberDataType>),
@@ -523,6 +720,40 @@ pub enum CommanderListValue {
     Generic(Vec<Box<CommanderValue>>),
 }
 
+/// Converts the rows of a list into the value type of its elements, failing instead of
+/// panicking if any row has a different type.
+fn typed_rows<T>(rows: Vec<CommanderValue>, expected: &str) -> Result<Vec<T>, Error>
+where
+    CommanderValue: TryInto<T>,
+    <CommanderValue as TryInto<T>>::Error: std::fmt::Display,
+{
+    rows.into_iter()
+        .map(|row| {
+            row.try_into()
+                .map_err(|s| anyhow!("Expected a list of {expected} values. {s}"))
+        })
+        .collect()
+}
+
+impl CommanderListDataType {
+    /// The data type of the list's elements.
+    pub fn element_type(&self) -> CommanderDataType {
+        match self {
+            CommanderListDataType::Boolean(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Number(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::String(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Bytes(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Color(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Json(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Svg(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Path(inner) => (*inner.child_type()).into(),
+            CommanderListDataType::Enum(inner) => inner.child_type().clone().into(),
+            CommanderListDataType::Struct(inner) => inner.child_type().clone().into(),
+            CommanderListDataType::Generic(inner) => inner.child_type().clone(),
+        }
+    }
+}
+
 impl CommanderCoder for CommanderListDataType {
     type Value = Vec<CommanderValue>;
 
@@ -548,46 +779,36 @@ impl CommanderCoder for CommanderListDataType {
         value: Self::Value,
     ) -> Result<(), Error> {
         match self {
-            CommanderListDataType::Boolean(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Number(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::String(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Bytes(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Color(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Json(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Svg(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Path(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Enum(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
-            CommanderListDataType::Struct(inner) => inner.encode_to_serializer(
-                serializer,
-                value.into_iter().map(|v| v.try_into().unwrap()).collect(),
-            ),
+            CommanderListDataType::Boolean(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "boolean")?)
+            }
+            CommanderListDataType::Number(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "number")?)
+            }
+            CommanderListDataType::String(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "string")?)
+            }
+            CommanderListDataType::Bytes(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "bytes")?)
+            }
+            CommanderListDataType::Color(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "color")?)
+            }
+            CommanderListDataType::Json(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "json")?)
+            }
+            CommanderListDataType::Svg(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "svg")?)
+            }
+            CommanderListDataType::Path(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "path")?)
+            }
+            CommanderListDataType::Enum(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "enum")?)
+            }
+            CommanderListDataType::Struct(inner) => {
+                inner.encode_to_serializer(serializer, typed_rows(value, "struct")?)
+            }
             CommanderListDataType::Generic(inner) => inner.encode_to_serializer(serializer, value),
         }
     }
@@ -0,0 +1,60 @@
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, Error};
+use flexbuffers::{FlexBufferType, Reader};
+
+use crate::types::CommanderValue;
+
+// How deeply vectors and maps may nest. Flexbuffer offsets can point back at their own vector,
+// so without a limit a malformed buffer could recurse forever.
+const MAX_DEPTH: usize = 64;
+
+impl CommanderValue {
+    /// Decodes a flexbuffer without knowing its data type, e.g. to inspect what a program sent
+    /// when debugging. This is best effort: numbers become `Number`, strings `String`, maps
+    /// `Struct` and vectors generic lists. Values whose type only exists in the schema can't be
+    /// told apart, so enums decode as the `Number` of their ordinal, paths as lists of strings,
+    /// colors and bytes as lists of numbers, and structs (which are encoded as vectors) as lists.
+    pub fn from_flexbuffer_untyped(bytes: &[u8]) -> Result<CommanderValue, Error> {
+        decode_untyped(Reader::get_root(bytes)?, 0)
+    }
+}
+
+fn decode_untyped(reader: Reader<&[u8]>, depth: usize) -> Result<CommanderValue, Error> {
+    let flexbuffer_type = reader.flexbuffer_type();
+    match flexbuffer_type {
+        FlexBufferType::Null => Ok(CommanderValue::Trigger(PhantomData)),
+        FlexBufferType::Bool => Ok(CommanderValue::Boolean(reader.get_bool()?)),
+        FlexBufferType::Int | FlexBufferType::IndirectInt => {
+            Ok(CommanderValue::Number(reader.get_i64()? as f64))
+        }
+        FlexBufferType::UInt | FlexBufferType::IndirectUInt => {
+            Ok(CommanderValue::Number(reader.get_u64()? as f64))
+        }
+        FlexBufferType::Float | FlexBufferType::IndirectFloat => {
+            Ok(CommanderValue::Number(reader.get_f64()?))
+        }
+        FlexBufferType::String => Ok(CommanderValue::String(reader.get_str()?.to_string())),
+        FlexBufferType::Key => Ok(CommanderValue::String(reader.get_key()?.to_string())),
+        FlexBufferType::Blob => Ok(CommanderValue::Bytes(reader.get_blob()?.0.to_vec())),
+        _ if depth >= MAX_DEPTH => Err(anyhow!(
+            "Values nested more than {} levels deep cannot be decoded",
+            MAX_DEPTH
+        )),
+        FlexBufferType::Map => {
+            let map = reader.get_map()?;
+            map.iter_keys()
+                .zip(map.iter_values())
+                .map(|(key, value)| Ok((key.to_string(), decode_untyped(value, depth + 1)?)))
+                .collect::<Result<_, Error>>()
+                .map(CommanderValue::Struct)
+        }
+        _ if flexbuffer_type.is_vector() => reader
+            .get_vector()?
+            .iter()
+            .map(|element| decode_untyped(element, depth + 1))
+            .collect::<Result<_, Error>>()
+            .map(CommanderValue::List),
+        _ => Err(anyhow!("Unsupported flexbuffer type {:?}", flexbuffer_type)),
+    }
+}
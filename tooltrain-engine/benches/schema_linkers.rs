@@ -0,0 +1,43 @@
+//! Compares reading programs' schemas through the minimal schema linker with reading them through
+//! the full linker. Run with `cargo bench -p tooltrain-engine --features schema-bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tooltrain_engine::{mock_program_sources, CommanderEngine};
+
+/// How many distinct programs each iteration reads the schemas of, as when scanning a directory.
+const PROGRAM_COUNT: usize = 8;
+
+fn schema_linkers(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let engine = CommanderEngine::new();
+    let programs = runtime.block_on(async {
+        let mut programs = Vec::new();
+        for source in mock_program_sources(PROGRAM_COUNT) {
+            programs.push(engine.open_program(source).await.unwrap());
+        }
+        programs
+    });
+
+    let mut group = c.benchmark_group("read schemas");
+    group.bench_function("minimal linker", |b| {
+        b.to_async(&runtime).iter(|| async {
+            for program in &programs {
+                program.read_schema_with_minimal_linker().await.unwrap();
+            }
+        })
+    });
+    group.bench_function("full linker", |b| {
+        b.to_async(&runtime).iter(|| async {
+            for program in &programs {
+                program.read_schema_with_full_linker().await.unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, schema_linkers);
+criterion_main!(benches);
@@ -1,73 +1,317 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, ops::Index, sync::Arc};
 
 use anyhow::{anyhow, Error};
+use im::{vector, Vector};
 use tooltrain_data::CommanderValue;
 use tokio::sync::broadcast;
 
+use super::DataStreamCapacity;
+
 #[derive(Clone, Debug)]
 pub enum ListChange {
     Add(Arc<CommanderValue>),
+    /// A row was inserted before the row that was previously at the given index.
+    Insert(usize, Arc<CommanderValue>),
+    /// The row at the given index was removed.
+    RemoveAt(usize),
     Pop(Arc<CommanderValue>),
+    /// The oldest row was dropped from the front of the list.
+    Shift(Arc<CommanderValue>),
     HasMorePages(bool),
+    TotalCount(Option<u64>),
     Clear,
+    /// The producer finished streaming the list. Its rows remain available.
+    Complete,
     Destroy,
 }
 
+/// A list's rows at one point in time. Snapshots share their rows with the list, which stores
+/// them in a persistent vector, so neither taking a snapshot nor changing the list while one is
+/// alive copies more than the few chunks a change touches.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ListSnapshot(Vector<Arc<CommanderValue>>);
+
+impl ListSnapshot {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Arc<CommanderValue>> {
+        self.0.get(index)
+    }
+
+    pub fn iter(&self) -> vector::Iter<'_, Arc<CommanderValue>> {
+        self.0.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<Arc<CommanderValue>> {
+        self.0.iter().cloned().collect()
+    }
+
+    /// Whether both snapshots share the same rows, rather than merely having equal rows.
+    pub fn shares_rows_with(&self, other: &ListSnapshot) -> bool {
+        self.0.ptr_eq(&other.0)
+    }
+}
+
+impl Index<usize> for ListSnapshot {
+    type Output = Arc<CommanderValue>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a ListSnapshot {
+    type Item = &'a Arc<CommanderValue>;
+    type IntoIter = vector::Iter<'a, Arc<CommanderValue>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for ListSnapshot {
+    type Item = Arc<CommanderValue>;
+    type IntoIter = vector::ConsumingIter<Arc<CommanderValue>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl FromIterator<Arc<CommanderValue>> for ListSnapshot {
+    fn from_iter<I: IntoIterator<Item = Arc<CommanderValue>>>(rows: I) -> Self {
+        ListSnapshot(rows.into_iter().collect())
+    }
+}
+
+impl From<Vec<Arc<CommanderValue>>> for ListSnapshot {
+    fn from(rows: Vec<Arc<CommanderValue>>) -> Self {
+        ListSnapshot(rows.into())
+    }
+}
+
 #[derive(Debug)]
 pub struct ListStream {
-    value: Vec<Arc<CommanderValue>>,
+    /// Shares its chunks with any live snapshots. Only the chunks a change touches are copied.
+    value: Vector<Arc<CommanderValue>>,
+    max_length: Option<usize>,
     updates: broadcast::Sender<ListChange>,
     has_more_rows: bool,
+    total_count: Option<u64>,
+    complete: bool,
     page_load_sender: broadcast::Sender<u32>,
+    /// Incremented for every change, so consumers can tell which changes they have seen.
+    sequence: u64,
+    /// The most recent changes and their sequence numbers, oldest first.
+    history: VecDeque<(u64, ListChange)>,
+    history_capacity: usize,
+}
+
+impl Default for ListStream {
+    fn default() -> Self {
+        let capacity = DataStreamCapacity::default();
+        Self::with_capacity(capacity.list_updates, capacity.requests)
+    }
 }
 
 impl ListStream {
-    pub(crate) fn new() -> Self {
-        let (updates, _) = broadcast::channel::<ListChange>(128);
-        let (page_load_sender, _) = broadcast::channel::<u32>(32);
+    pub(crate) fn with_capacity(updates_capacity: usize, requests_capacity: usize) -> Self {
+        let (updates, _) = broadcast::channel::<ListChange>(updates_capacity);
+        let (page_load_sender, _) = broadcast::channel::<u32>(requests_capacity);
         ListStream {
-            value: vec![],
+            value: Default::default(),
+            max_length: None,
             updates,
             has_more_rows: false,
+            total_count: None,
+            complete: false,
             page_load_sender,
+            sequence: 0,
+            history: VecDeque::new(),
+            history_capacity: updates_capacity,
         }
     }
 
-    pub fn snapshot(&self) -> Vec<Arc<CommanderValue>> {
-        self.value.to_vec()
+    pub fn snapshot(&self) -> ListSnapshot {
+        ListSnapshot(self.value.clone())
+    }
+
+    /// The sequence number of the most recent change, or 0 if the list never changed.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns the list together with the sequence number of the last change it includes.
+    pub fn snapshot_with_sequence(&self) -> (u64, ListSnapshot) {
+        (self.sequence, self.snapshot())
+    }
+
+    /// Returns every change made after the change numbered `sequence`, with their sequence
+    /// numbers. Only recent changes are retained, so this fails if `sequence` is too old, in
+    /// which case the consumer has to start over from a snapshot.
+    pub fn changes_since(&self, sequence: u64) -> Result<Vec<(u64, ListChange)>, Error> {
+        if sequence > self.sequence {
+            return Err(anyhow!(
+                "Sequence {} is ahead of the list, which is at {}",
+                sequence,
+                self.sequence
+            ));
+        }
+        let oldest_retained = self
+            .history
+            .front()
+            .map_or(self.sequence + 1, |(oldest, _)| *oldest);
+        if sequence + 1 < oldest_retained {
+            return Err(anyhow!(
+                "Changes after sequence {} are no longer available",
+                sequence
+            ));
+        }
+        Ok(self
+            .history
+            .iter()
+            .filter(|(change_sequence, _)| *change_sequence > sequence)
+            .cloned()
+            .collect())
+    }
+
+    fn emit(&mut self, change: ListChange) {
+        self.sequence += 1;
+        if self.history.len() >= self.history_capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.sequence, change.clone()));
+        let _ = self.updates.send(change);
     }
 
     pub(crate) fn add(&mut self, value: CommanderValue) -> Result<(), Error> {
+        self.check_not_complete()?;
         let value_arc = Arc::new(value);
-        self.value.push(value_arc.clone());
-        let _ = self.updates.send(ListChange::Add(value_arc));
+        self.value.push_back(value_arc.clone());
+        self.emit(ListChange::Add(value_arc));
+        self.drop_oldest_rows();
+        Ok(())
+    }
+
+    /// Inserts a row before the row at `index`. An `index` equal to the list's length appends.
+    pub(crate) fn insert(&mut self, index: usize, value: CommanderValue) -> Result<(), Error> {
+        self.check_not_complete()?;
+        if index > self.value.len() {
+            return Err(anyhow!(
+                "Cannot insert at index {} in a list of {} rows",
+                index,
+                self.value.len()
+            ));
+        }
+        let value_arc = Arc::new(value);
+        self.value.insert(index, value_arc.clone());
+        self.emit(ListChange::Insert(index, value_arc));
+        self.drop_oldest_rows();
+        Ok(())
+    }
+
+    /// Caps the list at `max_length` rows. Once the cap is exceeded the oldest rows are dropped,
+    /// which keeps memory bounded for producers that append indefinitely.
+    pub(crate) fn set_max_length(&mut self, max_length: Option<usize>) -> Result<(), Error> {
+        self.check_not_complete()?;
+        self.max_length = max_length;
+        self.drop_oldest_rows();
         Ok(())
     }
 
+    fn drop_oldest_rows(&mut self) {
+        let Some(max_length) = self.max_length else {
+            return;
+        };
+        while self.value.len() > max_length {
+            if let Some(oldest) = self.value.pop_front() {
+                self.emit(ListChange::Shift(oldest));
+            }
+        }
+    }
+
     pub(crate) fn pop(&mut self) -> Result<(), Error> {
-        if let Some(pop) = self.value.pop() {
-            let _ = self.updates.send(ListChange::Pop(pop));
+        self.check_not_complete()?;
+        if let Some(pop) = self.value.pop_back() {
+            self.emit(ListChange::Pop(pop));
             Ok(())
         } else {
             Err(anyhow!("Cannot pop values from an empty list"))
         }
     }
 
+    pub(crate) fn remove_at(&mut self, index: usize) -> Result<(), Error> {
+        self.check_not_complete()?;
+        if index >= self.value.len() {
+            return Err(anyhow!(
+                "Cannot remove index {} from a list of {} rows",
+                index,
+                self.value.len()
+            ));
+        }
+        self.value.remove(index);
+        self.emit(ListChange::RemoveAt(index));
+        Ok(())
+    }
+
     pub(crate) fn clear(&mut self) -> Result<(), Error> {
-        self.value.clear();
-        let _ = self.updates.send(ListChange::Clear);
+        self.check_not_complete()?;
+        self.value = Default::default();
+        self.emit(ListChange::Clear);
+        Ok(())
+    }
+
+    /// Whether the producer finished streaming the list.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Marks the list as finished. Its rows can no longer change afterwards, as subscribers stop
+    /// following it once it completes.
+    pub(crate) fn complete(&mut self) -> Result<(), Error> {
+        if !self.complete {
+            self.complete = true;
+            self.emit(ListChange::Complete);
+        }
+        Ok(())
+    }
+
+    fn check_not_complete(&self) -> Result<(), Error> {
+        if self.complete {
+            return Err(anyhow!("Cannot change a list after it was completed"));
+        }
         Ok(())
     }
 
     pub(crate) fn destroy(&mut self) -> Result<(), Error> {
-        self.value.clear();
-        let _ = self.updates.send(ListChange::Destroy);
+        self.value = Default::default();
+        self.emit(ListChange::Destroy);
         Ok(())
     }
 
     pub(crate) fn set_has_more_rows(&mut self, has_more_pages: bool) -> Result<(), Error> {
+        self.check_not_complete()?;
         self.has_more_rows = has_more_pages;
-        let _ = self.updates.send(ListChange::HasMorePages(has_more_pages));
+        self.emit(ListChange::HasMorePages(has_more_pages));
+        Ok(())
+    }
+
+    /// The estimated total number of rows in the list, including rows not yet loaded, as
+    /// reported by the list's producer.
+    pub fn total_count(&self) -> Option<u64> {
+        self.total_count
+    }
+
+    pub(crate) fn set_total_count(&mut self, total_count: Option<u64>) -> Result<(), Error> {
+        self.check_not_complete()?;
+        self.total_count = total_count;
+        self.emit(ListChange::TotalCount(total_count));
         Ok(())
     }
 
@@ -88,3 +332,185 @@ impl ListStream {
         self.page_load_sender.subscribe()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_oldest_rows_beyond_max_length() {
+        let mut list = ListStream::default();
+        list.set_max_length(Some(2)).unwrap();
+        let mut updates = list.subscribe();
+
+        for row in 0..4 {
+            list.add(CommanderValue::Number(row as f64)).unwrap();
+        }
+
+        assert_eq!(
+            list.snapshot()
+                .iter()
+                .map(|row| (**row).clone())
+                .collect::<Vec<_>>(),
+            vec![CommanderValue::Number(2.0), CommanderValue::Number(3.0)]
+        );
+        let mut shifted = vec![];
+        while let Ok(change) = updates.try_recv() {
+            if let ListChange::Shift(row) = change {
+                shifted.push((*row).clone());
+            }
+        }
+        assert_eq!(
+            shifted,
+            vec![CommanderValue::Number(0.0), CommanderValue::Number(1.0)]
+        );
+
+        list.set_max_length(Some(1)).unwrap();
+        assert_eq!(list.snapshot().len(), 1);
+        assert!(matches!(updates.try_recv().unwrap(), ListChange::Shift(_)));
+    }
+
+    #[test]
+    fn rejects_changes_after_completing() {
+        let mut list = ListStream::default();
+        list.add(CommanderValue::Number(1.0)).unwrap();
+        list.complete().unwrap();
+        let mut updates = list.subscribe();
+
+        assert!(list.add(CommanderValue::Number(2.0)).is_err());
+        assert!(list.insert(0, CommanderValue::Number(0.0)).is_err());
+        assert!(list.set_max_length(Some(0)).is_err());
+        assert!(list.pop().is_err());
+        assert!(list.remove_at(0).is_err());
+        assert!(list.clear().is_err());
+        assert!(list.set_has_more_rows(true).is_err());
+        assert!(list.set_total_count(Some(2)).is_err());
+
+        assert_eq!(list.snapshot().len(), 1);
+        assert_eq!(list.total_count(), None);
+        assert!(list.is_complete());
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn inserts_rows_at_an_index() {
+        let mut list = ListStream::default();
+        let mut updates = list.subscribe();
+        list.add(CommanderValue::Number(1.0)).unwrap();
+        list.add(CommanderValue::Number(3.0)).unwrap();
+
+        list.insert(0, CommanderValue::Number(0.0)).unwrap();
+        list.insert(2, CommanderValue::Number(2.0)).unwrap();
+        list.insert(4, CommanderValue::Number(4.0)).unwrap();
+        assert!(list.insert(6, CommanderValue::Number(6.0)).is_err());
+
+        assert_eq!(
+            list.snapshot()
+                .iter()
+                .map(|row| (**row).clone())
+                .collect::<Vec<_>>(),
+            (0..5)
+                .map(|row| CommanderValue::Number(row as f64))
+                .collect::<Vec<_>>()
+        );
+        let mut inserted = vec![];
+        while let Ok(change) = updates.try_recv() {
+            if let ListChange::Insert(index, row) = change {
+                inserted.push((index, (*row).clone()));
+            }
+        }
+        assert_eq!(
+            inserted,
+            vec![
+                (0, CommanderValue::Number(0.0)),
+                (2, CommanderValue::Number(2.0)),
+                (4, CommanderValue::Number(4.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_rows_at_an_index() {
+        let mut list = ListStream::default();
+        for row in 0..3 {
+            list.add(CommanderValue::Number(row as f64)).unwrap();
+        }
+        let mut updates = list.subscribe();
+
+        list.remove_at(1).unwrap();
+        assert!(list.remove_at(2).is_err());
+
+        assert_eq!(
+            list.snapshot()
+                .iter()
+                .map(|row| (**row).clone())
+                .collect::<Vec<_>>(),
+            vec![CommanderValue::Number(0.0), CommanderValue::Number(2.0)]
+        );
+        assert!(matches!(
+            updates.try_recv().unwrap(),
+            ListChange::RemoveAt(1)
+        ));
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn returns_changes_since_a_sequence() {
+        let mut list = ListStream::with_capacity(4, 4);
+        list.add(CommanderValue::Number(0.0)).unwrap();
+        list.add(CommanderValue::Number(1.0)).unwrap();
+        let (cursor, rows) = list.snapshot_with_sequence();
+        assert_eq!(cursor, 2);
+        assert_eq!(rows.len(), 2);
+
+        list.add(CommanderValue::Number(2.0)).unwrap();
+        list.add(CommanderValue::Number(3.0)).unwrap();
+
+        let changes = list.changes_since(cursor).unwrap();
+        let added: Vec<(u64, CommanderValue)> = changes
+            .into_iter()
+            .map(|(sequence, change)| match change {
+                ListChange::Add(row) => (sequence, (*row).clone()),
+                other => panic!("Expected an add, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            added,
+            vec![
+                (3, CommanderValue::Number(2.0)),
+                (4, CommanderValue::Number(3.0))
+            ]
+        );
+        assert!(list.changes_since(4).unwrap().is_empty());
+        assert!(list.changes_since(5).is_err());
+
+        // Only the last four changes are retained.
+        list.add(CommanderValue::Number(4.0)).unwrap();
+        assert!(list.changes_since(0).is_err());
+        assert_eq!(list.changes_since(1).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn snapshots_share_rows_with_the_list() {
+        let mut list = ListStream::default();
+        for row in 0..100_000 {
+            list.add(CommanderValue::Number(row as f64)).unwrap();
+        }
+
+        // Without changes in between, snapshots share all of their rows.
+        let first = list.snapshot();
+        assert!((0..1_000).all(|_| list.snapshot().shares_rows_with(&first)));
+
+        // Changing the list leaves existing snapshots untouched.
+        list.add(CommanderValue::Number(-1.0)).unwrap();
+        list.insert(50_000, CommanderValue::Number(-2.0)).unwrap();
+        list.remove_at(0).unwrap();
+        let second = list.snapshot();
+        assert!(!second.shares_rows_with(&first));
+        assert_eq!(first.len(), 100_000);
+        assert_eq!(*first[0], CommanderValue::Number(0.0));
+        assert_eq!(second.len(), 100_001);
+        assert_eq!(*second[49_999], CommanderValue::Number(-2.0));
+        assert_eq!(*second[100_000], CommanderValue::Number(-1.0));
+    }
+}
@@ -6,11 +6,34 @@ mod tree;
 mod value;
 
 use anyhow::{anyhow, Error};
-use tooltrain_data::CommanderValue;
-pub use list::{ListChange, ListStream};
-pub use tree::{TreeChange, TreeStream, TreeStreamNode};
+use serde_json::{json, Value};
+use tooltrain_data::{CommanderCoder, CommanderDataType, CommanderValue};
+pub use list::{ListChange, ListSnapshot, ListStream};
+pub use tree::{TreeChange, TreeNode, TreeStream, TreeStreamNode};
 pub use value::{ValueChange, ValueStream};
 
+/// Sizes of the broadcast channels backing each kind of data stream. Subscribers that fall more
+/// than this many messages behind skip the messages they missed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataStreamCapacity {
+    pub value_updates: usize,
+    pub list_updates: usize,
+    pub tree_updates: usize,
+    /// Capacity of the page-load and load-children request channels.
+    pub requests: usize,
+}
+
+impl Default for DataStreamCapacity {
+    fn default() -> Self {
+        DataStreamCapacity {
+            value_updates: 16,
+            list_updates: 128,
+            tree_updates: 128,
+            requests: 32,
+        }
+    }
+}
+
 #[derive(Debug, TryInto, IsVariant, Unwrap)]
 pub enum DataStream {
     List(ListStream),
@@ -18,13 +41,49 @@ pub enum DataStream {
     Value(ValueStream),
 }
 
-#[derive(Clone, Debug, TryInto, IsVariant, Unwrap)]
+#[derive(Clone, Debug, PartialEq, TryInto, IsVariant, Unwrap)]
 pub enum DataStreamSnapshot {
-    List(Vec<Arc<CommanderValue>>),
+    List(ListSnapshot),
     Tree(Vec<TreeStreamNode>),
     Value(Option<Arc<CommanderValue>>),
 }
 
+impl DataStreamSnapshot {
+    /// Converts the snapshot into plain JSON. `data_type` is the data stream's declared type,
+    /// which is needed to decode tree node values. Trees become nested objects with a `children`
+    /// array, lists become arrays and an empty value becomes `null`.
+    pub fn to_json(&self, data_type: &CommanderDataType) -> Result<Value, Error> {
+        match self {
+            DataStreamSnapshot::List(rows) => {
+                Ok(Value::Array(rows.iter().map(|row| row.to_json()).collect()))
+            }
+            DataStreamSnapshot::Tree(nodes) => Ok(Value::Array(
+                nodes
+                    .iter()
+                    .map(|node| tree_node_to_json(node, data_type))
+                    .collect::<Result<_, Error>>()?,
+            )),
+            DataStreamSnapshot::Value(value) => {
+                Ok(value.as_ref().map_or(Value::Null, |value| value.to_json()))
+            }
+        }
+    }
+}
+
+fn tree_node_to_json(node: &TreeStreamNode, data_type: &CommanderDataType) -> Result<Value, Error> {
+    Ok(json!({
+        "id": node.value.id,
+        "value": data_type.decode(&node.value.value)?.to_json(),
+        "has_children": node.value.has_children,
+        "label": node.value.label,
+        "children": node
+            .children
+            .iter()
+            .map(|child| tree_node_to_json(child, data_type))
+            .collect::<Result<Vec<Value>, Error>>()?,
+    }))
+}
+
 impl DataStream {
     pub fn try_get_list(&self) -> Result<&ListStream, Error> {
         match self {
@@ -84,3 +143,109 @@ impl DataStream {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tooltrain_data::{CommanderNumberDataType, CommanderPathDataType};
+
+    #[test]
+    fn converts_value_snapshots_to_json() {
+        let data_type: CommanderDataType = CommanderNumberDataType {}.into();
+        assert_eq!(
+            DataStreamSnapshot::Value(None).to_json(&data_type).unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            DataStreamSnapshot::Value(Some(Arc::new(CommanderValue::Number(2.5))))
+                .to_json(&data_type)
+                .unwrap(),
+            json!(2.5)
+        );
+    }
+
+    #[test]
+    fn converts_list_snapshots_to_json() {
+        let data_type: CommanderDataType = CommanderNumberDataType {}.into();
+        let snapshot = DataStreamSnapshot::List(
+            vec![
+                Arc::new(CommanderValue::Number(1.0)),
+                Arc::new(CommanderValue::Number(2.0)),
+            ]
+            .into(),
+        );
+        assert_eq!(snapshot.to_json(&data_type).unwrap(), json!([1.0, 2.0]));
+        assert_eq!(
+            DataStreamSnapshot::List(ListSnapshot::default())
+                .to_json(&data_type)
+                .unwrap(),
+            json!([])
+        );
+    }
+
+    #[test]
+    fn compares_snapshots_by_value() {
+        let list = |rows: &[f64]| {
+            DataStreamSnapshot::List(
+                rows.iter()
+                    .map(|row| Arc::new(CommanderValue::Number(*row)))
+                    .collect(),
+            )
+        };
+        assert_eq!(list(&[1.0, 2.0]), list(&[1.0, 2.0]));
+        assert_ne!(list(&[1.0, 2.0]), list(&[1.0, 3.0]));
+        assert_ne!(list(&[1.0, 2.0]), list(&[1.0]));
+        assert_ne!(list(&[]), DataStreamSnapshot::Value(None));
+
+        let tree = |has_children: bool| {
+            DataStreamSnapshot::Tree(vec![TreeStreamNode {
+                value: Arc::new(TreeNode {
+                    id: "root".to_string(),
+                    value: vec![],
+                    has_children,
+                    label: None,
+                }),
+                children: vec![],
+            }])
+        };
+        assert_eq!(tree(true), tree(true));
+        assert_ne!(tree(true), tree(false));
+    }
+
+    #[test]
+    fn converts_tree_snapshots_to_nested_json() {
+        let path_type = CommanderPathDataType {};
+        let node = |id: &str, has_children: bool, children: Vec<TreeStreamNode>| TreeStreamNode {
+            value: Arc::new(TreeNode {
+                id: id.to_string(),
+                value: path_type.encode(PathBuf::from(id)).unwrap(),
+                has_children,
+                label: id.rsplit('/').next().map(String::from),
+            }),
+            children,
+        };
+        let snapshot = DataStreamSnapshot::Tree(vec![node(
+            "/tmp",
+            true,
+            vec![node("/tmp/a.txt", false, vec![])],
+        )]);
+
+        assert_eq!(
+            snapshot.to_json(&path_type.into()).unwrap(),
+            json!([{
+                "id": "/tmp",
+                "value": "/tmp",
+                "has_children": true,
+                "label": "tmp",
+                "children": [{
+                    "id": "/tmp/a.txt",
+                    "value": "/tmp/a.txt",
+                    "has_children": false,
+                    "label": "a.txt",
+                    "children": [],
+                }],
+            }])
+        );
+    }
+}
@@ -1,9 +1,14 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Error};
 use tokio::sync::broadcast;
 
-use crate::bindings::streaming_outputs::TreeNode;
+use super::DataStreamCapacity;
+
+pub use crate::bindings::streaming_outputs::TreeNode;
 
 #[derive(Clone, Debug)]
 pub enum TreeChange {
@@ -13,6 +18,13 @@ pub enum TreeChange {
     },
     Remove(Arc<TreeNode>),
     Clear,
+    /// The producer could not load the children of `parent`. They may be requested again.
+    LoadFailed {
+        parent: String,
+        message: String,
+    },
+    /// The producer finished streaming the tree. Its nodes remain available.
+    Complete,
     Destroy,
 }
 
@@ -22,23 +34,48 @@ pub struct TreeStreamNode {
     pub children: Vec<TreeStreamNode>,
 }
 
+impl PartialEq for TreeStreamNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.value.id == other.value.id
+            && self.value.value == other.value.value
+            && self.value.has_children == other.value.has_children
+            && self.value.label == other.value.label
+            && self.children == other.children
+    }
+}
+
 #[derive(Debug)]
 pub struct TreeStream {
     nodes: HashMap<String, Arc<TreeNode>>,
     edges: HashMap<Option<String>, Vec<String>>,
     updates: broadcast::Sender<TreeChange>,
     load_children_sender: broadcast::Sender<String>,
+    // Parents whose children were requested but have not been added yet.
+    pending_children: HashSet<String>,
+    // Parents whose children request was cancelled by the host before the children were added.
+    cancelled_children: HashSet<String>,
+    complete: bool,
+}
+
+impl Default for TreeStream {
+    fn default() -> Self {
+        let capacity = DataStreamCapacity::default();
+        Self::with_capacity(capacity.tree_updates, capacity.requests)
+    }
 }
 
 impl TreeStream {
-    pub(crate) fn new() -> Self {
-        let (updates, _) = broadcast::channel::<TreeChange>(128);
-        let (load_children_sender, _) = broadcast::channel::<String>(32);
+    pub(crate) fn with_capacity(updates_capacity: usize, requests_capacity: usize) -> Self {
+        let (updates, _) = broadcast::channel::<TreeChange>(updates_capacity);
+        let (load_children_sender, _) = broadcast::channel::<String>(requests_capacity);
         TreeStream {
             nodes: HashMap::new(),
             edges: HashMap::new(),
             updates,
             load_children_sender,
+            pending_children: HashSet::new(),
+            cancelled_children: HashSet::new(),
+            complete: false,
         }
     }
 
@@ -46,6 +83,12 @@ impl TreeStream {
         self.subtree(&None)
     }
 
+    /// Returns the loaded descendants of the node with id `parent_id`, without cloning the rest
+    /// of the tree.
+    pub fn subtree_snapshot(&self, parent_id: &str) -> Vec<TreeStreamNode> {
+        self.subtree(&Some(parent_id.to_string()))
+    }
+
     fn subtree(&self, root: &Option<String>) -> Vec<TreeStreamNode> {
         self.edges
             .get(root)
@@ -63,11 +106,33 @@ impl TreeStream {
             .unwrap_or_default()
     }
 
+    /// Resolves a node by walking down from the root, matching one path component per level.
+    /// A child matches a component when its id is either the component itself or the parent's id
+    /// joined with the component by a `/`.
+    pub fn node_at_path(&self, components: &[String]) -> Option<&Arc<TreeNode>> {
+        let mut current: Option<&Arc<TreeNode>> = None;
+        for component in components {
+            let parent_id = current.map(|node| node.id.clone());
+            let joined_id = parent_id
+                .as_ref()
+                .map(|parent| format!("{}/{}", parent, component));
+            current = Some(
+                self.edges
+                    .get(&parent_id)?
+                    .iter()
+                    .filter_map(|id| self.nodes.get(id))
+                    .find(|node| node.id == *component || Some(&node.id) == joined_id.as_ref())?,
+            );
+        }
+        current
+    }
+
     pub(crate) fn add(
         &mut self,
         parent: Option<String>,
         children: Vec<TreeNode>,
     ) -> Result<(), Error> {
+        self.check_not_complete()?;
         if parent.is_some() && !self.nodes.contains_key(parent.as_ref().unwrap()) {
             return Err(anyhow!(
                 "Could not add children to non-existent parent {:?}",
@@ -75,6 +140,9 @@ impl TreeStream {
             ));
         }
 
+        if let Some(parent) = &parent {
+            self.settle_children_request(parent);
+        }
         let node_arcs: Vec<Arc<TreeNode>> = children.into_iter().map(Arc::new).collect();
         self.nodes.extend(
             node_arcs
@@ -93,28 +161,125 @@ impl TreeStream {
         Ok(())
     }
 
+    /// Replaces the children of `parent` with `children`, diffing by id so that unchanged nodes
+    /// (and everything loaded below them) are kept. Only nodes that were removed, added or whose
+    /// contents changed are reported, so refreshing a directory with the same contents emits no
+    /// changes at all.
+    pub(crate) fn reconcile(
+        &mut self,
+        parent: Option<String>,
+        children: Vec<TreeNode>,
+    ) -> Result<(), Error> {
+        self.check_not_complete()?;
+        if parent.is_some() && !self.nodes.contains_key(parent.as_ref().unwrap()) {
+            return Err(anyhow!(
+                "Could not reconcile children of non-existent parent {:?}",
+                parent
+            ));
+        }
+
+        if let Some(parent) = &parent {
+            self.settle_children_request(parent);
+        }
+        let existing_ids = self.edges.get(&parent).cloned().unwrap_or_default();
+        let mut kept_ids = HashSet::new();
+        for id in existing_ids {
+            let unchanged = children.iter().any(|child| {
+                self.nodes.get(&id).is_some_and(|node| {
+                    node.id == child.id
+                        && node.value == child.value
+                        && node.has_children == child.has_children
+                        && node.label == child.label
+                })
+            });
+            if unchanged {
+                kept_ids.insert(id);
+            } else {
+                self.remove_subtree(id);
+            }
+        }
+
+        self.edges.insert(
+            parent.clone(),
+            children.iter().map(|child| child.id.clone()).collect(),
+        );
+        let added: Vec<Arc<TreeNode>> = children
+            .into_iter()
+            .filter(|child| !kept_ids.contains(&child.id))
+            .map(Arc::new)
+            .collect();
+        if added.is_empty() {
+            return Ok(());
+        }
+        self.nodes
+            .extend(added.iter().cloned().map(|node| (node.id.clone(), node)));
+        let _ = self.updates.send(TreeChange::Add {
+            parent,
+            children: added,
+        });
+        Ok(())
+    }
+
     pub(crate) fn remove(&mut self, id: String) -> Result<(), Error> {
-        let Some(node) = self.nodes.remove(&id) else {
+        self.check_not_complete()?;
+        if !self.nodes.contains_key(&id) {
             return Err(anyhow!("Could not remove non-existent node {:?}", id));
-        };
+        }
+
+        for siblings in self.edges.values_mut() {
+            siblings.retain(|sibling| *sibling != id);
+        }
+        self.remove_subtree(id);
+        Ok(())
+    }
 
-        if let Some(child_ids) = self.edges.remove(&Some(id)) {
+    /// Removes a node and all of its descendants, emitting a `TreeChange::Remove` for every
+    /// removed node. Children are always reported before their parent.
+    fn remove_subtree(&mut self, id: String) {
+        if let Some(child_ids) = self.edges.remove(&Some(id.clone())) {
             for child in child_ids {
-                self.remove(child)?;
+                self.remove_subtree(child);
             }
         }
 
-        let _ = self.updates.send(TreeChange::Remove(node));
-        Ok(())
+        self.settle_children_request(&id);
+        if let Some(node) = self.nodes.remove(&id) {
+            let _ = self.updates.send(TreeChange::Remove(node));
+        }
     }
 
     pub(crate) fn clear(&mut self) -> Result<(), Error> {
+        self.check_not_complete()?;
         self.nodes.clear();
         self.edges.clear();
+        self.pending_children.clear();
+        self.cancelled_children.clear();
         let _ = self.updates.send(TreeChange::Clear);
         Ok(())
     }
 
+    /// Whether the producer finished streaming the tree.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Marks the tree as finished. Its nodes can no longer change afterwards, as subscribers stop
+    /// following it once it completes.
+    pub(crate) fn complete(&mut self) -> Result<(), Error> {
+        if !self.complete {
+            self.complete = true;
+            let _ = self.updates.send(TreeChange::Complete);
+        }
+        Ok(())
+    }
+
+    fn check_not_complete(&self) -> Result<(), Error> {
+        if self.complete {
+            return Err(anyhow!("Cannot change a tree after it was completed"));
+        }
+        Ok(())
+    }
+
     pub(crate) fn destroy(&mut self) -> Result<(), Error> {
         self.nodes.clear();
         self.edges.clear();
@@ -122,15 +287,67 @@ impl TreeStream {
         Ok(())
     }
 
+    /// Asks the program to load the children of `parent`. Returns `false` without sending
+    /// another request if the children of `parent` were already requested and have not been
+    /// added yet.
     pub fn request_children(&mut self, parent: String) -> Result<bool, Error> {
         if !self.nodes.contains_key(&parent) {
+            return Err(anyhow!(
+                "Cannot request children of unknown node {:?}",
+                parent
+            ));
+        }
+        if self.pending_children.contains(&parent) {
             return Ok(false);
         }
 
-        self.load_children_sender.send(parent)?;
+        self.load_children_sender.send(parent.clone())?;
+        self.cancelled_children.remove(&parent);
+        self.pending_children.insert(parent);
         Ok(true)
     }
 
+    /// Withdraws the pending request for the children of `parent`, so the program can abandon
+    /// loading them. Returns `false` if there was no pending request, e.g. because the children
+    /// were already added.
+    pub fn cancel_children_request(&mut self, parent: &str) -> bool {
+        if !self.pending_children.remove(parent) {
+            return false;
+        }
+        self.cancelled_children.insert(parent.to_string());
+        true
+    }
+
+    /// Whether the request for the children of `parent` was cancelled, and not made again since.
+    pub fn is_children_request_cancelled(&self, parent: &str) -> bool {
+        self.cancelled_children.contains(parent)
+    }
+
+    /// Settles the request for the children of `parent` without adding any, so that a later
+    /// [`TreeStream::request_children`] sends a new request instead of waiting on this one.
+    pub(crate) fn fail_children_request(
+        &mut self,
+        parent: String,
+        message: String,
+    ) -> Result<(), Error> {
+        if !self.nodes.contains_key(&parent) {
+            return Err(anyhow!(
+                "Cannot fail loading children of unknown node {:?}",
+                parent
+            ));
+        }
+        self.settle_children_request(&parent);
+        let _ = self
+            .updates
+            .send(TreeChange::LoadFailed { parent, message });
+        Ok(())
+    }
+
+    fn settle_children_request(&mut self, parent: &str) {
+        self.pending_children.remove(parent);
+        self.cancelled_children.remove(parent);
+    }
+
     pub fn subscribe(&self) -> broadcast::Receiver<TreeChange> {
         self.updates.subscribe()
     }
@@ -139,3 +356,257 @@ impl TreeStream {
         self.load_children_sender.subscribe()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str) -> TreeNode {
+        TreeNode {
+            id: id.to_string(),
+            value: vec![],
+            has_children: true,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn rejects_changes_after_completing() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("Users")]).unwrap();
+        tree.complete().unwrap();
+        let mut updates = tree.subscribe();
+
+        assert!(tree.add(None, vec![node("tmp")]).is_err());
+        assert!(tree.reconcile(None, vec![node("tmp")]).is_err());
+        assert!(tree.remove("Users".to_string()).is_err());
+        assert!(tree.clear().is_err());
+
+        assert!(tree.node_at_path(&["Users".to_string()]).is_some());
+        assert!(tree.is_complete());
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn resolves_node_at_path() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("Users"), node("tmp")]).unwrap();
+        tree.add(Some("Users".to_string()), vec![node("Users/keaton")])
+            .unwrap();
+
+        let resolved = tree
+            .node_at_path(&["Users".to_string(), "keaton".to_string()])
+            .unwrap();
+        assert_eq!(resolved.id, "Users/keaton");
+
+        assert!(tree
+            .node_at_path(&["Users".to_string(), "missing".to_string()])
+            .is_none());
+        assert!(tree.node_at_path(&["missing".to_string()]).is_none());
+        assert!(tree.node_at_path(&[]).is_none());
+    }
+
+    #[test]
+    fn removing_a_node_reports_all_descendants() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("root")]).unwrap();
+        tree.add(Some("root".to_string()), vec![node("root/child")])
+            .unwrap();
+        tree.add(
+            Some("root/child".to_string()),
+            vec![node("root/child/a"), node("root/child/b")],
+        )
+        .unwrap();
+
+        let mut updates = tree.subscribe();
+        tree.remove("root/child".to_string()).unwrap();
+
+        let mut removed = vec![];
+        while let Ok(TreeChange::Remove(node)) = updates.try_recv() {
+            removed.push(node.id.clone());
+        }
+        assert_eq!(removed, vec!["root/child/a", "root/child/b", "root/child"]);
+
+        let snapshot = tree.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert!(snapshot[0].children.is_empty());
+        assert!(tree.remove("root/child/a".to_string()).is_err());
+    }
+
+    #[test]
+    fn reconciling_identical_children_emits_nothing() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("root")]).unwrap();
+        tree.add(
+            Some("root".to_string()),
+            vec![node("root/a"), node("root/b")],
+        )
+        .unwrap();
+        tree.add(Some("root/a".to_string()), vec![node("root/a/x")])
+            .unwrap();
+
+        let mut updates = tree.subscribe();
+        tree.reconcile(
+            Some("root".to_string()),
+            vec![node("root/a"), node("root/b")],
+        )
+        .unwrap();
+        assert!(updates.try_recv().is_err());
+        assert_eq!(tree.subtree_snapshot("root/a").len(), 1);
+    }
+
+    #[test]
+    fn snapshots_keep_node_labels() {
+        let mut tree = TreeStream::default();
+        tree.add(
+            None,
+            vec![TreeNode {
+                label: Some("Documents".to_string()),
+                ..node("Users/keaton/Documents")
+            }],
+        )
+        .unwrap();
+
+        let snapshot = tree.snapshot();
+        assert_eq!(snapshot[0].value.label.as_deref(), Some("Documents"));
+
+        let mut updates = tree.subscribe();
+        tree.reconcile(None, vec![node("Users/keaton/Documents")])
+            .unwrap();
+        assert!(matches!(updates.try_recv(), Ok(TreeChange::Remove(_))));
+        assert_eq!(tree.snapshot()[0].value.label, None);
+    }
+
+    #[test]
+    fn reconciling_reports_only_differences() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("a"), node("b"), node("c")])
+            .unwrap();
+
+        let mut updates = tree.subscribe();
+        let changed_b = TreeNode {
+            value: vec![1],
+            ..node("b")
+        };
+        tree.reconcile(None, vec![changed_b, node("c"), node("d")])
+            .unwrap();
+
+        let mut removed = vec![];
+        let mut added = vec![];
+        while let Ok(change) = updates.try_recv() {
+            match change {
+                TreeChange::Remove(node) => removed.push(node.id.clone()),
+                TreeChange::Add { children, .. } => {
+                    added.extend(children.iter().map(|node| node.id.clone()))
+                }
+                other => panic!("Unexpected change {:?}", other),
+            }
+        }
+        assert_eq!(removed, vec!["a", "b"]);
+        assert_eq!(added, vec!["b", "d"]);
+
+        let ids: Vec<String> = tree
+            .snapshot()
+            .iter()
+            .map(|node| node.value.id.clone())
+            .collect();
+        assert_eq!(ids, vec!["b", "c", "d"]);
+        assert_eq!(tree.snapshot()[0].value.value, vec![1]);
+    }
+
+    #[test]
+    fn requesting_children_of_unknown_node_fails() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("root")]).unwrap();
+        let mut requests = tree.get_request_children_stream();
+
+        assert!(tree.request_children("root".to_string()).unwrap());
+        assert_eq!(requests.try_recv().unwrap(), "root");
+
+        let error = tree.request_children("bogus".to_string()).unwrap_err();
+        assert!(error.to_string().contains("unknown node"));
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn coalesces_repeated_children_requests() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("root")]).unwrap();
+        let mut requests = tree.get_request_children_stream();
+
+        assert!(tree.request_children("root".to_string()).unwrap());
+        assert!(!tree.request_children("root".to_string()).unwrap());
+        assert_eq!(requests.try_recv().unwrap(), "root");
+        assert!(requests.try_recv().is_err());
+
+        // Once the children arrive, the parent can be refreshed again.
+        tree.add(Some("root".to_string()), vec![node("root/a")])
+            .unwrap();
+        assert!(tree.request_children("root".to_string()).unwrap());
+        assert_eq!(requests.try_recv().unwrap(), "root");
+    }
+
+    #[test]
+    fn cancels_pending_children_requests() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("root")]).unwrap();
+        let mut requests = tree.get_request_children_stream();
+
+        assert!(!tree.cancel_children_request("root"));
+        assert!(tree.request_children("root".to_string()).unwrap());
+        assert!(tree.cancel_children_request("root"));
+        assert!(tree.is_children_request_cancelled("root"));
+        assert!(!tree.cancel_children_request("root"));
+
+        // Requesting the children again sends a new request that is no longer cancelled.
+        assert!(tree.request_children("root".to_string()).unwrap());
+        assert!(!tree.is_children_request_cancelled("root"));
+        assert_eq!(requests.try_recv().unwrap(), "root");
+        assert_eq!(requests.try_recv().unwrap(), "root");
+    }
+
+    #[test]
+    fn failed_children_requests_can_be_retried() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("root")]).unwrap();
+        let mut requests = tree.get_request_children_stream();
+        let mut changes = tree.subscribe();
+
+        assert!(tree.request_children("root".to_string()).unwrap());
+        tree.fail_children_request("root".to_string(), "Permission denied".to_string())
+            .unwrap();
+        assert!(matches!(
+            changes.try_recv().unwrap(),
+            TreeChange::LoadFailed { parent, message }
+                if parent == "root" && message == "Permission denied"
+        ));
+
+        assert!(tree.request_children("root".to_string()).unwrap());
+        assert_eq!(requests.try_recv().unwrap(), "root");
+        assert_eq!(requests.try_recv().unwrap(), "root");
+        assert!(tree
+            .fail_children_request("missing".to_string(), String::new())
+            .is_err());
+    }
+
+    #[test]
+    fn subtree_snapshot_returns_only_the_requested_branch() {
+        let mut tree = TreeStream::default();
+        tree.add(None, vec![node("a"), node("b")]).unwrap();
+        tree.add(Some("a".to_string()), vec![node("a/1"), node("a/2")])
+            .unwrap();
+        tree.add(Some("a/1".to_string()), vec![node("a/1/x")])
+            .unwrap();
+        tree.add(Some("b".to_string()), vec![node("b/1")]).unwrap();
+
+        let branch = tree.subtree_snapshot("a");
+        let ids: Vec<&str> = branch.iter().map(|n| n.value.id.as_str()).collect();
+        assert_eq!(ids, vec!["a/1", "a/2"]);
+        assert_eq!(branch[0].children.len(), 1);
+        assert_eq!(branch[0].children[0].value.id, "a/1/x");
+        assert!(branch[1].children.is_empty());
+
+        assert!(tree.subtree_snapshot("a/1/x").is_empty());
+        assert!(tree.subtree_snapshot("missing").is_empty());
+    }
+}
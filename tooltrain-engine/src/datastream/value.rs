@@ -1,43 +1,89 @@
 use std::sync::Arc;
 
-use anyhow::Error;
-use tooltrain_data::CommanderValue;
+use anyhow::{anyhow, Error};
 use tokio::sync::broadcast;
+use tooltrain_data::CommanderValue;
 
 #[derive(Clone, Debug)]
 pub enum ValueChange {
     Set(Arc<CommanderValue>),
+    // A chunk of bytes appended to a bytes value.
+    AppendBytes(Arc<Vec<u8>>),
     Destroy,
 }
 
 #[derive(Debug)]
 pub struct ValueStream {
     value: Option<Arc<CommanderValue>>,
+    /// Chunks appended to a bytes `value` since it was last set. They are kept apart from the
+    /// value, and shared with subscribers, so that appending never copies the bytes before them.
+    appended: Vec<Arc<Vec<u8>>>,
     updates: broadcast::Sender<ValueChange>,
 }
 
 impl ValueStream {
-    pub(crate) fn new(initial: Option<CommanderValue>) -> Self {
-        let (updates, _) = broadcast::channel::<ValueChange>(128);
+    pub(crate) fn with_capacity(initial: Option<CommanderValue>, capacity: usize) -> Self {
+        let (updates, _) = broadcast::channel::<ValueChange>(capacity);
         ValueStream {
             value: initial.map(Arc::new),
+            appended: vec![],
             updates,
         }
     }
 
+    /// Returns the current value. A bytes value that was appended to is only concatenated here.
     pub fn snapshot(&self) -> Option<Arc<CommanderValue>> {
-        self.value.clone()
+        let value = self.value.as_ref()?;
+        let CommanderValue::Bytes(bytes) = value.as_ref() else {
+            return Some(value.clone());
+        };
+        if self.appended.is_empty() {
+            return Some(value.clone());
+        }
+        let length = bytes.len() + self.appended.iter().map(|chunk| chunk.len()).sum::<usize>();
+        let mut whole = Vec::with_capacity(length);
+        whole.extend_from_slice(bytes);
+        for chunk in &self.appended {
+            whole.extend_from_slice(chunk);
+        }
+        Some(Arc::new(CommanderValue::Bytes(whole)))
     }
 
     pub(crate) fn set(&mut self, value: CommanderValue) -> Result<(), Error> {
         let value_arc = Arc::new(value);
         self.value = Some(value_arc.clone());
+        self.appended.clear();
         let _ = self.updates.send(ValueChange::Set(value_arc));
         Ok(())
     }
 
+    /// Sets the value unless it is equal to the current one, so that subscribers are not told
+    /// about updates that change nothing. Returns whether the value changed.
+    pub(crate) fn set_if_changed(&mut self, value: CommanderValue) -> Result<bool, Error> {
+        if self.snapshot().as_deref() == Some(&value) {
+            return Ok(false);
+        }
+        self.set(value)?;
+        Ok(true)
+    }
+
+    /// Appends `chunk` to a bytes value, so that large values can be built up and streamed
+    /// piece by piece. Subscribers are sent only the new chunk.
+    pub(crate) fn append_bytes(&mut self, chunk: Vec<u8>) -> Result<(), Error> {
+        match self.value.as_deref() {
+            None => self.value = Some(Arc::new(CommanderValue::Bytes(vec![]))),
+            Some(CommanderValue::Bytes(_)) => {}
+            Some(_) => return Err(anyhow!("Can only append bytes to a bytes value")),
+        }
+        let chunk = Arc::new(chunk);
+        self.appended.push(chunk.clone());
+        let _ = self.updates.send(ValueChange::AppendBytes(chunk));
+        Ok(())
+    }
+
     pub(crate) fn destroy(&mut self) -> Result<(), Error> {
         self.value = None;
+        self.appended.clear();
         let _ = self.updates.send(ValueChange::Destroy);
         Ok(())
     }
@@ -46,3 +92,52 @@ impl ValueStream {
         self.updates.subscribe()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_if_changed_skips_unchanged_values() {
+        let mut stream = ValueStream::with_capacity(None, 16);
+        let mut updates = stream.subscribe();
+
+        assert!(stream.set_if_changed(CommanderValue::Number(1.0)).unwrap());
+        assert!(!stream.set_if_changed(CommanderValue::Number(1.0)).unwrap());
+
+        assert!(matches!(updates.try_recv().unwrap(), ValueChange::Set(_)));
+        assert!(updates.try_recv().is_err());
+        assert_eq!(
+            stream.snapshot().as_deref(),
+            Some(&CommanderValue::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn appends_bytes_to_bytes_values() {
+        let mut stream = ValueStream::with_capacity(None, 16);
+        let mut updates = stream.subscribe();
+
+        stream.append_bytes(vec![1, 2]).unwrap();
+        let snapshot = stream.snapshot();
+        stream.append_bytes(vec![3]).unwrap();
+        assert_eq!(
+            stream.snapshot().as_deref(),
+            Some(&CommanderValue::Bytes(vec![1, 2, 3]))
+        );
+        // Earlier snapshots keep the bytes they were taken with.
+        assert_eq!(
+            snapshot.as_deref(),
+            Some(&CommanderValue::Bytes(vec![1, 2]))
+        );
+        let ValueChange::AppendBytes(first_chunk) = updates.try_recv().unwrap() else {
+            panic!("Expected an appended chunk");
+        };
+        assert_eq!(*first_chunk, vec![1, 2]);
+        // Subscribers share the appended chunk rather than receiving a copy of it.
+        assert!(Arc::ptr_eq(&first_chunk, &stream.appended[0]));
+
+        stream.set(CommanderValue::Number(1.0)).unwrap();
+        assert!(stream.append_bytes(vec![4]).is_err());
+    }
+}
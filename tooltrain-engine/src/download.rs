@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Error};
+use http_body_util::{BodyExt, Empty};
+use hyper::{header, Uri};
+use wasmtime_wasi_http::types::{default_send_request_handler, OutgoingRequestConfig};
+
+/// Limits applied when a program is loaded from a URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DownloadConfig {
+    /// How long the whole download, from connecting to reading the last byte, may take.
+    pub timeout: Duration,
+    /// Largest program that will be downloaded, in bytes.
+    pub max_bytes: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            timeout: Duration::from_secs(30),
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl DownloadConfig {
+    /// Downloads the body of `url`, failing on non-success statuses and bodies over
+    /// [`DownloadConfig::max_bytes`].
+    pub(crate) async fn download(&self, url: &str) -> Result<Vec<u8>, Error> {
+        tokio::time::timeout(self.timeout, self.download_without_timeout(url))
+            .await
+            .map_err(|_| anyhow!("Timed out downloading {} after {:?}", url, self.timeout))?
+            .map_err(|error| error.context(format!("Could not download {}", url)))
+    }
+
+    async fn download_without_timeout(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let uri: Uri = url.parse()?;
+        let use_tls = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(anyhow!("Only http and https URLs are supported")),
+        };
+        let authority = uri
+            .authority()
+            .ok_or_else(|| anyhow!("URL has no host"))?
+            .to_string();
+        let request = hyper::Request::get(uri)
+            .header(header::HOST, authority)
+            .body(Empty::new().map_err(|never| match never {}).boxed())?;
+        let config = OutgoingRequestConfig {
+            use_tls,
+            connect_timeout: self.timeout,
+            first_byte_timeout: self.timeout,
+            between_bytes_timeout: self.timeout,
+        };
+
+        // The response keeps the connection's worker task alive while its body is read.
+        let mut response = default_send_request_handler(request, config)
+            .await
+            .map_err(|error| anyhow!("{:?}", error))?;
+        let status = response.resp.status();
+        if !status.is_success() {
+            return Err(anyhow!("Server responded with {}", status));
+        }
+        let content_length = response
+            .resp
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+        if content_length.is_some_and(|length| length > self.max_bytes) {
+            return Err(self.too_large());
+        }
+
+        let mut bytes = Vec::new();
+        let body = response.resp.body_mut();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(|error| anyhow!("{:?}", error))?;
+            if let Ok(data) = frame.into_data() {
+                if (bytes.len() + data.len()) as u64 > self.max_bytes {
+                    return Err(self.too_large());
+                }
+                bytes.extend_from_slice(&data);
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn too_large(&self) -> Error {
+        anyhow!("Program is larger than the {} byte limit", self.max_bytes)
+    }
+}
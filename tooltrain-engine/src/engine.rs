@@ -1,33 +1,84 @@
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, VecDeque},
     future::Future,
     path::PathBuf,
-    sync::Arc,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
 
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, Context, Error};
 
 use tooltrain_data::{CommanderCoder, CommanderDataType, CommanderValue};
 
-use tokio::sync::watch;
+use futures::FutureExt;
+use sha2::{Digest, Sha256};
+use tokio::{
+    sync::watch,
+    task::{AbortHandle, JoinHandle},
+};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt, StreamMap};
 
 use wasmtime::{
-    component::{Component, Linker},
-    Config, Engine, Store,
+    component::{types::ComponentItem, Component, Linker, LinkerInstance, ResourceType},
+    Config, Engine, Store, Trap,
 };
 use wasmtime_wasi::WasiImpl;
 
 use crate::{
     bindings::{
         inputs::{self, ArgumentSpec, Schema},
-        streaming::{Input, StreamingPlugin},
+        streaming::{Input, StreamingPlugin, StreamingPluginPre},
+    },
+    datastream::{DataStream, DataStreamCapacity, ListChange, TreeChange, ValueChange},
+    download::DownloadConfig,
+    limits::{ResourceLimitExceeded, ResourceLimits},
+    streaming::{
+        DataStreamResourceChange, DataStreamStorage, Inputs, ListOutputRef, OutputHandle,
+        OutputPipe, OutputRef, Outputs, ResourceId, WasiConfig, WasmStorage,
     },
-    streaming::{DataStreamStorage, Inputs, OutputRef, Outputs, WasmStorage},
 };
 
 struct CommanderEngineInternal {
     wasm_engine: Engine,
     linker: Linker<WasmStorage>,
+    /// Links only the interfaces in [`SCHEMA_LINKER_INTERFACES`], for reading programs' schemas.
+    schema_linker: Linker<WasmStorage>,
+    stream_capacity: DataStreamCapacity,
+    wasi_config: WasiConfig,
+    download_config: DownloadConfig,
+    resource_limits: ResourceLimits,
+    run_timeout: Option<Duration>,
+    unique_output_names: bool,
+    program_verifier: Option<Arc<ProgramVerifier>>,
+}
+
+/// How often the epochs that [`CommanderEngineBuilder::run_timeout`] deadlines are counted in
+/// advance.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Checks a program's bytes before they are compiled, e.g. against its publisher's signature.
+type ProgramVerifier = dyn Fn(&[u8]) -> Result<(), Error> + Send + Sync;
+
+impl CommanderEngineInternal {
+    fn verify_program(&self, bytes: &[u8]) -> Result<(), Error> {
+        match &self.program_verifier {
+            Some(verifier) => {
+                verifier(bytes).map_err(|error| error.context("Program was rejected"))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Makes `store` trap once the engine's run timeout has passed from now. Without a timeout
+    /// the epoch never advances, so the deadline is never reached.
+    fn reset_deadline(run_timeout: Option<Duration>, store: &mut Store<WasmStorage>) {
+        store.epoch_deadline_trap();
+        store.set_epoch_deadline(match run_timeout {
+            Some(timeout) => timeout.as_nanos().div_ceil(EPOCH_TICK.as_nanos()).max(1) as u64,
+            None => 1,
+        });
+    }
 }
 
 impl Default for CommanderEngineInternal {
@@ -35,41 +86,138 @@ impl Default for CommanderEngineInternal {
         let engine = Engine::new(
             Config::default()
                 .async_support(true)
-                .wasm_component_model(true),
+                .wasm_component_model(true)
+                .epoch_interruption(true),
         )
         .unwrap();
 
-        fn get_host(storage: &mut WasmStorage) -> WasiImpl<&mut WasmStorage> {
-            WasiImpl(storage)
-        }
-
         let mut linker: Linker<WasmStorage> = Linker::new(&engine);
         wasmtime_wasi::add_to_linker_async(&mut linker).unwrap();
         wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker).unwrap();
         StreamingPlugin::add_to_linker_imports_get_host(&mut linker, get_host).unwrap();
-        crate::bindings::streaming::tooltrain::base::inputs::add_to_linker_get_host(
-            &mut linker,
-            get_host,
-        )
-        .unwrap();
-        crate::bindings::streaming::tooltrain::base::streaming_inputs::add_to_linker_get_host(
-            &mut linker,
-            get_host,
-        )
-        .unwrap();
-        crate::bindings::streaming::tooltrain::base::streaming_outputs::add_to_linker_get_host(
-            &mut linker,
-            get_host,
-        )
-        .unwrap();
+        add_tooltrain_interfaces_to_linker(&mut linker).unwrap();
 
         CommanderEngineInternal {
+            schema_linker: new_schema_linker(&engine).unwrap(),
             wasm_engine: engine,
             linker,
+            stream_capacity: DataStreamCapacity::default(),
+            wasi_config: WasiConfig::default(),
+            download_config: DownloadConfig::default(),
+            resource_limits: ResourceLimits::default(),
+            run_timeout: None,
+            unique_output_names: false,
+            program_verifier: None,
         }
     }
 }
 
+fn get_host(storage: &mut WasmStorage) -> WasiImpl<&mut WasmStorage> {
+    WasiImpl(storage)
+}
+
+/// Links the interfaces that define the resources used in the `run` export's signature, which
+/// both linkers need for the program's exports to type check.
+fn add_tooltrain_interfaces_to_linker(linker: &mut Linker<WasmStorage>) -> Result<(), Error> {
+    use crate::bindings::streaming::tooltrain::base::{
+        inputs, streaming_inputs, streaming_outputs,
+    };
+    inputs::add_to_linker_get_host(linker, get_host)?;
+    streaming_inputs::add_to_linker_get_host(linker, get_host)?;
+    streaming_outputs::add_to_linker_get_host(linker, get_host)?;
+    Ok(())
+}
+
+/// The interfaces a program's runtime may touch while it builds its schema: clocks, randomness,
+/// streams and the CLI environment, plus the interfaces defining the resources in the program's
+/// exports. Every other import, including filesystem, sockets, HTTP and the world-level functions
+/// that add inputs and outputs, is stubbed by [`stub_unprovided_imports`] with a function that
+/// fails when called.
+const SCHEMA_LINKER_INTERFACES: &[&str] = &[
+    "wasi:clocks/wall-clock",
+    "wasi:clocks/monotonic-clock",
+    "wasi:io/error",
+    "wasi:io/poll",
+    "wasi:io/streams",
+    "wasi:random/random",
+    "wasi:random/insecure",
+    "wasi:random/insecure-seed",
+    "wasi:cli/exit",
+    "wasi:cli/environment",
+    "wasi:cli/stdin",
+    "wasi:cli/stdout",
+    "wasi:cli/stderr",
+    "tooltrain:base/inputs",
+    "tooltrain:base/streaming-inputs",
+    "tooltrain:base/streaming-outputs",
+];
+
+fn new_schema_linker(engine: &Engine) -> Result<Linker<WasmStorage>, Error> {
+    use wasmtime_wasi::bindings::{cli, clocks, io, random};
+    let mut linker: Linker<WasmStorage> = Linker::new(engine);
+    clocks::wall_clock::add_to_linker_get_host(&mut linker, get_host)?;
+    clocks::monotonic_clock::add_to_linker_get_host(&mut linker, get_host)?;
+    io::error::add_to_linker_get_host(&mut linker, get_host)?;
+    io::poll::add_to_linker_get_host(&mut linker, get_host)?;
+    io::streams::add_to_linker_get_host(&mut linker, get_host)?;
+    random::random::add_to_linker_get_host(&mut linker, get_host)?;
+    random::insecure::add_to_linker_get_host(&mut linker, get_host)?;
+    random::insecure_seed::add_to_linker_get_host(&mut linker, get_host)?;
+    cli::exit::add_to_linker_get_host(&mut linker, get_host)?;
+    cli::environment::add_to_linker_get_host(&mut linker, get_host)?;
+    cli::stdin::add_to_linker_get_host(&mut linker, get_host)?;
+    cli::stdout::add_to_linker_get_host(&mut linker, get_host)?;
+    cli::stderr::add_to_linker_get_host(&mut linker, get_host)?;
+    add_tooltrain_interfaces_to_linker(&mut linker)?;
+    Ok(linker)
+}
+
+/// Defines every import of `component` that the schema linker does not provide as a function
+/// that fails when called, so that the component can still be instantiated.
+fn stub_unprovided_imports(
+    linker: &mut Linker<WasmStorage>,
+    component: &Component,
+) -> Result<(), Error> {
+    let engine = linker.engine().clone();
+    for (name, item) in component.component_type().imports(&engine) {
+        let interface = name.split('@').next().unwrap_or(name);
+        if !SCHEMA_LINKER_INTERFACES.contains(&interface) {
+            stub_import(&mut linker.root(), &engine, name, item)?;
+        }
+    }
+    Ok(())
+}
+
+fn stub_import(
+    linker: &mut LinkerInstance<WasmStorage>,
+    engine: &Engine,
+    name: &str,
+    item: ComponentItem,
+) -> Result<(), Error> {
+    match item {
+        ComponentItem::ComponentFunc(_) => {
+            let qualified_name = name.to_string();
+            linker.func_new(name, move |_, _, _| {
+                Err(anyhow!(
+                    "{} is not available while reading a program's schema",
+                    qualified_name
+                ))
+            })
+        }
+        ComponentItem::ComponentInstance(instance) => {
+            let mut instance_linker = linker.instance(name)?;
+            for (export_name, export) in instance.exports(engine) {
+                stub_import(&mut instance_linker, engine, export_name, export)?;
+            }
+            Ok(())
+        }
+        ComponentItem::Resource(_) => {
+            linker.resource(name, ResourceType::host::<()>(), |_, _| Ok(()))
+        }
+        _ => Ok(()),
+    }
+}
+
 pub struct CommanderEngine(Arc<CommanderEngineInternal>);
 
 impl Default for CommanderEngine {
@@ -80,50 +228,318 @@ impl Default for CommanderEngine {
 
 pub enum ProgramSource {
     FilePath(PathBuf),
+    /// A compiled component served over HTTP(S), e.g. by a program registry. Downloads are
+    /// limited by the engine's [`DownloadConfig`].
+    Url(String),
+    /// A compiled component that is only loaded if its SHA-256 digest matches the expected one.
+    BytesWithHash(Vec<u8>, [u8; 32]),
 }
 
 impl ProgramSource {
-    fn open(&self, engine: &CommanderEngineInternal) -> Result<Component, Error> {
+    async fn open(&self, engine: &CommanderEngineInternal) -> Result<Component, Error> {
         match self {
-            ProgramSource::FilePath(path) => Component::from_file(&engine.wasm_engine, path),
+            ProgramSource::FilePath(path) => {
+                if engine.program_verifier.is_none() {
+                    return Component::from_file(&engine.wasm_engine, path);
+                }
+                let bytes = std::fs::read(path)?;
+                engine.verify_program(&bytes)?;
+                // Only binaries, as the verifier checked these exact bytes.
+                Component::from_binary(&engine.wasm_engine, &bytes)
+            }
+            ProgramSource::Url(url) => {
+                let bytes = engine.download_config.download(url).await?;
+                engine.verify_program(&bytes)?;
+                Component::from_binary(&engine.wasm_engine, &bytes)
+                    .map_err(|error| error.context(format!("{} is not a valid program", url)))
+            }
+            ProgramSource::BytesWithHash(bytes, expected_sha256) => {
+                let actual_sha256: [u8; 32] = Sha256::digest(bytes).into();
+                if actual_sha256 != *expected_sha256 {
+                    return Err(anyhow!(
+                        "Program's SHA-256 {} does not match the expected {}",
+                        to_hex(&actual_sha256),
+                        to_hex(expected_sha256)
+                    ));
+                }
+                engine.verify_program(bytes)?;
+                Component::from_binary(&engine.wasm_engine, bytes)
+            }
         }
     }
 }
 
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 impl CommanderEngine {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Starts configuring an engine. Every setting not set on the builder keeps its default.
+    pub fn builder() -> CommanderEngineBuilder {
+        CommanderEngineBuilder::default()
+    }
+
     pub async fn open_program(
         &self,
         program: ProgramSource,
     ) -> Result<CommanderStreamingProgram, Error> {
-        let component = program.open(&self.0)?;
-        Ok(CommanderStreamingProgram {
-            engine: self.0.clone(),
-            component,
-        })
+        let component = program.open(&self.0).await?;
+        Ok(CommanderStreamingProgram::new(self.0.clone(), component))
+    }
+
+    /// Like [`CommanderEngine::open_program`], but also reads and validates the program's schema,
+    /// so that a program with a malformed schema fails to load instead of failing when it is run.
+    pub async fn open_program_validated(
+        &self,
+        program: ProgramSource,
+    ) -> Result<CommanderStreamingProgram, Error> {
+        let mut program = self.open_program(program).await?;
+        program.validate_schema().await?;
+        Ok(program)
+    }
+}
+
+/// Configures a [`CommanderEngine`]. Settings can be combined freely, e.g. a host loading
+/// untrusted programs from URLs will usually set download limits, resource limits and a program
+/// verifier together.
+#[derive(Default)]
+pub struct CommanderEngineBuilder {
+    stream_capacity: DataStreamCapacity,
+    wasi_config: WasiConfig,
+    download_config: DownloadConfig,
+    resource_limits: ResourceLimits,
+    run_timeout: Option<Duration>,
+    unique_output_names: bool,
+    program_verifier: Option<Arc<ProgramVerifier>>,
+}
+
+impl CommanderEngineBuilder {
+    /// Sets the broadcast channel capacities of programs' inputs and outputs, e.g. to give a fast
+    /// list producer more headroom over a slow UI.
+    pub fn stream_capacity(mut self, stream_capacity: DataStreamCapacity) -> Self {
+        self.stream_capacity = stream_capacity;
+        self
+    }
+
+    /// Sets the clocks, random number generators and readable directories programs see, e.g. to
+    /// make a program's output reproducible in tests.
+    pub fn wasi_config(mut self, wasi_config: WasiConfig) -> Self {
+        self.wasi_config = wasi_config;
+        self
+    }
+
+    /// Sets the timeout and size limit applied when loading programs from a
+    /// [`ProgramSource::Url`].
+    pub fn download_config(mut self, download_config: DownloadConfig) -> Self {
+        self.download_config = download_config;
+        self
+    }
+
+    /// Stops programs from growing their memories, tables or instance counts past the given
+    /// limits.
+    pub fn resource_limits(mut self, resource_limits: ResourceLimits) -> Self {
+        self.resource_limits = resource_limits;
+        self
+    }
+
+    /// Interrupts programs that run for longer than `timeout`, ending their runs with
+    /// [`RunError::Timeout`]. The timeout is counted from [`StreamingRunBuilder::start`], and is
+    /// enforced to within about 10ms.
+    pub fn run_timeout(mut self, timeout: Duration) -> Self {
+        self.run_timeout = Some(timeout);
+        self
+    }
+
+    /// Makes programs fail to add an output named like another output of the same run, so that
+    /// hosts can look outputs up by name reliably. Off by default.
+    pub fn unique_output_names(mut self, unique_output_names: bool) -> Self {
+        self.unique_output_names = unique_output_names;
+        self
+    }
+
+    /// Passes every program's bytes to `verifier` before compiling them, and refuses to load the
+    /// program if it returns an error. This is where hosts that load untrusted programs can check
+    /// their publishers' signatures.
+    pub fn program_verifier(
+        mut self,
+        verifier: impl Fn(&[u8]) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.program_verifier = Some(Arc::new(verifier));
+        self
+    }
+
+    pub fn build(self) -> CommanderEngine {
+        let engine = CommanderEngineInternal {
+            stream_capacity: self.stream_capacity,
+            wasi_config: self.wasi_config,
+            download_config: self.download_config,
+            resource_limits: self.resource_limits,
+            run_timeout: self.run_timeout,
+            unique_output_names: self.unique_output_names,
+            program_verifier: self.program_verifier,
+            ..Default::default()
+        };
+        if self.run_timeout.is_some() {
+            // Advances the epoch until the engine is dropped.
+            let wasm_engine = engine.wasm_engine.weak();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(EPOCH_TICK);
+                match wasm_engine.upgrade() {
+                    Some(wasm_engine) => wasm_engine.increment_epoch(),
+                    None => break,
+                }
+            });
+        }
+        CommanderEngine(Arc::new(engine))
     }
 }
 
+/// Checks that a schema's argument names are unique and that all of its argument types parse.
+fn validate_schema(schema: &Schema) -> Result<(), Error> {
+    let mut existing_names = BTreeSet::new();
+    for argument in &schema.arguments {
+        if !existing_names.insert(&argument.name) {
+            return Err(anyhow!(
+                "Schema contains duplicate argument name: {}",
+                argument.name
+            ));
+        }
+        tooltrain_data::parse(&argument.data_type).map_err(|error| {
+            anyhow!(
+                "Argument {} has an invalid type {:?}: {}",
+                argument.name,
+                argument.data_type,
+                error
+            )
+        })?;
+    }
+    for output in &schema.declared_outputs {
+        tooltrain_data::parse(&output.data_type).map_err(|error| {
+            anyhow!(
+                "Declared output {} has an invalid type {:?}: {}",
+                output.name,
+                output.data_type,
+                error
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// A compiled program. Each call to [`CommanderStreamingProgram::run`] instantiates the program
+/// in a fresh [`Store`], so every run has its own memory, inputs, outputs and log pipes, and runs
+/// never observe each other's state. The program itself only holds the compiled component and its
+/// cached schema, so it can be cloned cheaply to start runs from several tasks at once.
+#[derive(Clone)]
 pub struct CommanderStreamingProgram {
     engine: Arc<CommanderEngineInternal>,
     component: Component,
+    schema: Option<Schema>,
+    /// The component pre-linked against the minimal schema linker, shared between clones so that
+    /// unprovided imports are only stubbed once per component.
+    schema_instance_pre: Arc<OnceLock<StreamingPluginPre<WasmStorage>>>,
 }
 
 impl CommanderStreamingProgram {
+    fn new(engine: Arc<CommanderEngineInternal>, component: Component) -> Self {
+        CommanderStreamingProgram {
+            engine,
+            component,
+            schema: None,
+            schema_instance_pre: Arc::default(),
+        }
+    }
+
+    /// Returns the program's schema. The `get-schema` export is only called the first time, as a
+    /// component's schema cannot change.
+    ///
+    /// The schema is first read from an instance linked against a minimal set of host interfaces,
+    /// which keeps the filesystem, sockets and HTTP out of `get-schema`'s reach; see
+    /// `benches/schema_linkers.rs` for how its cost compares with the full linker's. Programs whose
+    /// `get-schema` needs anything more are transparently retried with the full linker.
     pub async fn get_schema(&mut self) -> Result<inputs::Schema, Error> {
+        if let Some(schema) = &self.schema {
+            return Ok(schema.clone());
+        }
+
+        let schema = match self.get_schema_from_minimal_instance().await {
+            Ok(schema) => schema,
+            Err(minimal_error) => self.get_schema_from_full_instance().await.with_context(|| {
+                format!("Could not read the schema, after the minimal linker failed with: {minimal_error:#}")
+            })?,
+        };
+        self.schema = Some(schema.clone());
+        Ok(schema)
+    }
+
+    async fn get_schema_from_minimal_instance(&self) -> Result<inputs::Schema, Error> {
+        let instance_pre = self.schema_instance_pre()?;
+        let mut store = self.new_store()?;
+        let program = instance_pre.instantiate_async(&mut store).await?;
+        program.call_get_schema(&mut store).await
+    }
+
+    async fn get_schema_from_full_instance(&self) -> Result<inputs::Schema, Error> {
         let (mut store, program) = self.load_instance().await?;
         program.call_get_schema(&mut store).await
     }
 
+    /// Reads the schema through the minimal linker without caching it, for
+    /// `benches/schema_linkers.rs`.
+    #[cfg(feature = "schema-bench")]
+    #[doc(hidden)]
+    pub async fn read_schema_with_minimal_linker(&self) -> Result<inputs::Schema, Error> {
+        self.get_schema_from_minimal_instance().await
+    }
+
+    /// Reads the schema through the full linker without caching it, for
+    /// `benches/schema_linkers.rs`.
+    #[cfg(feature = "schema-bench")]
+    #[doc(hidden)]
+    pub async fn read_schema_with_full_linker(&self) -> Result<inputs::Schema, Error> {
+        self.get_schema_from_full_instance().await
+    }
+
+    fn schema_instance_pre(&self) -> Result<StreamingPluginPre<WasmStorage>, Error> {
+        if let Some(instance_pre) = self.schema_instance_pre.get() {
+            return Ok(instance_pre.clone());
+        }
+        let mut linker = self.engine.schema_linker.clone();
+        stub_unprovided_imports(&mut linker, &self.component)?;
+        let instance_pre = StreamingPluginPre::new(linker.instantiate_pre(&self.component)?)?;
+        Ok(self
+            .schema_instance_pre
+            .get_or_init(|| instance_pre)
+            .clone())
+    }
+
     pub async fn run(&mut self) -> Result<StreamingRunBuilder, Error> {
         StreamingRunBuilder::new(self).await
     }
 
-    async fn load_instance(&mut self) -> Result<(Store<WasmStorage>, StreamingPlugin), Error> {
-        let mut store = Store::new(&self.engine.wasm_engine, WasmStorage::new());
+    async fn validate_schema(&mut self) -> Result<(), Error> {
+        validate_schema(&self.get_schema().await?)
+    }
+
+    fn new_store(&self) -> Result<Store<WasmStorage>, Error> {
+        let mut storage =
+            WasmStorage::with_wasi_config(self.engine.stream_capacity, &self.engine.wasi_config)?;
+        storage.resource_limits = self.engine.resource_limits;
+        if self.engine.unique_output_names {
+            storage.outputs.require_unique_names();
+        }
+        let mut store = Store::new(&self.engine.wasm_engine, storage);
+        store.limiter(|storage| &mut storage.resource_limits);
+        CommanderEngineInternal::reset_deadline(self.engine.run_timeout, &mut store);
+        Ok(store)
+    }
+
+    async fn load_instance(&self) -> Result<(Store<WasmStorage>, StreamingPlugin), Error> {
+        let mut store = self.new_store()?;
         let plugin =
             StreamingPlugin::instantiate_async(&mut store, &self.component, &self.engine.linker)
                 .await?;
@@ -136,30 +552,30 @@ pub struct StreamingRunBuilder {
     store: Store<WasmStorage>,
     inputs: BTreeMap<String, Input>,
     schema: Schema,
+    run_timeout: Option<Duration>,
 }
 
 impl StreamingRunBuilder {
     pub async fn new(program: &mut CommanderStreamingProgram) -> Result<Self, Error> {
-        let (store, instance) = program.load_instance().await?;
-        let schema = program.get_schema().await?;
-
-        schema.arguments.iter().map(|a| &a.name).try_fold(
-            BTreeSet::<String>::new(),
-            |mut existing_names, name| {
-                if existing_names.contains(name) {
-                    Err(anyhow!("Schema contains duplicate argument name: {}", name))
-                } else {
-                    existing_names.insert(name.to_string());
-                    Ok(existing_names)
-                }
-            },
-        )?;
+        let (mut store, instance) = program.load_instance().await?;
+        // Read the schema from this run's own instance rather than instantiating another one.
+        let schema = match &program.schema {
+            Some(schema) => schema.clone(),
+            None => {
+                let schema = instance.call_get_schema(&mut store).await?;
+                program.schema = Some(schema.clone());
+                schema
+            }
+        };
+
+        validate_schema(&schema)?;
 
         Ok(Self {
             instance,
             store,
             inputs: BTreeMap::new(),
             schema,
+            run_timeout: program.engine.run_timeout,
         })
     }
 
@@ -177,19 +593,47 @@ impl StreamingRunBuilder {
         ValueType: Into<CommanderDataType>,
         ValueType::Value: Into<CommanderValue>,
     {
-        let inputs = Inputs(&self.store.data().inputs);
         let data_type = tooltrain_data::parse(&argument.data_type)?;
-        let input_handle = inputs.bind_input(
-            argument.name.clone(),
-            argument.description.clone(),
-            data_type,
-            to_output,
-        )?;
-        self.inputs
-            .insert(argument.name.clone(), input_handle.as_input_binding());
+        let input =
+            Self::bind_argument_input(&self.store.data().inputs, argument, data_type, to_output)?;
+        self.inputs.insert(argument.name.clone(), input);
+        Ok(self)
+    }
+
+    /// Binds `to_output` to the argument's input. Arguments that do not declare
+    /// `supports_updates` get a snapshot of the output instead, as the program only reads them
+    /// once and would never see later changes anyway.
+    fn bind_argument_input<O: OutputRef>(
+        storage: &DataStreamStorage,
+        argument: &ArgumentSpec,
+        data_type: CommanderDataType,
+        to_output: O,
+    ) -> Result<Input, Error> {
+        let inputs = Inputs(storage);
+        let name = argument.name.clone();
+        let description = argument.description.clone();
+        let input_handle = if argument.supports_updates {
+            inputs.bind_input(name, description, data_type, to_output)?
+        } else {
+            inputs.snapshot_input(name, description, data_type, to_output)?
+        };
+        Ok(input_handle.as_input_binding())
+    }
+
+    /// Binds a list-typed argument to a list output, so the program sees the output's rows as
+    /// its input. Rows added later are only seen if the argument `supports_updates`.
+    pub fn bind_list_argument(
+        mut self,
+        argument: &ArgumentSpec,
+        to_output: ListOutputRef<'_>,
+    ) -> Result<StreamingRunBuilder, Error> {
+        let input = Self::bind_list_input(&self.store.data().inputs, argument, to_output)?;
+        self.inputs.insert(argument.name.clone(), input);
         Ok(self)
     }
 
+    /// Sets the initial value of an argument. List-typed arguments take a
+    /// [`CommanderValue::List`], whose items pre-populate the list input.
     pub fn set_value_argument<ValueType>(
         mut self,
         argument: &ArgumentSpec,
@@ -200,19 +644,113 @@ impl StreamingRunBuilder {
         ValueType: Into<CommanderDataType>,
         ValueType::Value: Into<CommanderValue>,
     {
-        let inputs = Inputs(&self.store.data().inputs);
-        let data_type = tooltrain_data::parse(&argument.data_type)?;
-        let input_handle = inputs.new_value_input(
-            argument.name.clone(),
-            argument.description.clone(),
-            data_type,
+        let input = Self::new_argument_input(
+            &self.store.data().inputs,
+            argument,
             Some(initial_value.into()),
         )?;
-        self.inputs
-            .insert(argument.name.clone(), input_handle.as_input_binding());
+        self.inputs.insert(argument.name.clone(), input);
         Ok(self)
     }
 
+    fn new_argument_input(
+        storage: &DataStreamStorage,
+        argument: &ArgumentSpec,
+        initial_value: Option<CommanderValue>,
+    ) -> Result<Input, Error> {
+        let inputs = Inputs(storage);
+        let data_type = tooltrain_data::parse(&argument.data_type)?;
+        match data_type {
+            CommanderDataType::List(list_type) => {
+                let rows = match initial_value {
+                    None => vec![],
+                    Some(CommanderValue::List(rows)) => rows,
+                    Some(_) => {
+                        return Err(anyhow!(
+                            "Argument {} expects a value of type {}: not a list",
+                            argument.name,
+                            argument.data_type
+                        ))
+                    }
+                };
+                let handle = inputs.new_generic_list_input(
+                    argument.name.clone(),
+                    argument.description.clone(),
+                    list_type,
+                )?;
+                let list_input = handle.load(Inputs(storage));
+                for row in rows {
+                    list_input.add(row)?;
+                }
+                Ok(handle.as_input_binding())
+            }
+            _ => {
+                if let Some(value) = &initial_value {
+                    Self::check_argument_value(argument, &data_type, value)?;
+                }
+                Ok(inputs
+                    .new_value_input(
+                        argument.name.clone(),
+                        argument.description.clone(),
+                        data_type,
+                        initial_value,
+                    )?
+                    .as_input_binding())
+            }
+        }
+    }
+
+    fn bind_list_input(
+        storage: &DataStreamStorage,
+        argument: &ArgumentSpec,
+        to_output: ListOutputRef<'_>,
+    ) -> Result<Input, Error> {
+        let data_type = tooltrain_data::parse(&argument.data_type)?;
+        if !matches!(data_type, CommanderDataType::List(_)) {
+            return Err(anyhow!(
+                "Argument {} has type {} and cannot be bound to a list output",
+                argument.name,
+                argument.data_type
+            ));
+        }
+        Self::bind_argument_input(storage, argument, data_type, to_output)
+    }
+
+    /// Ensures `value` can be encoded as the argument's declared type, so that a value of the
+    /// wrong type is rejected here rather than surfacing as garbage inside the program.
+    fn check_argument_value(
+        argument: &ArgumentSpec,
+        data_type: &CommanderDataType,
+        value: &CommanderValue,
+    ) -> Result<(), Error> {
+        data_type.encode(value.clone()).map(|_| ()).map_err(|e| {
+            anyhow!(
+                "Argument {} expects a value of type {}: {}",
+                argument.name,
+                argument.data_type,
+                e
+            )
+        })
+    }
+
+    /// Runs the program without letting it change anything outside the engine, so a UI can
+    /// preview its outputs safely. Programs whose schema declares `performs_state_change` are
+    /// denied outgoing HTTP requests (their filesystem access is always read-only). For other
+    /// programs this does nothing.
+    pub fn dry_run(mut self) -> StreamingRunBuilder {
+        if self.schema.performs_state_change {
+            self.store.data_mut().dry_run = true;
+        }
+        self
+    }
+
+    /// Answers the program's outgoing HTTP requests from `responses` instead of the network.
+    #[cfg(any(test, feature = "mock-http"))]
+    pub fn mock_http(mut self, responses: crate::streaming::MockHttpResponses) -> Self {
+        self.store.data_mut().mock_http = Some(responses);
+        self
+    }
+
     pub fn build_arguments<F: FnOnce(Self, Schema) -> Result<Self, Error>>(
         self,
         f: F,
@@ -224,43 +762,47 @@ impl StreamingRunBuilder {
     pub fn start(self) -> Result<CommanderStreamingProgramRun, Error> {
         let Self {
             instance,
-            store,
-            mut inputs,
+            mut store,
+            inputs,
             schema,
+            run_timeout,
         } = self;
         let inputs_storage = store.data().inputs.clone();
         let outputs_storage = store.data().outputs.clone();
+        let stdout = store.data().stdout.clone();
+        let stderr = store.data().stderr.clone();
 
-        let input_storage_clone = inputs_storage.clone();
-        let full_arguments: Vec<Input> = schema
-            .arguments
-            .into_iter()
-            .map(move |arg_spec| {
-                let maybe_configured_input = inputs.remove(&arg_spec.name);
-                if let Some(configured_input) = maybe_configured_input {
-                    Ok(configured_input)
-                } else {
-                    let data_type = tooltrain_data::parse(&arg_spec.data_type)?;
-                    Ok(match data_type {
-                        CommanderDataType::List(l) => Inputs(&input_storage_clone)
-                            .new_generic_list_input(arg_spec.name, arg_spec.description, l)?
-                            .as_input_binding(),
-                        _ => Inputs(&input_storage_clone)
-                            .new_value_input(arg_spec.name, arg_spec.description, data_type, None)?
-                            .as_input_binding(),
-                    })
-                }
-            })
-            .collect::<Result<Vec<Input>, Error>>()?;
+        let full_arguments =
+            Self::inputs_in_argument_order(&inputs_storage, &schema.arguments, inputs)?;
 
+        CommanderEngineInternal::reset_deadline(run_timeout, &mut store);
         let run_result = Self::run_wrapper(store, instance, full_arguments);
         Ok(CommanderStreamingProgramRun::new(
             inputs_storage,
             outputs_storage,
+            stdout,
+            stderr,
             run_result,
         ))
     }
 
+    /// Lists the configured inputs in the order `arguments` declares them, whatever order they
+    /// were configured in, creating empty inputs for arguments that were never configured.
+    /// Programs can also look their arguments up by the name each input reports.
+    fn inputs_in_argument_order(
+        storage: &DataStreamStorage,
+        arguments: &[ArgumentSpec],
+        mut configured: BTreeMap<String, Input>,
+    ) -> Result<Vec<Input>, Error> {
+        arguments
+            .iter()
+            .map(|argument| match configured.remove(&argument.name) {
+                Some(input) => Ok(input),
+                None => Self::new_argument_input(storage, argument, None),
+            })
+            .collect()
+    }
+
     async fn run_wrapper(
         mut store: Store<WasmStorage>,
         plugin: StreamingPlugin,
@@ -270,45 +812,1417 @@ impl StreamingRunBuilder {
     }
 }
 
+type OutputEventStream = Pin<Box<dyn Stream<Item = RunEvent> + Send>>;
+
+/// Why a program run ended without producing a value.
+#[derive(Debug)]
+pub enum RunError {
+    /// The program ran to completion and returned an error.
+    Program(String),
+    /// The program trapped, or the host failed while running it.
+    Trap(Error),
+    /// The program ran for longer than its engine's [`CommanderEngineBuilder::run_timeout`].
+    Timeout,
+    /// The program tried to allocate more than its engine's [`ResourceLimits`] allow.
+    ResourceLimit(Error),
+    /// The run stopped before the program finished, for example because its task was dropped.
+    Cancelled,
+}
+
+impl RunError {
+    fn from_host_error(error: Error) -> Self {
+        if error.downcast_ref::<ResourceLimitExceeded>().is_some() {
+            return RunError::ResourceLimit(error);
+        }
+        match error.downcast_ref::<Trap>() {
+            Some(Trap::Interrupt) => RunError::Timeout,
+            _ => RunError::Trap(error),
+        }
+    }
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Program(message) => write!(f, "Program ended with an error: {}", message),
+            RunError::Trap(error) => write!(f, "Program trapped: {}", error),
+            RunError::Timeout => write!(f, "Program timed out"),
+            // The limit itself says more than the wasm backtrace it is wrapped in.
+            RunError::ResourceLimit(error) => match error.downcast_ref::<ResourceLimitExceeded>() {
+                Some(exceeded) => write!(f, "{}", exceeded),
+                None => write!(f, "{}", error),
+            },
+            RunError::Cancelled => write!(f, "Program run ended without a result"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RunError::Trap(error) | RunError::ResourceLimit(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+pub type RunResult = Result<String, RunError>;
+
+/// A single event observed while a program runs, as delivered by
+/// [`CommanderStreamingProgramRun::subscribe_all`].
+#[derive(Debug)]
+pub enum RunEvent {
+    OutputAdded(OutputHandle),
+    OutputRemoved(ResourceId),
+    ValueChanged {
+        output: ResourceId,
+        change: ValueChange,
+    },
+    ListChanged {
+        output: ResourceId,
+        change: ListChange,
+    },
+    TreeChanged {
+        output: ResourceId,
+        change: TreeChange,
+    },
+    Stdout(String),
+    Stderr(String),
+    Finished(Arc<RunResult>),
+}
+
+/// A change to which outputs a run has, or the run finishing, as delivered by
+/// [`RunLifecycle::next_event`].
+#[derive(Debug)]
+pub enum LifecycleEvent {
+    OutputAdded(OutputHandle),
+    OutputRemoved(ResourceId),
+    Finished(Arc<RunResult>),
+}
+
+/// Reports outputs being added and removed and finally the run's result, so that a host's event
+/// loop can be a single `while let Some(event) = lifecycle.next_event().await`.
+pub struct RunLifecycle {
+    existing_outputs: VecDeque<OutputHandle>,
+    output_changes: Pin<Box<dyn Stream<Item = DataStreamResourceChange> + Send>>,
+    result_reader: watch::Receiver<Option<Arc<RunResult>>>,
+    finished: bool,
+}
+
+impl RunLifecycle {
+    /// Resolves with the next event, or `None` once the run's result has been delivered.
+    /// Outputs that already existed when the lifecycle was created are reported as added first.
+    pub async fn next_event(&mut self) -> Option<LifecycleEvent> {
+        if self.finished {
+            return None;
+        }
+        if let Some(handle) = self.existing_outputs.pop_front() {
+            return Some(LifecycleEvent::OutputAdded(handle));
+        }
+        loop {
+            tokio::select! {
+                biased;
+                Some(change) = self.output_changes.next() => match change {
+                    DataStreamResourceChange::Added(metadata) => {
+                        return Some(LifecycleEvent::OutputAdded(OutputHandle::from_metadata(
+                            metadata,
+                        )));
+                    }
+                    DataStreamResourceChange::Removed(id) => {
+                        return Some(LifecycleEvent::OutputRemoved(id));
+                    }
+                    DataStreamResourceChange::DataStreamChanged(_) => {}
+                },
+                result = CommanderStreamingProgramRun::wait_for_result(&mut self.result_reader) => {
+                    self.finished = true;
+                    return Some(LifecycleEvent::Finished(result));
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CommanderStreamingProgramRun {
     inputs: DataStreamStorage,
     outputs: DataStreamStorage,
-    result_reader: watch::Receiver<Option<Arc<Result<String, Error>>>>,
+    stdout: OutputPipe,
+    stderr: OutputPipe,
+    result_reader: watch::Receiver<Option<Arc<RunResult>>>,
+    task: Arc<RunTask>,
+}
+
+/// Aborts the task running the program once every clone of its run has been dropped, so that a
+/// program that never returns does not keep its instance alive forever.
+#[derive(Debug)]
+struct RunTask(AbortHandle);
+
+impl Drop for RunTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
 }
 
 impl CommanderStreamingProgramRun {
     fn new(
         inputs: DataStreamStorage,
         outputs: DataStreamStorage,
+        stdout: OutputPipe,
+        stderr: OutputPipe,
         run_future: impl Future<Output = Result<Result<String, String>, Error>> + Send + 'static,
     ) -> Self {
         let (result_writer, result_reader) = watch::channel(None);
-        tokio::spawn(async move {
-            let result = run_future
-                .await
-                .and_then(|r| r.map_err(|e| anyhow!("Program ended with an error: {}", e)));
-            result_writer.send(Some(Arc::new(result))).unwrap();
+        let pipes = [stdout.clone(), stderr.clone()];
+        let task = tokio::spawn(async move {
+            let result = match run_future.await {
+                Ok(program_result) => program_result.map_err(RunError::Program),
+                Err(error) => Err(RunError::from_host_error(error)),
+            };
+            for pipe in pipes {
+                pipe.close();
+            }
+            // Every reader may already have been dropped, in which case nobody needs the result.
+            let _ = result_writer.send(Some(Arc::new(result)));
         });
         Self {
             inputs,
             outputs,
+            stdout,
+            stderr,
             result_reader,
+            task: Arc::new(RunTask(task.abort_handle())),
         }
     }
 
-    pub async fn get_result(&mut self) -> Arc<Result<String, Error>> {
-        if self.result_reader.borrow().is_none() {
-            self.result_reader.changed().await.unwrap();
+    /// Stops the program at its next yield point. The run's result becomes
+    /// [`RunError::Cancelled`] unless the program already finished.
+    pub fn cancel(&self) {
+        self.task.0.abort();
+    }
+
+    /// Waits for the program to finish. If the task running the program panicked or was
+    /// dropped before producing a result, the returned result is [`RunError::Cancelled`].
+    pub async fn get_result(&mut self) -> Arc<RunResult> {
+        Self::wait_for_result(&mut self.result_reader).await
+    }
+
+    async fn wait_for_result(
+        result_reader: &mut watch::Receiver<Option<Arc<RunResult>>>,
+    ) -> Arc<RunResult> {
+        match result_reader.wait_for(Option::is_some).await {
+            Ok(result) => result.clone().unwrap(),
+            Err(_) => Arc::new(Err(RunError::Cancelled)),
         }
-        self.result_reader.borrow().as_ref().unwrap().clone()
     }
 
     pub fn outputs(&self) -> Outputs<'_> {
         Outputs(&self.outputs)
     }
 
+    /// Points the argument `name` at `to_output` while the program is running, e.g. to show a
+    /// different directory in a running file explorer. The program receives the output's current
+    /// data as a replacement of the argument's value, followed by the output's later changes.
+    pub fn rebind_argument<O: OutputRef>(&self, name: &str, to_output: O) -> Result<(), Error> {
+        let id = self
+            .inputs()
+            .get_handle(name)
+            .ok_or_else(|| anyhow!("Program has no argument named {}", name))?
+            .metadata()
+            .id;
+        self.inputs
+            .change_data_stream(id, to_output.inner_data_stream()?, &to_output.source())
+    }
+
     pub fn inputs(&self) -> Inputs<'_> {
         Inputs(&self.inputs)
     }
+
+    /// Delivers every observable event of this run to `sink`, in order: outputs being added or
+    /// removed, changes to each output's data, log lines, and finally the program's result.
+    /// Outputs that already exist are reported as added before any new events.
+    pub fn subscribe_all(&self, mut sink: impl FnMut(RunEvent) + Send + 'static) -> JoinHandle<()> {
+        let outputs = self.outputs.clone();
+        let mut output_changes = BroadcastStream::new(outputs.changes()).filter_map(Result::ok);
+        let mut stdout = self.stdout_stream();
+        let mut stderr = self.stderr_stream();
+        let mut result_reader = self.result_reader.clone();
+
+        let mut data_streams: StreamMap<ResourceId, OutputEventStream> = StreamMap::new();
+        for handle in Outputs(&outputs).handles() {
+            let id = handle.metadata().id;
+            if let Ok(stream) = Self::output_event_stream(&outputs, id) {
+                data_streams.insert(id, stream);
+            }
+            sink(RunEvent::OutputAdded(handle));
+        }
+
+        tokio::spawn(async move {
+            let result = async move { Self::wait_for_result(&mut result_reader).await };
+            tokio::pin!(result);
+
+            loop {
+                tokio::select! {
+                    biased;
+                    Some((_, event)) = data_streams.next(), if !data_streams.is_empty() => sink(event),
+                    Some(change) = output_changes.next() => match change {
+                        DataStreamResourceChange::Added(metadata) => {
+                            let id = metadata.id;
+                            if let Ok(stream) = Self::output_event_stream(&outputs, id) {
+                                data_streams.insert(id, stream);
+                            }
+                            sink(RunEvent::OutputAdded(OutputHandle::from_metadata(metadata)));
+                        }
+                        DataStreamResourceChange::Removed(id) => {
+                            data_streams.remove(&id);
+                            sink(RunEvent::OutputRemoved(id));
+                        }
+                        DataStreamResourceChange::DataStreamChanged(_) => {}
+                    },
+                    Some(line) = stdout.next() => sink(RunEvent::Stdout(line)),
+                    Some(line) = stderr.next() => sink(RunEvent::Stderr(line)),
+                    result = &mut result => {
+                        // Everything the program emitted was broadcast before it returned, so
+                        // deliver whatever is still buffered before reporting completion.
+                        while let Some(Some((_, event))) = data_streams.next().now_or_never() {
+                            sink(event);
+                        }
+                        while let Some(Some(line)) = stdout.next().now_or_never() {
+                            sink(RunEvent::Stdout(line));
+                        }
+                        while let Some(Some(line)) = stderr.next().now_or_never() {
+                            sink(RunEvent::Stderr(line));
+                        }
+                        sink(RunEvent::Finished(result));
+                        break;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Follows the run's outputs being added and removed until it finishes. Unlike
+    /// [`CommanderStreamingProgramRun::subscribe_all`], events are pulled by the caller rather
+    /// than pushed to a callback.
+    pub fn lifecycle(&self) -> RunLifecycle {
+        let output_changes = BroadcastStream::new(self.outputs.changes()).filter_map(Result::ok);
+        RunLifecycle {
+            existing_outputs: Outputs(&self.outputs).handles().into(),
+            output_changes: Box::pin(output_changes),
+            result_reader: self.result_reader.clone(),
+            finished: false,
+        }
+    }
+
+    fn output_event_stream(
+        storage: &DataStreamStorage,
+        id: ResourceId,
+    ) -> Result<OutputEventStream, Error> {
+        let resource = storage.get(id)?;
+        let stream = resource.stream.read();
+        Ok(match &*stream {
+            DataStream::Value(v) => Box::pin(
+                BroadcastStream::new(v.subscribe())
+                    .filter_map(Result::ok)
+                    .map(move |change| RunEvent::ValueChanged { output: id, change }),
+            ),
+            DataStream::List(l) => Box::pin(
+                BroadcastStream::new(l.subscribe())
+                    .filter_map(Result::ok)
+                    .map(move |change| RunEvent::ListChanged { output: id, change }),
+            ),
+            DataStream::Tree(t) => Box::pin(
+                BroadcastStream::new(t.subscribe())
+                    .filter_map(Result::ok)
+                    .map(move |change| RunEvent::TreeChanged { output: id, change }),
+            ),
+        })
+    }
+
+    /// Lines the plugin writes to stdout after this call.
+    pub fn stdout_stream(&self) -> impl Stream<Item = String> {
+        BroadcastStream::new(self.stdout.subscribe()).filter_map(Result::ok)
+    }
+
+    /// Lines the plugin writes to stderr after this call.
+    pub fn stderr_stream(&self) -> impl Stream<Item = String> {
+        BroadcastStream::new(self.stderr.subscribe()).filter_map(Result::ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        datastream::{DataStreamSnapshot, ListStream},
+        mock_program::{
+            mock_argument_names_component_wat, mock_component_wat, mock_failing_component_wat,
+            mock_http_component_wat, mock_program_source, mock_program_wasm, mock_schema,
+            mock_stderr_component_wat,
+        },
+        streaming::InputHandle,
+    };
+    use parking_lot::{Mutex, RwLock};
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use tokio::sync::Notify;
+    use tooltrain_data::{
+        CommanderListDataType, CommanderNumberDataType, CommanderTypedListDataType,
+    };
+
+    #[test]
+    fn checks_argument_values_against_schema_type() {
+        let argument = ArgumentSpec {
+            name: "root".to_string(),
+            description: "The root directory".to_string(),
+            data_type: "path".to_string(),
+            supports_updates: false,
+        };
+        let data_type = tooltrain_data::parse(&argument.data_type).unwrap();
+
+        StreamingRunBuilder::check_argument_value(
+            &argument,
+            &data_type,
+            &CommanderValue::Path(PathBuf::from("/tmp")),
+        )
+        .unwrap();
+
+        let error = StreamingRunBuilder::check_argument_value(
+            &argument,
+            &data_type,
+            &CommanderValue::String("/tmp".to_string()),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Argument root expects a value of type path"));
+    }
+
+    fn list_input_rows(inputs: &DataStreamStorage, name: &str) -> Vec<CommanderValue> {
+        let Some(InputHandle::List(handle)) = Inputs(inputs).get_handle(name) else {
+            panic!("Expected a list input handle");
+        };
+        let Some(DataStreamSnapshot::List(rows)) =
+            Inputs(inputs).values().remove(&handle.metadata.id)
+        else {
+            panic!("Expected a list snapshot");
+        };
+        rows.iter().map(|row| (**row).clone()).collect()
+    }
+
+    #[test]
+    fn binds_list_output_to_list_argument() {
+        let argument = ArgumentSpec {
+            name: "rows".to_string(),
+            description: "Rows to process".to_string(),
+            data_type: "list<number>".to_string(),
+            supports_updates: true,
+        };
+        let outputs = DataStreamStorage::default();
+        let output_id = outputs
+            .add(
+                "Rows".to_string(),
+                "Some rows".to_string(),
+                CommanderNumberDataType {}.into(),
+                outputs.new_list_stream(),
+            )
+            .unwrap();
+        let add_row = |row: f64| {
+            outputs
+                .get(output_id)
+                .unwrap()
+                .stream
+                .write()
+                .try_get_list_mut()
+                .unwrap()
+                .add(CommanderValue::Number(row))
+                .unwrap();
+        };
+        add_row(1.0);
+
+        let Some(OutputHandle::List(handle)) = Outputs(&outputs).get_handle("Rows") else {
+            panic!("Expected a list output handle");
+        };
+        let inputs = DataStreamStorage::default();
+        let input = StreamingRunBuilder::bind_list_input(
+            &inputs,
+            &argument,
+            handle.load(Outputs(&outputs)),
+        )
+        .unwrap();
+        assert!(matches!(input, Input::ListInput(_)));
+        add_row(2.0);
+
+        assert_eq!(
+            list_input_rows(&inputs, "rows"),
+            vec![CommanderValue::Number(1.0), CommanderValue::Number(2.0)]
+        );
+
+        let value_argument = ArgumentSpec {
+            name: "count".to_string(),
+            description: "A count".to_string(),
+            data_type: "number".to_string(),
+            supports_updates: false,
+        };
+        assert!(StreamingRunBuilder::bind_list_input(
+            &inputs,
+            &value_argument,
+            handle.load(Outputs(&outputs)),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn only_updating_arguments_follow_their_output() {
+        let outputs = DataStreamStorage::default();
+        let output_id = outputs
+            .add(
+                "Count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {}.into(),
+                outputs.new_value_stream(Some(CommanderValue::Number(1.0))),
+            )
+            .unwrap();
+        let Some(OutputHandle::Value(handle)) = Outputs(&outputs).get_handle("Count") else {
+            panic!("Expected a value output handle");
+        };
+
+        let inputs = DataStreamStorage::default();
+        for (name, supports_updates) in [("live", true), ("once", false)] {
+            let argument = ArgumentSpec {
+                name: name.to_string(),
+                description: "A count".to_string(),
+                data_type: "number".to_string(),
+                supports_updates,
+            };
+            StreamingRunBuilder::bind_argument_input(
+                &inputs,
+                &argument,
+                CommanderNumberDataType {}.into(),
+                handle.load(Outputs(&outputs)),
+            )
+            .unwrap();
+        }
+
+        outputs
+            .get(output_id)
+            .unwrap()
+            .stream
+            .write()
+            .try_get_value_mut()
+            .unwrap()
+            .set(CommanderValue::Number(2.0))
+            .unwrap();
+
+        let input_value = |name: &str| {
+            let Some(InputHandle::Value(handle)) = Inputs(&inputs).get_handle(name) else {
+                panic!("Expected a value input handle");
+            };
+            let Some(DataStreamSnapshot::Value(Some(value))) =
+                Inputs(&inputs).values().remove(&handle.metadata.id)
+            else {
+                panic!("Expected a value snapshot");
+            };
+            (*value).clone()
+        };
+        assert_eq!(input_value("live"), CommanderValue::Number(2.0));
+        assert_eq!(input_value("once"), CommanderValue::Number(1.0));
+    }
+
+    #[test]
+    fn prepopulates_list_arguments() {
+        let argument = ArgumentSpec {
+            name: "rows".to_string(),
+            description: "Rows to process".to_string(),
+            data_type: "list<number>".to_string(),
+            supports_updates: true,
+        };
+        let inputs = DataStreamStorage::default();
+        let input = StreamingRunBuilder::new_argument_input(
+            &inputs,
+            &argument,
+            Some(CommanderValue::List(vec![CommanderValue::Number(3.0)])),
+        )
+        .unwrap();
+        assert!(matches!(input, Input::ListInput(_)));
+        assert_eq!(
+            list_input_rows(&inputs, "rows"),
+            vec![CommanderValue::Number(3.0)]
+        );
+
+        let other_inputs = DataStreamStorage::default();
+        assert!(StreamingRunBuilder::new_argument_input(
+            &other_inputs,
+            &argument,
+            Some(CommanderValue::Number(3.0)),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn passes_inputs_in_argument_order() {
+        let argument = |name: &str| ArgumentSpec {
+            name: name.to_string(),
+            description: String::new(),
+            data_type: "number".to_string(),
+            supports_updates: false,
+        };
+        let arguments = vec![argument("width"), argument("height"), argument("depth")];
+        let storage = DataStreamStorage::default();
+
+        // Configure the arguments in the reverse of their declared order, leaving one unset.
+        let mut configured = BTreeMap::new();
+        for argument in arguments[1..].iter().rev() {
+            let input = StreamingRunBuilder::new_argument_input(
+                &storage,
+                argument,
+                Some(CommanderValue::Number(1.0)),
+            )
+            .unwrap();
+            configured.insert(argument.name.clone(), input);
+        }
+
+        let names: Vec<String> =
+            StreamingRunBuilder::inputs_in_argument_order(&storage, &arguments, configured)
+                .unwrap()
+                .into_iter()
+                .map(|input| {
+                    let Input::ValueInput(resource) = input else {
+                        panic!("Expected a value input");
+                    };
+                    storage.get(resource.rep()).unwrap().metadata.name.clone()
+                })
+                .collect();
+        assert_eq!(names, vec!["width", "height", "depth"]);
+    }
+
+    #[tokio::test]
+    async fn programs_read_the_names_of_their_inputs() {
+        let argument = |name: &str| ArgumentSpec {
+            name: name.to_string(),
+            description: String::new(),
+            data_type: "number".to_string(),
+            supports_updates: false,
+        };
+        let schema = Schema {
+            arguments: vec![argument("width"), argument("height")],
+            ..mock_schema()
+        };
+        let engine = CommanderEngine::new();
+        let wat = mock_argument_names_component_wat(&schema);
+        let component = Component::new(&engine.0.wasm_engine, wat).unwrap();
+
+        for names in [["width", "height"], ["height", "width"]] {
+            let mut program = CommanderStreamingProgram::new(engine.0.clone(), component.clone());
+            let mut run = program.run().await.unwrap();
+            for name in names {
+                run = run
+                    .set_value_argument::<CommanderNumberDataType>(&argument(name), 1.0)
+                    .unwrap();
+            }
+            let result = run.start().unwrap().get_result().await;
+            assert_eq!(result.as_ref().as_ref().unwrap(), "width,height");
+        }
+    }
+
+    #[tokio::test]
+    async fn captures_the_last_line_a_program_prints() {
+        let engine = CommanderEngine::new();
+        let wat = mock_stderr_component_wat("Listing /a\nDirectory does not exist: /b");
+        let mut program = CommanderStreamingProgram::new(
+            engine.0.clone(),
+            Component::new(&engine.0.wasm_engine, wat).unwrap(),
+        );
+        let mut run = program.run().await.unwrap().start().unwrap();
+        let mut stderr = Box::pin(run.stderr_stream());
+
+        assert_eq!(run.get_result().await.as_ref().as_ref().unwrap(), "Mock");
+        let mut lines = vec![];
+        while let Some(Some(line)) = stderr.next().now_or_never() {
+            lines.push(line);
+        }
+        assert_eq!(lines, vec!["Listing /a", "Directory does not exist: /b"]);
+    }
+
+    #[tokio::test]
+    async fn get_result_reports_a_lost_run() {
+        let mut run = CommanderStreamingProgramRun::new(
+            DataStreamStorage::default(),
+            DataStreamStorage::default(),
+            OutputPipe::default(),
+            OutputPipe::default(),
+            async { panic!("The run task died") },
+        );
+        let result = run.get_result().await;
+        assert!(matches!(result.as_ref(), Err(RunError::Cancelled)));
+        assert_eq!(
+            result.as_ref().as_ref().unwrap_err().to_string(),
+            "Program run ended without a result"
+        );
+    }
+
+    #[tokio::test]
+    async fn rebinding_an_argument_replaces_the_programs_input() {
+        use crate::bindings::streaming::tooltrain::base::streaming_inputs::{
+            HostValueChangeStream, HostValueInput,
+        };
+        use wasmtime::component::Resource;
+
+        // The test plays the program, reading its input through the host bindings.
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let input = Inputs(&storage.inputs)
+            .new_value_input(
+                "count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {},
+                Some(1.0),
+            )
+            .unwrap();
+        let change_stream = HostValueInput::get_change_stream(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(input.metadata.id),
+        )
+        .await
+        .unwrap();
+        let run = CommanderStreamingProgramRun::new(
+            storage.inputs.clone(),
+            storage.outputs.clone(),
+            OutputPipe::default(),
+            OutputPipe::default(),
+            std::future::pending(),
+        );
+
+        let other_program = DataStreamStorage::default();
+        other_program
+            .add(
+                "total".to_string(),
+                "Another program's total".to_string(),
+                CommanderNumberDataType {}.into(),
+                other_program.new_value_stream(Some(CommanderValue::Number(7.0))),
+            )
+            .unwrap();
+        let Some(OutputHandle::Value(total)) = Outputs(&other_program).get_handle("total") else {
+            panic!("Expected a value output handle");
+        };
+        run.rebind_argument("count", total.load(Outputs(&other_program)))
+            .unwrap();
+
+        let Some(value) = HostValueChangeStream::poll_change_blocking(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(change_stream.rep()),
+        )
+        .await
+        .unwrap() else {
+            panic!("Expected the rebound value");
+        };
+        assert_eq!(CommanderNumberDataType {}.decode(&value).unwrap(), 7.0);
+        assert!(run
+            .rebind_argument("missing", total.load(Outputs(&other_program)))
+            .is_err());
+    }
+
+    async fn run_wat(engine: &CommanderEngine, wat: String) -> Arc<RunResult> {
+        let mut program = CommanderStreamingProgram::new(
+            engine.0.clone(),
+            Component::new(&engine.0.wasm_engine, wat).unwrap(),
+        );
+        program
+            .run()
+            .await
+            .unwrap()
+            .start()
+            .unwrap()
+            .get_result()
+            .await
+    }
+
+    #[tokio::test]
+    async fn distinguishes_program_errors_from_traps() {
+        let engine = CommanderEngine::new();
+        let result = run_wat(&engine, mock_failing_component_wat("bad input")).await;
+        let Err(RunError::Program(message)) = result.as_ref() else {
+            panic!("Expected a program error, got {:?}", result);
+        };
+        assert_eq!(message, "bad input");
+
+        let result = run_wat(&engine, mock_component_wat(false, "unreachable")).await;
+        let Err(RunError::Trap(error)) = result.as_ref() else {
+            panic!("Expected a trap, got {:?}", result);
+        };
+        assert_eq!(
+            error.downcast_ref::<Trap>(),
+            Some(&Trap::UnreachableCodeReached)
+        );
+    }
+
+    #[tokio::test]
+    async fn times_out_programs_that_run_too_long() {
+        let engine = CommanderEngine::builder()
+            .run_timeout(Duration::from_millis(100))
+            .build();
+        let spinning = mock_component_wat(false, "(loop $spin (br $spin))");
+        let result = run_wat(&engine, spinning).await;
+        assert!(matches!(result.as_ref(), Err(RunError::Timeout)));
+
+        // The timeout counts from the start of the run, not from when it was instantiated.
+        let mut program = CommanderStreamingProgram::new(
+            engine.0.clone(),
+            Component::new(&engine.0.wasm_engine, mock_component_wat(false, "")).unwrap(),
+        );
+        let run = program.run().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let result = run.start().unwrap().get_result().await;
+        assert_eq!(result.as_ref().as_ref().unwrap(), "Mock");
+    }
+
+    /// Builds a component with the streaming plugin's exports whose `get-schema` returns a schema
+    /// named "Mock". When `uses_sockets` is set, `get-schema` first calls into `wasi:sockets`,
+    /// which only the full linker provides.
+    fn mock_schema_component(engine: &CommanderEngine, uses_sockets: bool) -> Component {
+        Component::from_binary(&engine.0.wasm_engine, &mock_schema_wasm(uses_sockets)).unwrap()
+    }
+
+    fn mock_schema_wasm(uses_sockets: bool) -> Vec<u8> {
+        wat::parse_str(mock_component_wat(uses_sockets, "")).unwrap()
+    }
+
+    fn mock_program(engine: &CommanderEngine, schema: &Schema) -> CommanderStreamingProgram {
+        let component =
+            Component::from_binary(&engine.0.wasm_engine, &mock_program_wasm(schema)).unwrap();
+        CommanderStreamingProgram::new(engine.0.clone(), component)
+    }
+
+    /// Serves `program` at `/program.wasm` over plain HTTP, answering every other path with a 404.
+    /// When `chunked` is set, responses are sent with chunked transfer encoding, so clients cannot
+    /// know their size up front.
+    async fn serve_program(program: Vec<u8>, chunked: bool) -> std::net::SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut connection, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match connection.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let body: &[u8] = if request.starts_with(b"GET /program.wasm ") {
+                    &program
+                } else {
+                    &[]
+                };
+                let status = if body.is_empty() {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                if !chunked {
+                    let head = format!(
+                        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let _ = connection.write_all(head.as_bytes()).await;
+                    let _ = connection.write_all(body).await;
+                    continue;
+                }
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+                );
+                let _ = connection.write_all(head.as_bytes()).await;
+                for chunk in body.chunks(64) {
+                    let _ = connection
+                        .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                        .await;
+                    let _ = connection.write_all(chunk).await;
+                    let _ = connection.write_all(b"\r\n").await;
+                }
+                let _ = connection.write_all(b"0\r\n\r\n").await;
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn opens_programs_from_urls() {
+        let address = serve_program(mock_schema_wasm(false), false).await;
+        let engine = CommanderEngine::new();
+        let mut program = engine
+            .open_program(ProgramSource::Url(format!("http://{address}/program.wasm")))
+            .await
+            .unwrap();
+        assert_eq!(program.get_schema().await.unwrap().name, "Mock");
+
+        let error = engine
+            .open_program(ProgramSource::Url(format!("http://{address}/missing.wasm")))
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error).contains("404 Not Found"));
+
+        let error = engine
+            .open_program(ProgramSource::Url(
+                "ftp://example.com/program.wasm".to_string(),
+            ))
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error).contains("Only http and https URLs are supported"));
+
+        let limited = CommanderEngine::builder()
+            .download_config(DownloadConfig {
+                max_bytes: 16,
+                ..Default::default()
+            })
+            .build();
+        let error = limited
+            .open_program(ProgramSource::Url(format!("http://{address}/program.wasm")))
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error).contains("larger than the 16 byte limit"));
+    }
+
+    #[tokio::test]
+    async fn limits_programs_downloaded_without_a_content_length() {
+        let wasm = mock_schema_wasm(false);
+        let address = serve_program(wasm.clone(), true).await;
+        let url = format!("http://{address}/program.wasm");
+        let fits = CommanderEngine::builder()
+            .download_config(DownloadConfig {
+                max_bytes: wasm.len() as u64,
+                ..Default::default()
+            })
+            .build();
+        let mut program = fits
+            .open_program(ProgramSource::Url(url.clone()))
+            .await
+            .unwrap();
+        assert_eq!(program.get_schema().await.unwrap().name, "Mock");
+
+        // Only the streamed length can exceed the limit, after some chunks have been read.
+        let too_small = CommanderEngine::builder()
+            .download_config(DownloadConfig {
+                max_bytes: wasm.len() as u64 - 1,
+                ..Default::default()
+            })
+            .build();
+        let error = too_small
+            .open_program(ProgramSource::Url(url))
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error)
+            .contains(&format!("larger than the {} byte limit", wasm.len() - 1)));
+    }
+
+    #[test]
+    fn builder_combines_settings() {
+        let stream_capacity = DataStreamCapacity {
+            list_updates: 4096,
+            ..Default::default()
+        };
+        let download_config = DownloadConfig {
+            max_bytes: 1024,
+            ..Default::default()
+        };
+        let resource_limits = ResourceLimits {
+            max_memory_bytes: Some(1 << 20),
+            ..Default::default()
+        };
+        let engine = CommanderEngine::builder()
+            .stream_capacity(stream_capacity)
+            .download_config(download_config)
+            .resource_limits(resource_limits)
+            .unique_output_names(true)
+            .program_verifier(|_| Ok(()))
+            .build();
+
+        assert_eq!(engine.0.stream_capacity, stream_capacity);
+        assert_eq!(engine.0.wasi_config, WasiConfig::default());
+        assert_eq!(engine.0.download_config, download_config);
+        assert_eq!(engine.0.resource_limits, resource_limits);
+        assert!(engine.0.unique_output_names);
+        assert!(engine.0.program_verifier.is_some());
+    }
+
+    #[tokio::test]
+    async fn verifies_program_hashes() {
+        let wasm = mock_schema_wasm(false);
+        let sha256: [u8; 32] = Sha256::digest(&wasm).into();
+        let engine = CommanderEngine::new();
+        let mut program = engine
+            .open_program(ProgramSource::BytesWithHash(wasm.clone(), sha256))
+            .await
+            .unwrap();
+        assert_eq!(program.get_schema().await.unwrap().name, "Mock");
+
+        let mut tampered = wasm.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let error = engine
+            .open_program(ProgramSource::BytesWithHash(tampered, sha256))
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("does not match the expected"));
+
+        let verifier_calls = Arc::new(AtomicUsize::new(0));
+        let rejecting = CommanderEngine::builder()
+            .program_verifier({
+                let verifier_calls = verifier_calls.clone();
+                move |_| {
+                    verifier_calls.fetch_add(1, Ordering::SeqCst);
+                    Err(anyhow!("Unsigned"))
+                }
+            })
+            .build();
+        let error = rejecting
+            .open_program(ProgramSource::BytesWithHash(wasm, sha256))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(format!("{:#}", error), "Program was rejected: Unsigned");
+        assert_eq!(verifier_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn verifies_programs_loaded_from_files() {
+        let directory =
+            std::env::temp_dir().join(format!("tooltrain-verified-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let binary = directory.join("mock.wasm");
+        let text = directory.join("mock.wat");
+        std::fs::write(&binary, mock_schema_wasm(false)).unwrap();
+        std::fs::write(&text, mock_component_wat(false, "")).unwrap();
+        let engine = CommanderEngine::builder()
+            .program_verifier(|_| Ok(()))
+            .build();
+
+        let binary_program = engine.open_program(ProgramSource::FilePath(binary)).await;
+        let text_program = engine.open_program(ProgramSource::FilePath(text)).await;
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            binary_program.unwrap().get_schema().await.unwrap().name,
+            "Mock"
+        );
+        assert!(text_program.is_err());
+    }
+
+    #[tokio::test]
+    async fn stops_programs_that_exceed_resource_limits() {
+        let engine = CommanderEngine::builder()
+            .resource_limits(ResourceLimits {
+                max_memory_bytes: Some(1 << 20),
+                ..Default::default()
+            })
+            .build();
+        let run_growing_memory_by = |pages: u32| {
+            let wat =
+                mock_component_wat(false, &format!("(drop (memory.grow (i32.const {pages})))"));
+            let mut program = CommanderStreamingProgram::new(
+                engine.0.clone(),
+                Component::new(&engine.0.wasm_engine, wat).unwrap(),
+            );
+            async move { program.run().await?.start() }
+        };
+
+        // 64KiB pages, so 8 more stay under the 1MiB cap and 32 more exceed it.
+        let result = run_growing_memory_by(8).await.unwrap().get_result().await;
+        assert_eq!(result.as_ref().as_ref().unwrap(), "Mock");
+
+        let result = run_growing_memory_by(32).await.unwrap().get_result().await;
+        let Err(RunError::ResourceLimit(_)) = result.as_ref() else {
+            panic!("Expected a resource limit error, got {:?}", result);
+        };
+        assert_eq!(
+            result.as_ref().as_ref().unwrap_err().to_string(),
+            "Program tried to grow a memory to 2162688 bytes, over the limit of 1048576"
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_mocked_http_responses_to_programs() {
+        let engine = CommanderEngine::new();
+        let wat = mock_http_component_wat(
+            &mock_schema(),
+            "example.social",
+            "/api/v1/timelines/public?limit=2",
+        );
+        let mut program = CommanderStreamingProgram::new(
+            engine.0.clone(),
+            Component::new(&engine.0.wasm_engine, wat).unwrap(),
+        );
+        let body = r#"[{"id":"1"},{"id":"2"}]"#;
+        let responses = [(
+            "/api/v1/timelines/public?limit=2".to_string(),
+            (200, body.as_bytes().to_vec()),
+        )];
+
+        let result = program
+            .run()
+            .await
+            .unwrap()
+            .mock_http(responses.into())
+            .start()
+            .unwrap()
+            .get_result()
+            .await;
+        assert_eq!(result.as_ref().as_ref().unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn dry_runs_deny_requests_from_state_changing_programs() {
+        let engine = CommanderEngine::new();
+        let body = r#"[{"id":"1"}]"#;
+        let dry_run_with_state_change = |performs_state_change: bool| {
+            let schema = Schema {
+                performs_state_change,
+                ..mock_schema()
+            };
+            let wat = mock_http_component_wat(&schema, "example.social", "/api/v1/statuses");
+            let mut program = CommanderStreamingProgram::new(
+                engine.0.clone(),
+                Component::new(&engine.0.wasm_engine, wat).unwrap(),
+            );
+            let responses = [(
+                "/api/v1/statuses".to_string(),
+                (200, body.as_bytes().to_vec()),
+            )];
+            async move {
+                let run = program.run().await.unwrap().mock_http(responses.into());
+                run.dry_run().start().unwrap().get_result().await
+            }
+        };
+
+        let result = dry_run_with_state_change(true).await;
+        let Err(RunError::Program(message)) = result.as_ref() else {
+            panic!("Expected the request to fail, got {:?}", result);
+        };
+        // HTTP-request-denied is case 15 of wasi:http's error-code.
+        assert_eq!(message, "HTTP error-code 15");
+
+        let result = dry_run_with_state_change(false).await;
+        assert_eq!(result.as_ref().as_ref().unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn reads_schemas_with_the_minimal_linker() {
+        let engine = CommanderEngine::new();
+        let program =
+            CommanderStreamingProgram::new(engine.0.clone(), mock_schema_component(&engine, false));
+        let schema = program.get_schema_from_minimal_instance().await.unwrap();
+        assert_eq!(schema.name, "Mock");
+        assert_eq!(schema.version, "0.1.0");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_full_linker_for_schemas() {
+        let engine = CommanderEngine::new();
+        let mut program =
+            CommanderStreamingProgram::new(engine.0.clone(), mock_schema_component(&engine, true));
+        assert!(program.get_schema_from_minimal_instance().await.is_err());
+        assert_eq!(program.get_schema().await.unwrap().name, "Mock");
+    }
+
+    #[tokio::test]
+    async fn links_the_schema_instance_once_per_component() {
+        let engine = CommanderEngine::new();
+        let program =
+            CommanderStreamingProgram::new(engine.0.clone(), mock_schema_component(&engine, false));
+        let mut other_program = program.clone();
+        assert_eq!(other_program.get_schema().await.unwrap().name, "Mock");
+        assert!(program.schema_instance_pre.get().is_some());
+        let schema = program.get_schema_from_minimal_instance().await.unwrap();
+        assert_eq!(schema.name, "Mock");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_minimal_linker_error_when_both_linkers_fail() {
+        let engine = CommanderEngine::new();
+        let component = Component::new(&engine.0.wasm_engine, "(component)").unwrap();
+        let mut program = CommanderStreamingProgram::new(engine.0.clone(), component);
+        let error = format!("{:#}", program.get_schema().await.unwrap_err());
+        assert!(
+            error.starts_with("Could not read the schema, after the minimal linker failed with:")
+        );
+    }
+
+    #[tokio::test]
+    async fn get_schema_reuses_cached_schema() {
+        let engine = CommanderEngine::new();
+        let mut program = mock_program(&engine, &mock_schema());
+        assert_eq!(program.get_schema().await.unwrap().name, "Mock");
+
+        // An empty component has no `get-schema` export, so calling it again would fail.
+        program.component = Component::new(&engine.0.wasm_engine, "(component)").unwrap();
+        program.schema_instance_pre = Arc::default();
+        assert_eq!(program.get_schema().await.unwrap().name, "Mock");
+    }
+
+    #[tokio::test]
+    async fn validating_rejects_malformed_schemas() {
+        let engine = CommanderEngine::new();
+        let argument = |name: &str, data_type: &str| ArgumentSpec {
+            name: name.to_string(),
+            description: "An argument".to_string(),
+            data_type: data_type.to_string(),
+            supports_updates: false,
+        };
+        let open_with_arguments = |arguments: Vec<ArgumentSpec>| {
+            engine.open_program_validated(mock_program_source(&Schema {
+                arguments,
+                ..mock_schema()
+            }))
+        };
+
+        let mut program =
+            open_with_arguments(vec![argument("path", "path"), argument("count", "number")])
+                .await
+                .unwrap();
+        let arguments = program.get_schema().await.unwrap().arguments;
+        assert_eq!(arguments[1].name, "count");
+        assert_eq!(arguments[1].data_type, "number");
+
+        let error = open_with_arguments(vec![
+            argument("path", "path"),
+            argument("count", "number"),
+            argument("path", "string"),
+        ])
+        .await
+        .err()
+        .unwrap();
+        assert!(error.to_string().contains("duplicate argument name: path"));
+
+        let error = open_with_arguments(vec![argument("count", "numbr")])
+            .await
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Argument count has an invalid type"));
+
+        // Loading fails up front when the schema cannot be read at all.
+        let missing = PathBuf::from("/nonexistent/program.wasm");
+        assert!(engine
+            .open_program_validated(ProgramSource::FilePath(missing))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn runs_of_one_program_do_not_share_streams() {
+        let engine = CommanderEngine::builder().unique_output_names(true).build();
+        let program = CommanderStreamingProgram::new(
+            engine.0.clone(),
+            Component::new(&engine.0.wasm_engine, "(component)").unwrap(),
+        );
+        let other_program = program.clone();
+        let first_run = program.new_store().unwrap();
+        let second_run = other_program.new_store().unwrap();
+
+        let add_output = |store: &Store<WasmStorage>| {
+            let outputs = &store.data().outputs;
+            outputs.add(
+                "Rows".to_string(),
+                "Some rows".to_string(),
+                CommanderNumberDataType {}.into(),
+                outputs.new_list_stream(),
+            )
+        };
+        add_output(&first_run).unwrap();
+        assert!(Outputs(&second_run.data().outputs).handles().is_empty());
+        assert!(Inputs(&second_run.data().inputs).handles().is_empty());
+        // Both runs can create an output with the same name, as names are scoped to a run.
+        add_output(&second_run).unwrap();
+        assert_eq!(Outputs(&first_run.data().outputs).handles().len(), 1);
+        assert!(add_output(&first_run).is_err());
+
+        // Only outputs are looked up by name, so inputs may still share theirs.
+        let inputs = Inputs(&first_run.data().inputs);
+        for _ in 0..2 {
+            inputs
+                .new_value_input(
+                    "count".to_string(),
+                    "A count".to_string(),
+                    CommanderNumberDataType {},
+                    None,
+                )
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_all_delivers_outputs_rows_and_completion() {
+        let outputs = DataStreamStorage::default();
+        let start = Arc::new(Notify::new());
+
+        let run_outputs = outputs.clone();
+        let run_start = start.clone();
+        let run = CommanderStreamingProgramRun::new(
+            DataStreamStorage::default(),
+            outputs,
+            OutputPipe::default(),
+            OutputPipe::default(),
+            async move {
+                run_start.notified().await;
+                let id = run_outputs.add(
+                    "Files".to_string(),
+                    "The list of files".to_string(),
+                    CommanderDataType::List(CommanderListDataType::Number(
+                        CommanderTypedListDataType::new(CommanderNumberDataType {}),
+                    )),
+                    Arc::new(RwLock::new(DataStream::List(ListStream::default()))),
+                )?;
+                tokio::task::yield_now().await;
+                for row in 0..3 {
+                    run_outputs
+                        .get(id)?
+                        .stream
+                        .write()
+                        .try_get_list_mut()?
+                        .add(CommanderValue::Number(row as f64))?;
+                }
+                Ok(Ok("Done".to_string()))
+            },
+        );
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink_events = events.clone();
+        let subscription = run.subscribe_all(move |event| sink_events.lock().push(event));
+        start.notify_one();
+        subscription.await.unwrap();
+
+        let events = events.lock();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(
+            events[0],
+            RunEvent::OutputAdded(OutputHandle::List(_))
+        ));
+        for event in &events[1..4] {
+            assert!(matches!(
+                event,
+                RunEvent::ListChanged {
+                    change: ListChange::Add(_),
+                    ..
+                }
+            ));
+        }
+        let RunEvent::Finished(result) = &events[4] else {
+            panic!("Expected the run to finish last");
+        };
+        assert_eq!(result.as_ref().as_ref().unwrap(), "Done");
+    }
+
+    #[tokio::test]
+    async fn lifecycle_reports_outputs_then_completion() {
+        let outputs = DataStreamStorage::default();
+        outputs
+            .add(
+                "Status".to_string(),
+                "The current status".to_string(),
+                CommanderNumberDataType {}.into(),
+                outputs.new_value_stream(None),
+            )
+            .unwrap();
+        let start = Arc::new(Notify::new());
+
+        let run_outputs = outputs.clone();
+        let run_start = start.clone();
+        let run = CommanderStreamingProgramRun::new(
+            DataStreamStorage::default(),
+            outputs,
+            OutputPipe::default(),
+            OutputPipe::default(),
+            async move {
+                run_start.notified().await;
+                let id = run_outputs.add(
+                    "Scratch".to_string(),
+                    "A temporary list".to_string(),
+                    CommanderDataType::List(CommanderListDataType::Number(
+                        CommanderTypedListDataType::new(CommanderNumberDataType {}),
+                    )),
+                    run_outputs.new_list_stream(),
+                )?;
+                run_outputs.remove(id)?;
+                Ok(Ok("Done".to_string()))
+            },
+        );
+
+        let mut lifecycle = run.lifecycle();
+        start.notify_one();
+        let mut events = vec![];
+        while let Some(event) = lifecycle.next_event().await {
+            events.push(event);
+        }
+
+        assert_eq!(events.len(), 4);
+        let LifecycleEvent::OutputAdded(OutputHandle::Value(status)) = &events[0] else {
+            panic!("Expected the existing output first, got {:?}", events[0]);
+        };
+        assert_eq!(status.metadata.name, "Status");
+        let LifecycleEvent::OutputAdded(OutputHandle::List(scratch)) = &events[1] else {
+            panic!("Expected the new output to be added, got {:?}", events[1]);
+        };
+        assert!(
+            matches!(events[2], LifecycleEvent::OutputRemoved(id) if id == scratch.metadata.id)
+        );
+        let LifecycleEvent::Finished(result) = &events[3] else {
+            panic!("Expected the run to finish last");
+        };
+        assert_eq!(result.as_ref().as_ref().unwrap(), "Done");
+        assert!(lifecycle.next_event().await.is_none());
+    }
+
+    fn counting_run(counter: Arc<AtomicUsize>) -> CommanderStreamingProgramRun {
+        CommanderStreamingProgramRun::new(
+            DataStreamStorage::default(),
+            DataStreamStorage::default(),
+            OutputPipe::default(),
+            OutputPipe::default(),
+            async move {
+                // Like file-explorer, this program never returns on its own.
+                loop {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            },
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropping_every_run_handle_aborts_the_program() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let run = counting_run(counter.clone());
+        let clone = run.clone();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        drop(run);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(counter.load(Ordering::SeqCst) > 5);
+
+        drop(clone);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let stopped_at = counter.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(counter.load(Ordering::SeqCst), stopped_at);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelling_a_run_reports_cancelled() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut run = counting_run(counter.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        run.cancel();
+        let result = run.get_result().await;
+        assert!(matches!(result.as_ref(), Err(RunError::Cancelled)));
+        let stopped_at = counter.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(counter.load(Ordering::SeqCst), stopped_at);
+    }
 }
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use tooltrain_data::{CommanderStructDataType, CommanderValue};
+
+/// Writes a snapshot of a list of structs as CSV, with a header row of the struct's field names
+/// followed by one row per value.
+pub fn list_snapshot_to_csv(
+    snapshot: &[Arc<CommanderValue>],
+    data_type: &CommanderStructDataType,
+) -> Result<String, Error> {
+    let field_names = data_type.field_names();
+    let mut csv = csv_row(field_names.iter().map(String::as_str));
+    for row in snapshot {
+        let CommanderValue::Struct(fields) = row.as_ref() else {
+            return Err(anyhow!(
+                "Cannot export {:?} as CSV, only lists of {} structs can be exported",
+                row,
+                data_type.name
+            ));
+        };
+        let cells: Vec<String> = field_names
+            .iter()
+            .map(|name| fields.get(name).map(csv_cell).unwrap_or_default())
+            .collect();
+        csv.push_str(&csv_row(cells.iter().map(String::as_str)));
+    }
+    Ok(csv)
+}
+
+fn csv_row<'a>(cells: impl Iterator<Item = &'a str>) -> String {
+    let mut row = cells.map(escape_csv).collect::<Vec<String>>().join(",");
+    row.push('\n');
+    row
+}
+
+fn escape_csv(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+fn csv_cell(value: &CommanderValue) -> String {
+    match value {
+        CommanderValue::Trigger(_) => String::new(),
+        CommanderValue::Boolean(b) => b.to_string(),
+        CommanderValue::Number(n) => n.to_string(),
+        CommanderValue::String(s) => s.clone(),
+        CommanderValue::Bytes(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        CommanderValue::Color([r, g, b, a]) => format!("rgba({}, {}, {}, {})", r, g, b, a),
+        CommanderValue::Json(json) => json.to_string(),
+        CommanderValue::Svg(svg) => svg.to_string(),
+        CommanderValue::Path(path) => path.to_string_lossy().to_string(),
+        CommanderValue::Enum(variant) => variant.get_name().to_string(),
+        CommanderValue::Struct(_) => value.to_json().to_string(),
+        CommanderValue::List(values) => values.iter().map(csv_cell).collect::<Vec<_>>().join("; "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tooltrain_data::{
+        CommanderEnumDataType, CommanderNumberDataType, CommanderStringDataType,
+        CommanderStructTypeBuilder,
+    };
+
+    fn file_entity_type() -> CommanderEnumDataType {
+        CommanderEnumDataType::new(
+            "FileEntityType".to_string(),
+            vec!["FILE".to_string(), "DIRECTORY".to_string()],
+        )
+    }
+
+    fn file_struct() -> CommanderStructDataType {
+        CommanderStructTypeBuilder::new("File")
+            .add_field("name", CommanderStringDataType {})
+            .add_field("size", CommanderNumberDataType {})
+            .add_field("type", file_entity_type())
+            .build()
+    }
+
+    fn file(name: &str, size: f64, file_type: &str) -> Arc<CommanderValue> {
+        Arc::new(CommanderValue::Struct(
+            [
+                ("name", CommanderValue::String(name.to_string())),
+                ("size", CommanderValue::Number(size)),
+                (
+                    "type",
+                    file_entity_type().get_variant(file_type).unwrap().into(),
+                ),
+            ]
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect(),
+        ))
+    }
+
+    #[test]
+    fn exports_files_as_csv() {
+        let csv = list_snapshot_to_csv(
+            &[
+                file("notes.txt", 120.0, "FILE"),
+                file("src", 0.0, "DIRECTORY"),
+                file("a, \"quoted\" name", 1.5, "FILE"),
+            ],
+            &file_struct(),
+        )
+        .unwrap();
+        assert_eq!(
+            csv,
+            "name,size,type\n\
+             notes.txt,120,FILE\n\
+             src,0,DIRECTORY\n\
+             \"a, \"\"quoted\"\" name\",1.5,FILE\n"
+        );
+    }
+
+    #[test]
+    fn rejects_non_struct_rows() {
+        let error = list_snapshot_to_csv(&[Arc::new(CommanderValue::Number(1.0))], &file_struct())
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("only lists of File structs can be exported"));
+    }
+}
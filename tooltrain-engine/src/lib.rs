@@ -1,8 +1,27 @@
 mod bindings;
 pub mod datastream;
+mod download;
 mod engine;
+pub mod export;
+mod limits;
+#[cfg(any(test, feature = "schema-bench"))]
+#[cfg_attr(not(test), allow(dead_code))]
+mod mock_program;
+mod registry;
 pub mod streaming;
 
+pub use download::DownloadConfig;
 pub use engine::CommanderEngine;
+pub use engine::CommanderEngineBuilder;
 pub use engine::CommanderStreamingProgramRun;
+pub use engine::LifecycleEvent;
 pub use engine::ProgramSource;
+pub use engine::RunError;
+pub use engine::RunEvent;
+pub use engine::RunLifecycle;
+pub use engine::RunResult;
+pub use limits::ResourceLimits;
+#[cfg(feature = "schema-bench")]
+#[doc(hidden)]
+pub use mock_program::mock_program_sources;
+pub use registry::ProgramRegistry;
@@ -0,0 +1,77 @@
+use anyhow::Error;
+use wasmtime::{ResourceLimiter, DEFAULT_INSTANCE_LIMIT};
+
+/// Caps on what a program may allocate, so that a buggy program fails with
+/// [`crate::RunError::ResourceLimit`] instead of exhausting the host. Unset limits are unbounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Largest size, in bytes, that any one of the program's linear memories may grow to.
+    pub max_memory_bytes: Option<usize>,
+    /// Most elements that any one of the program's tables may grow to.
+    pub max_table_elements: Option<u32>,
+    /// Most core instances the program may create. Defaults to wasmtime's own limit.
+    pub max_instances: Option<usize>,
+}
+
+/// Returned when a program tries to grow a memory or table past its [`ResourceLimits`].
+#[derive(Debug)]
+pub(crate) struct ResourceLimitExceeded {
+    resource: &'static str,
+    unit: &'static str,
+    desired: usize,
+    limit: usize,
+}
+
+impl std::fmt::Display for ResourceLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Program tried to grow a {} to {} {}, over the limit of {}",
+            self.resource, self.desired, self.unit, self.limit
+        )
+    }
+}
+
+impl std::error::Error for ResourceLimitExceeded {}
+
+impl ResourceLimiter for ResourceLimits {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool, Error> {
+        match self.max_memory_bytes {
+            Some(limit) if desired > limit => Err(ResourceLimitExceeded {
+                resource: "memory",
+                unit: "bytes",
+                desired,
+                limit,
+            }
+            .into()),
+            _ => Ok(true),
+        }
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        desired: u32,
+        _maximum: Option<u32>,
+    ) -> Result<bool, Error> {
+        match self.max_table_elements {
+            Some(limit) if desired > limit => Err(ResourceLimitExceeded {
+                resource: "table",
+                unit: "elements",
+                desired: desired as usize,
+                limit: limit as usize,
+            }
+            .into()),
+            _ => Ok(true),
+        }
+    }
+
+    fn instances(&self) -> usize {
+        self.max_instances.unwrap_or(DEFAULT_INSTANCE_LIMIT)
+    }
+}
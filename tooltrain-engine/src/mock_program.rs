@@ -0,0 +1,710 @@
+//! Programs for tests, written in the WebAssembly text format so that tests do not need programs
+//! compiled for `wasm32`.
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bindings::inputs::{OutputKind, Schema},
+    engine::ProgramSource,
+};
+
+/// Where a mock program's schema is laid out in its memory.
+const SCHEMA_ADDRESS: u32 = 1024;
+/// Where a mock program's `realloc` starts allocating, above the schema.
+const HEAP_ADDRESS: u32 = 32 * 1024;
+
+/// The schema of the programs built by [`mock_component_wat`].
+pub(crate) fn mock_schema() -> Schema {
+    Schema {
+        name: "Mock".to_string(),
+        description: "Mock".to_string(),
+        version: "0.1.0".to_string(),
+        category: None,
+        arguments: vec![],
+        performs_state_change: false,
+        declared_outputs: vec![],
+    }
+}
+
+/// Compiles a program whose `get-schema` returns `schema` and whose `run` returns `Ok("Mock")`.
+pub(crate) fn mock_program_wasm(schema: &Schema) -> Vec<u8> {
+    wat::parse_str(mock_component_wat_with(
+        &MockImports::default(),
+        schema,
+        "",
+        "",
+    ))
+    .unwrap()
+}
+
+/// A [`ProgramSource`] for the program built by [`mock_program_wasm`].
+pub(crate) fn mock_program_source(schema: &Schema) -> ProgramSource {
+    let wasm = mock_program_wasm(schema);
+    let sha256 = Sha256::digest(&wasm).into();
+    ProgramSource::BytesWithHash(wasm, sha256)
+}
+
+/// Sources for `count` distinct mock programs, for `benches/schema_linkers.rs`.
+#[cfg(feature = "schema-bench")]
+pub fn mock_program_sources(count: usize) -> Vec<ProgramSource> {
+    (0..count)
+        .map(|i| {
+            mock_program_source(&Schema {
+                name: format!("Mock {i}"),
+                ..mock_schema()
+            })
+        })
+        .collect()
+}
+
+/// The text of a component whose `get-schema` returns [`mock_schema`] and whose `run` returns
+/// `Ok("Mock")` after executing `run_prelude`. When `uses_sockets` is set, `get-schema` first calls
+/// into `wasi:sockets`, which only the full linker provides.
+pub(crate) fn mock_component_wat(uses_sockets: bool, run_prelude: &str) -> String {
+    if !uses_sockets {
+        return mock_component_wat_with(&MockImports::default(), &mock_schema(), "", run_prelude);
+    }
+    let sockets = MockImports {
+        component: r#"
+            (import "wasi:sockets/network@0.2.1" (instance $network
+                (export "network" (type (sub resource)))
+            ))
+            (alias export $network "network" (type $network-type))
+            (import "wasi:sockets/instance-network@0.2.1" (instance $instance-network
+                (alias outer 1 $network-type (type $n))
+                (export $n-eq "network" (type (eq $n)))
+                (type $own-n (own $n-eq))
+                (export "instance-network" (func (result $own-n)))
+            ))
+            (alias export $instance-network "instance-network" (func $instance-network-fn))
+            (core func $instance-network-core (canon lower (func $instance-network-fn)))
+            (core instance $sockets
+                (export "instance-network" (func $instance-network-core))
+            )
+        "#
+        .to_string(),
+        module: r#"(import "sockets" "instance-network" (func $instance-network (result i32)))"#
+            .to_string(),
+        instantiate_with: r#"(with "sockets" (instance $sockets))"#.to_string(),
+        ..Default::default()
+    };
+    mock_component_wat_with(
+        &sockets,
+        &mock_schema(),
+        "(drop (call $instance-network))",
+        run_prelude,
+    )
+}
+
+/// The text of a component like [`mock_component_wat`]'s, whose `run` returns `Err(message)`.
+pub(crate) fn mock_failing_component_wat(message: &str) -> String {
+    let imports = MockImports {
+        module: format!(
+            r#"(data (i32.const 300) "{}")"#,
+            wat_string(message.as_bytes())
+        ),
+        ..Default::default()
+    };
+    let run_prelude = format!(
+        r#"
+        (i32.store8 (i32.const 64) (i32.const 1))
+        (i32.store (i32.const 68) (i32.const 300))
+        (i32.store (i32.const 72) (i32.const {}))
+        "#,
+        message.len()
+    );
+    mock_component_wat_with(&imports, &mock_schema(), "", &run_prelude)
+}
+
+/// The text of a component whose `get-schema` returns `schema` and whose `run` returns the names
+/// that its first two inputs, which must be value inputs, report, separated by a comma.
+pub(crate) fn mock_argument_names_component_wat(schema: &Schema) -> String {
+    let imports = MockImports {
+        streaming_inputs: r#"
+            (type $borrow-value-input (borrow $value-input))
+            (export "[method]value-input.name"
+                (func (param "self" $borrow-value-input) (result string)))
+        "#
+        .to_string(),
+        component: r#"
+            (alias export $inputs "[method]value-input.name" (func $name-fn))
+            (core func $name-core (canon lower (func $name-fn)
+                (memory $mem "memory") (realloc (func $mem "realloc")) string-encoding=utf8))
+            (core instance $names (export "value-input-name" (func $name-core)))
+        "#
+        .to_string(),
+        module: r#"(import "inputs" "value-input-name" (func $value-input-name (param i32 i32)))"#
+            .to_string(),
+        instantiate_with: r#"(with "inputs" (instance $names))"#.to_string(),
+    };
+    // Each input is a variant laid out as its case at 0 and its handle at 4. The names are
+    // written as pointers and lengths to 512 and 520, then joined at 2048.
+    let run_prelude = r#"
+        (call $value-input-name (i32.load offset=4 (local.get 0)) (i32.const 512))
+        (call $value-input-name (i32.load offset=12 (local.get 0)) (i32.const 520))
+        (memory.copy (i32.const 2048) (i32.load (i32.const 512)) (i32.load (i32.const 516)))
+        (i32.store8 (i32.add (i32.const 2048) (i32.load (i32.const 516))) (i32.const 44))
+        (memory.copy
+            (i32.add (i32.const 2049) (i32.load (i32.const 516)))
+            (i32.load (i32.const 520))
+            (i32.load (i32.const 524)))
+        ;; Return the names instead of "Mock"
+        (i32.store (i32.const 68) (i32.const 2048))
+        (i32.store (i32.const 72)
+            (i32.add (i32.const 1) (i32.add (i32.load (i32.const 516)) (i32.load (i32.const 524)))))
+    "#;
+    mock_component_wat_with(&imports, schema, "", run_prelude)
+}
+
+/// The text of a component like [`mock_component_wat`]'s, whose `run` first writes `text` to
+/// stderr through `wasi:cli/stderr`.
+pub(crate) fn mock_stderr_component_wat(text: &str) -> String {
+    let imports = MockImports {
+        component: r#"
+            (import "wasi:io/error@0.2.0" (instance $io-error
+                (export "error" (type (sub resource)))
+            ))
+            (alias export $io-error "error" (type $io-error-type))
+            (import "wasi:io/streams@0.2.0" (instance $streams
+                (alias outer 1 $io-error-type (type $e))
+                (export $error "error" (type (eq $e)))
+                (export $output-stream "output-stream" (type (sub resource)))
+                (type $own-error (own $error))
+                (type $stream-error (variant
+                    (case "last-operation-failed" $own-error)
+                    (case "closed")
+                ))
+                (export $stream-error-export "stream-error" (type (eq $stream-error)))
+                (type $borrow-output-stream (borrow $output-stream))
+                (type $bytes (list u8))
+                (type $write-result (result (error $stream-error-export)))
+                (export "[method]output-stream.blocking-write-and-flush"
+                    (func (param "self" $borrow-output-stream) (param "contents" $bytes)
+                        (result $write-result)))
+            ))
+            (alias export $streams "output-stream" (type $output-stream-type))
+            (import "wasi:cli/stderr@0.2.0" (instance $stderr
+                (alias outer 1 $output-stream-type (type $s))
+                (export $output-stream "output-stream" (type (eq $s)))
+                (type $own-output-stream (own $output-stream))
+                (export "get-stderr" (func (result $own-output-stream)))
+            ))
+
+            (alias export $stderr "get-stderr" (func $get-stderr-fn))
+            (alias export $streams "[method]output-stream.blocking-write-and-flush"
+                (func $write-fn))
+            (core func $get-stderr-core (canon lower (func $get-stderr-fn)))
+            (core func $write-core (canon lower (func $write-fn) (memory $mem "memory")))
+            (core instance $cli
+                (export "get-stderr" (func $get-stderr-core))
+                (export "write" (func $write-core))
+            )
+        "#
+        .to_string(),
+        module: format!(
+            r#"
+            (import "cli" "get-stderr" (func $get-stderr (result i32)))
+            (import "cli" "write" (func $write (param i32 i32 i32 i32)))
+            (data (i32.const 300) "{}")
+            "#,
+            wat_string(text.as_bytes())
+        ),
+        instantiate_with: r#"(with "cli" (instance $cli))"#.to_string(),
+        ..Default::default()
+    };
+    let run_prelude = format!(
+        "(call $write (call $get-stderr) (i32.const 300) (i32.const {}) (i32.const 512))",
+        text.len()
+    );
+    mock_component_wat_with(&imports, &mock_schema(), "", &run_prelude)
+}
+
+/// The text of a component whose `get-schema` returns `schema` and whose `run` makes a GET request
+/// for `path_with_query` on `authority` through `wasi:http`, and returns the response body as its
+/// result. If the request cannot be sent, `run` returns `Err("HTTP error-code <case>")` with the
+/// index of the `error-code` case the host returned instead.
+pub(crate) fn mock_http_component_wat(
+    schema: &Schema,
+    authority: &str,
+    path_with_query: &str,
+) -> String {
+    let http = MockImports {
+        component: r#"
+            (import "wasi:io/error@0.2.0" (instance $io-error
+                (export "error" (type (sub resource)))
+            ))
+            (alias export $io-error "error" (type $io-error-type))
+            (import "wasi:io/streams@0.2.0" (instance $streams
+                (alias outer 1 $io-error-type (type $e))
+                (export $error "error" (type (eq $e)))
+                (export $input-stream "input-stream" (type (sub resource)))
+                (type $own-error (own $error))
+                (type $stream-error (variant
+                    (case "last-operation-failed" $own-error)
+                    (case "closed")
+                ))
+                (export $stream-error-export "stream-error" (type (eq $stream-error)))
+                (type $borrow-input-stream (borrow $input-stream))
+                (type $bytes (list u8))
+                (type $read-result (result $bytes (error $stream-error-export)))
+                (export "[method]input-stream.blocking-read"
+                    (func (param "self" $borrow-input-stream) (param "len" u64) (result $read-result)))
+            ))
+            (alias export $streams "input-stream" (type $input-stream-type))
+            (import "wasi:http/types@0.2.0" (instance $http-types
+                (alias outer 1 $input-stream-type (type $s))
+                (export $input-stream "input-stream" (type (eq $s)))
+                (export $fields "fields" (type (sub resource)))
+                (export $outgoing-request "outgoing-request" (type (sub resource)))
+                (export $request-options "request-options" (type (sub resource)))
+                (export $future-incoming-response "future-incoming-response" (type (sub resource)))
+                (export $incoming-response "incoming-response" (type (sub resource)))
+                (export $incoming-body "incoming-body" (type (sub resource)))
+                (type $dns-error-payload (record
+                    (field "rcode" (option string))
+                    (field "info-code" (option u16))
+                ))
+                (export $dns-error-payload-export "DNS-error-payload" (type (eq $dns-error-payload)))
+                (type $tls-alert-received-payload (record
+                    (field "alert-id" (option u8))
+                    (field "alert-message" (option string))
+                ))
+                (export $tls-alert-received-payload-export "TLS-alert-received-payload"
+                    (type (eq $tls-alert-received-payload)))
+                (type $field-size-payload (record
+                    (field "field-name" (option string))
+                    (field "field-size" (option u32))
+                ))
+                (export $field-size-payload-export "field-size-payload" (type (eq $field-size-payload)))
+                (type $error-code (variant
+                    (case "DNS-timeout")
+                    (case "DNS-error" $dns-error-payload-export)
+                    (case "destination-not-found")
+                    (case "destination-unavailable")
+                    (case "destination-IP-prohibited")
+                    (case "destination-IP-unroutable")
+                    (case "connection-refused")
+                    (case "connection-terminated")
+                    (case "connection-timeout")
+                    (case "connection-read-timeout")
+                    (case "connection-write-timeout")
+                    (case "connection-limit-reached")
+                    (case "TLS-protocol-error")
+                    (case "TLS-certificate-error")
+                    (case "TLS-alert-received" $tls-alert-received-payload-export)
+                    (case "HTTP-request-denied")
+                    (case "HTTP-request-length-required")
+                    (case "HTTP-request-body-size" (option u64))
+                    (case "HTTP-request-method-invalid")
+                    (case "HTTP-request-URI-invalid")
+                    (case "HTTP-request-URI-too-long")
+                    (case "HTTP-request-header-section-size" (option u32))
+                    (case "HTTP-request-header-size" (option $field-size-payload-export))
+                    (case "HTTP-request-trailer-section-size" (option u32))
+                    (case "HTTP-request-trailer-size" $field-size-payload-export)
+                    (case "HTTP-response-incomplete")
+                    (case "HTTP-response-header-section-size" (option u32))
+                    (case "HTTP-response-header-size" $field-size-payload-export)
+                    (case "HTTP-response-body-size" (option u64))
+                    (case "HTTP-response-trailer-section-size" (option u32))
+                    (case "HTTP-response-trailer-size" $field-size-payload-export)
+                    (case "HTTP-response-transfer-coding" (option string))
+                    (case "HTTP-response-content-coding" (option string))
+                    (case "HTTP-response-timeout")
+                    (case "HTTP-upgrade-failed")
+                    (case "HTTP-protocol-error")
+                    (case "loop-detected")
+                    (case "configuration-error")
+                    (case "internal-error" (option string))
+                ))
+                (export $error-code-export "error-code" (type (eq $error-code)))
+
+                (type $own-fields (own $fields))
+                (export "[constructor]fields" (func (result $own-fields)))
+                (type $own-outgoing-request (own $outgoing-request))
+                (export "[constructor]outgoing-request"
+                    (func (param "headers" $own-fields) (result $own-outgoing-request)))
+                (type $borrow-outgoing-request (borrow $outgoing-request))
+                (type $option-string (option string))
+                (type $unit-result (result))
+                (export "[method]outgoing-request.set-path-with-query"
+                    (func (param "self" $borrow-outgoing-request) (param "path-with-query" $option-string)
+                        (result $unit-result)))
+                (export "[method]outgoing-request.set-authority"
+                    (func (param "self" $borrow-outgoing-request) (param "authority" $option-string)
+                        (result $unit-result)))
+                (type $borrow-future (borrow $future-incoming-response))
+                (type $own-incoming-response (own $incoming-response))
+                (type $response-result (result $own-incoming-response (error $error-code-export)))
+                (type $response-ready (result $response-result))
+                (type $get-result (option $response-ready))
+                (export "[method]future-incoming-response.get"
+                    (func (param "self" $borrow-future) (result $get-result)))
+                (type $borrow-incoming-response (borrow $incoming-response))
+                (type $own-incoming-body (own $incoming-body))
+                (type $consume-result (result $own-incoming-body))
+                (export "[method]incoming-response.consume"
+                    (func (param "self" $borrow-incoming-response) (result $consume-result)))
+                (type $borrow-incoming-body (borrow $incoming-body))
+                (type $own-input-stream (own $input-stream))
+                (type $stream-result (result $own-input-stream))
+                (export "[method]incoming-body.stream"
+                    (func (param "self" $borrow-incoming-body) (result $stream-result)))
+            ))
+            (alias export $http-types "outgoing-request" (type $outgoing-request-type))
+            (alias export $http-types "request-options" (type $request-options-type))
+            (alias export $http-types "future-incoming-response" (type $future-type))
+            (alias export $http-types "error-code" (type $error-code-type))
+            (import "wasi:http/outgoing-handler@0.2.0" (instance $outgoing-handler
+                (alias outer 1 $outgoing-request-type (type $r))
+                (export $outgoing-request "outgoing-request" (type (eq $r)))
+                (alias outer 1 $request-options-type (type $o))
+                (export $request-options "request-options" (type (eq $o)))
+                (alias outer 1 $future-type (type $f))
+                (export $future-incoming-response "future-incoming-response" (type (eq $f)))
+                (alias outer 1 $error-code-type (type $c))
+                (export $error-code "error-code" (type (eq $c)))
+                (type $own-request (own $outgoing-request))
+                (type $own-options (own $request-options))
+                (type $option-options (option $own-options))
+                (type $own-future (own $future-incoming-response))
+                (type $handle-result (result $own-future (error $error-code)))
+                (export "handle"
+                    (func (param "request" $own-request) (param "options" $option-options)
+                        (result $handle-result)))
+            ))
+
+            (alias export $http-types "[constructor]fields" (func $fields-fn))
+            (alias export $http-types "[constructor]outgoing-request" (func $outgoing-request-fn))
+            (alias export $http-types "[method]outgoing-request.set-path-with-query"
+                (func $set-path-with-query-fn))
+            (alias export $http-types "[method]outgoing-request.set-authority" (func $set-authority-fn))
+            (alias export $outgoing-handler "handle" (func $handle-fn))
+            (alias export $http-types "[method]future-incoming-response.get" (func $get-fn))
+            (alias export $http-types "[method]incoming-response.consume" (func $consume-fn))
+            (alias export $http-types "[method]incoming-body.stream" (func $stream-fn))
+            (alias export $streams "[method]input-stream.blocking-read" (func $blocking-read-fn))
+            (core func $fields-core (canon lower (func $fields-fn)))
+            (core func $outgoing-request-core (canon lower (func $outgoing-request-fn)))
+            (core func $set-path-with-query-core (canon lower (func $set-path-with-query-fn)
+                (memory $mem "memory") string-encoding=utf8))
+            (core func $set-authority-core (canon lower (func $set-authority-fn)
+                (memory $mem "memory") string-encoding=utf8))
+            (core func $handle-core (canon lower (func $handle-fn)
+                (memory $mem "memory") (realloc (func $mem "realloc")) string-encoding=utf8))
+            (core func $get-core (canon lower (func $get-fn)
+                (memory $mem "memory") (realloc (func $mem "realloc")) string-encoding=utf8))
+            (core func $consume-core (canon lower (func $consume-fn) (memory $mem "memory")))
+            (core func $stream-core (canon lower (func $stream-fn) (memory $mem "memory")))
+            (core func $blocking-read-core (canon lower (func $blocking-read-fn)
+                (memory $mem "memory") (realloc (func $mem "realloc"))))
+            (core instance $http
+                (export "fields" (func $fields-core))
+                (export "outgoing-request" (func $outgoing-request-core))
+                (export "set-path-with-query" (func $set-path-with-query-core))
+                (export "set-authority" (func $set-authority-core))
+                (export "handle" (func $handle-core))
+                (export "get" (func $get-core))
+                (export "consume" (func $consume-core))
+                (export "stream" (func $stream-core))
+                (export "blocking-read" (func $blocking-read-core))
+            )
+        "#
+        .to_string(),
+        module: format!(
+            r#"
+            (import "http" "fields" (func $fields (result i32)))
+            (import "http" "outgoing-request" (func $outgoing-request (param i32) (result i32)))
+            (import "http" "set-path-with-query"
+                (func $set-path-with-query (param i32 i32 i32 i32) (result i32)))
+            (import "http" "set-authority" (func $set-authority (param i32 i32 i32 i32) (result i32)))
+            (import "http" "handle" (func $handle (param i32 i32 i32 i32)))
+            (import "http" "get" (func $get (param i32 i32)))
+            (import "http" "consume" (func $consume (param i32 i32)))
+            (import "http" "stream" (func $stream (param i32 i32)))
+            (import "http" "blocking-read" (func $blocking-read (param i32 i64 i32)))
+            (data (i32.const 300) "{authority}")
+            (data (i32.const 400) "{path_with_query}")
+            (data (i32.const 700) "HTTP error-code 00")
+            "#
+        ),
+        instantiate_with: r#"(with "http" (instance $http))"#.to_string(),
+        ..Default::default()
+    };
+    // Each call that returns a result writes it to 512, and the handle or list it carries is
+    // read from the offset the canonical ABI lays it out at.
+    let run_prelude = format!(
+        r#"
+        (local $request i32)
+        (local.set $request (call $outgoing-request (call $fields)))
+        (drop (call $set-authority (local.get $request) (i32.const 1) (i32.const 300) (i32.const {})))
+        (drop (call $set-path-with-query
+            (local.get $request) (i32.const 1) (i32.const 400) (i32.const {})))
+        (call $handle (local.get $request) (i32.const 0) (i32.const 0) (i32.const 512))
+        (if (i32.load8_u (i32.const 512))
+            (then
+                ;; Write the error-code case as two digits and return Err
+                (i32.store8 (i32.const 716)
+                    (i32.add (i32.const 48) (i32.div_u (i32.load8_u (i32.const 520)) (i32.const 10))))
+                (i32.store8 (i32.const 717)
+                    (i32.add (i32.const 48) (i32.rem_u (i32.load8_u (i32.const 520)) (i32.const 10))))
+                (i32.store8 (i32.const 64) (i32.const 1))
+                (i32.store (i32.const 68) (i32.const 700))
+                (i32.store (i32.const 72) (i32.const 18))
+                (return (i32.const 64))))
+        (call $get (i32.load (i32.const 520)) (i32.const 512))
+        (call $consume (i32.load (i32.const 536)) (i32.const 512))
+        (call $stream (i32.load (i32.const 516)) (i32.const 512))
+        (call $blocking-read (i32.load (i32.const 516)) (i64.const 4096) (i32.const 512))
+        ;; Return the body instead of "Mock"
+        (i32.store (i32.const 68) (i32.load (i32.const 516)))
+        (i32.store (i32.const 72) (i32.load (i32.const 520)))
+        "#,
+        authority.len(),
+        path_with_query.len(),
+    );
+    mock_component_wat_with(&http, schema, "", &run_prelude)
+}
+
+/// Host interfaces imported by a mock component, beyond the streaming plugin's own.
+#[derive(Default)]
+struct MockImports {
+    /// Exports added to the `tooltrain:base/streaming-inputs` import, which can use its
+    /// `$value-input`, `$list-input` and `$tree-input` resource types.
+    streaming_inputs: String,
+    /// Component imports, and the core instances of their lowered functions. Lowered functions
+    /// can use the memory and `realloc` of the `$mem` core instance.
+    component: String,
+    /// Core imports and data segments added to the component's core module.
+    module: String,
+    /// The `with` clauses that pass the lowered core instances to the core module.
+    instantiate_with: String,
+}
+
+fn mock_component_wat_with(
+    imports: &MockImports,
+    schema: &Schema,
+    get_schema_prelude: &str,
+    run_prelude: &str,
+) -> String {
+    let MockImports {
+        streaming_inputs,
+        component: component_imports,
+        module: module_imports,
+        instantiate_with,
+    } = imports;
+    let schema_data = wat_string(&schema_data(schema, SCHEMA_ADDRESS));
+    format!(
+        r#"
+        (component
+            (import "tooltrain:base/streaming-inputs" (instance $inputs
+                (export $value-input "value-input" (type (sub resource)))
+                (export $list-input "list-input" (type (sub resource)))
+                (export $tree-input "tree-input" (type (sub resource)))
+                {streaming_inputs}
+            ))
+            (alias export $inputs "value-input" (type $value-input))
+            (alias export $inputs "list-input" (type $list-input))
+            (alias export $inputs "tree-input" (type $tree-input))
+
+            (core module $memory
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const {HEAP_ADDRESS}))
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                    (local $ptr i32)
+                    (local.set $ptr (global.get $next))
+                    (global.set $next (i32.add (local.get $ptr) (i32.add (local.get 3) (i32.const 8))))
+                    (local.get $ptr))
+            )
+            (core instance $mem (instantiate $memory))
+            {component_imports}
+
+            (core module $m
+                (import "mem" "memory" (memory 1))
+                {module_imports}
+                (func (export "get-schema") (result i32)
+                    {get_schema_prelude}
+                    (i32.const {SCHEMA_ADDRESS}))
+                (func (export "run") (param i32 i32) (result i32)
+                    {run_prelude}
+                    (i32.const 64))
+                ;; Ok("Mock")
+                (data (i32.const 64) "\00\00\00\00\c8\00\00\00\04\00\00\00")
+                (data (i32.const 200) "Mock")
+                (data (i32.const {SCHEMA_ADDRESS}) "{schema_data}")
+            )
+            (core instance $i (instantiate $m (with "mem" (instance $mem)) {instantiate_with}))
+
+            (type $argument-spec (record
+                (field "name" string)
+                (field "description" string)
+                (field "data-type" string)
+                (field "supports-updates" bool)
+            ))
+            (export $argument-spec-export "argument-spec" (type $argument-spec))
+            (type $output-kind (enum "value" "list" "tree"))
+            (export $output-kind-export "output-kind" (type $output-kind))
+            (type $output-spec (record
+                (field "name" string)
+                (field "description" string)
+                (field "kind" $output-kind-export)
+                (field "data-type" string)
+            ))
+            (export $output-spec-export "output-spec" (type $output-spec))
+            (type $schema (record
+                (field "name" string)
+                (field "description" string)
+                (field "version" string)
+                (field "category" (option string))
+                (field "arguments" (list $argument-spec-export))
+                (field "performs-state-change" bool)
+                (field "declared-outputs" (list $output-spec-export))
+            ))
+            (export $schema-export "schema" (type $schema))
+            (type $input (variant
+                (case "value-input" (own $value-input))
+                (case "list-input" (own $list-input))
+                (case "tree-input" (own $tree-input))
+            ))
+            (export $input-export "input" (type $input))
+
+            (func $get-schema (result $schema-export)
+                (canon lift (core func $i "get-schema") (memory $mem "memory")))
+            (export "get-schema" (func $get-schema))
+            (func $run (param "inputs" (list $input-export)) (result (result string (error string)))
+                (canon lift (core func $i "run") (memory $mem "memory")
+                    (realloc (func $mem "realloc")) string-encoding=utf8))
+            (export "run" (func $run))
+        )
+        "#
+    )
+}
+
+/// Lays `schema` out as the canonical ABI stores a `schema` record at `address`, followed by the
+/// strings and lists the record points to.
+fn schema_data(schema: &Schema, address: u32) -> Vec<u8> {
+    let mut memory = MockMemory {
+        address,
+        bytes: vec![],
+    };
+    let record = memory.reserve(56);
+    memory.write_string(record, &schema.name);
+    memory.write_string(record + 8, &schema.description);
+    memory.write_string(record + 16, &schema.version);
+    if let Some(category) = &schema.category {
+        memory.write_u8(record + 24, 1);
+        memory.write_string(record + 28, category);
+    }
+
+    let arguments = memory.reserve(28 * schema.arguments.len());
+    memory.write_u32(record + 36, arguments);
+    memory.write_u32(record + 40, schema.arguments.len() as u32);
+    for (index, argument) in schema.arguments.iter().enumerate() {
+        let spec = arguments + 28 * index as u32;
+        memory.write_string(spec, &argument.name);
+        memory.write_string(spec + 8, &argument.description);
+        memory.write_string(spec + 16, &argument.data_type);
+        memory.write_u8(spec + 24, argument.supports_updates as u8);
+    }
+
+    memory.write_u8(record + 44, schema.performs_state_change as u8);
+
+    let outputs = memory.reserve(28 * schema.declared_outputs.len());
+    memory.write_u32(record + 48, outputs);
+    memory.write_u32(record + 52, schema.declared_outputs.len() as u32);
+    for (index, output) in schema.declared_outputs.iter().enumerate() {
+        let spec = outputs + 28 * index as u32;
+        memory.write_string(spec, &output.name);
+        memory.write_string(spec + 8, &output.description);
+        let kind = match output.kind {
+            OutputKind::Value => 0,
+            OutputKind::List => 1,
+            OutputKind::Tree => 2,
+        };
+        memory.write_u8(spec + 16, kind);
+        memory.write_string(spec + 20, &output.data_type);
+    }
+
+    assert!(
+        address as usize + memory.bytes.len() <= HEAP_ADDRESS as usize,
+        "Mock schema is too large"
+    );
+    memory.bytes
+}
+
+/// A region of a mock program's memory starting at `address`.
+struct MockMemory {
+    address: u32,
+    bytes: Vec<u8>,
+}
+
+impl MockMemory {
+    /// Appends `size` zeroed bytes, aligned to 4 bytes, and returns their address.
+    fn reserve(&mut self, size: usize) -> u32 {
+        let start = self.bytes.len().next_multiple_of(4);
+        self.bytes.resize(start + size, 0);
+        self.address + start as u32
+    }
+
+    fn write_u8(&mut self, address: u32, value: u8) {
+        self.bytes[(address - self.address) as usize] = value;
+    }
+
+    fn write_u32(&mut self, address: u32, value: u32) {
+        let offset = (address - self.address) as usize;
+        self.bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Stores `value` after the existing bytes, and points the string at `address` to it.
+    fn write_string(&mut self, address: u32, value: &str) {
+        let pointer = self.reserve(value.len());
+        let offset = (pointer - self.address) as usize;
+        self.bytes[offset..].copy_from_slice(value.as_bytes());
+        self.write_u32(address, pointer);
+        self.write_u32(address + 4, value.len() as u32);
+    }
+}
+
+/// Escapes `bytes` for a data segment string.
+fn wat_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("\\{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bindings::inputs::{ArgumentSpec, OutputSpec},
+        engine::CommanderEngine,
+    };
+
+    #[tokio::test]
+    async fn get_schema_returns_the_mock_schema() {
+        let schema = Schema {
+            category: Some("Files".to_string()),
+            arguments: vec![ArgumentSpec {
+                name: "root".to_string(),
+                description: "The root directory".to_string(),
+                data_type: "path".to_string(),
+                supports_updates: true,
+            }],
+            performs_state_change: true,
+            declared_outputs: vec![OutputSpec {
+                name: "Files".to_string(),
+                description: "The list of files".to_string(),
+                kind: OutputKind::Tree,
+                data_type: "string".to_string(),
+            }],
+            ..mock_schema()
+        };
+        let mut program = CommanderEngine::new()
+            .open_program(mock_program_source(&schema))
+            .await
+            .unwrap();
+        // The generated schema types do not implement `PartialEq`.
+        assert_eq!(
+            format!("{:?}", program.get_schema().await.unwrap()),
+            format!("{:?}", schema)
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Error};
+
+use crate::{
+    bindings::inputs::Schema,
+    engine::{CommanderEngine, CommanderStreamingProgram, ProgramSource},
+};
+
+struct RegisteredProgram {
+    program: CommanderStreamingProgram,
+    schema: Schema,
+}
+
+/// Holds many programs along with their schemas, indexed by program name, so that a host can
+/// present them as a palette without instantiating each program again.
+#[derive(Default)]
+pub struct ProgramRegistry {
+    programs: BTreeMap<String, RegisteredProgram>,
+}
+
+impl ProgramRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the program's schema and adds the program under its schema's name. Fails if a
+    /// program with the same name is already registered.
+    pub async fn register(
+        &mut self,
+        mut program: CommanderStreamingProgram,
+    ) -> Result<&Schema, Error> {
+        let schema = program.get_schema().await?;
+        if self.programs.contains_key(&schema.name) {
+            return Err(anyhow!(
+                "A program named {:?} is already registered",
+                schema.name
+            ));
+        }
+        let name = schema.name.clone();
+        let registered = self
+            .programs
+            .entry(name)
+            .or_insert(RegisteredProgram { program, schema });
+        Ok(&registered.schema)
+    }
+
+    /// Opens a program from `source`, validating its schema, and registers it.
+    pub async fn open(
+        &mut self,
+        engine: &CommanderEngine,
+        source: ProgramSource,
+    ) -> Result<&Schema, Error> {
+        let program = engine.open_program_validated(source).await?;
+        self.register(program).await
+    }
+
+    /// Returns the program named `name`. Programs are cheap to clone, so a host can clone the
+    /// result to start runs.
+    pub fn find_by_name(&self, name: &str) -> Option<&CommanderStreamingProgram> {
+        self.programs
+            .get(name)
+            .map(|registered| &registered.program)
+    }
+
+    /// Returns the schemas of every program in `category`, ordered by name.
+    pub fn find_by_category<'a>(&'a self, category: &'a str) -> impl Iterator<Item = &'a Schema> {
+        self.all_schemas()
+            .filter(move |schema| schema.category.as_deref() == Some(category))
+    }
+
+    /// Returns the schemas of every registered program, ordered by name.
+    pub fn all_schemas(&self) -> impl Iterator<Item = &Schema> {
+        self.programs.values().map(|registered| &registered.schema)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<CommanderStreamingProgram> {
+        self.programs
+            .remove(name)
+            .map(|registered| registered.program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_program::{mock_program_source, mock_schema};
+    use std::path::PathBuf;
+
+    fn schema(name: &str, category: Option<&str>) -> Schema {
+        Schema {
+            name: name.to_string(),
+            description: format!("The {} program", name),
+            category: category.map(str::to_string),
+            ..mock_schema()
+        }
+    }
+
+    #[tokio::test]
+    async fn indexes_programs_by_name_and_category() {
+        let engine = CommanderEngine::new();
+        let mut registry = ProgramRegistry::new();
+        for (name, category) in [
+            ("List Files", Some("Files")),
+            ("File Explorer", Some("Files")),
+            ("Mastodon Feed", Some("Social")),
+            ("Scratchpad", None),
+        ] {
+            let source = mock_program_source(&schema(name, category));
+            assert_eq!(registry.open(&engine, source).await.unwrap().name, name);
+        }
+
+        let duplicate = mock_program_source(&schema("List Files", None));
+        assert!(registry.open(&engine, duplicate).await.is_err());
+
+        let names = |schemas: Vec<&Schema>| {
+            schemas
+                .into_iter()
+                .map(|schema| schema.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(registry.find_by_category("Files").collect()),
+            vec!["File Explorer", "List Files"]
+        );
+        assert_eq!(
+            names(registry.all_schemas().collect()),
+            vec!["File Explorer", "List Files", "Mastodon Feed", "Scratchpad"]
+        );
+        assert!(registry.find_by_category("Games").next().is_none());
+
+        let mut ls = registry.find_by_name("List Files").unwrap().clone();
+        assert_eq!(
+            ls.get_schema().await.unwrap().category.as_deref(),
+            Some("Files")
+        );
+        assert!(registry.find_by_name("ls").is_none());
+
+        assert!(registry.remove("List Files").is_some());
+        assert!(registry.find_by_name("List Files").is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ls and file-explorer built with `cargo component build --release`"]
+    async fn registers_the_core_programs() {
+        let engine = CommanderEngine::new();
+        let mut registry = ProgramRegistry::new();
+        let target =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/wasm32-wasip1/release");
+        for program in ["ls.wasm", "file_explorer.wasm"] {
+            registry
+                .open(&engine, ProgramSource::FilePath(target.join(program)))
+                .await
+                .unwrap();
+        }
+
+        let files: Vec<&str> = registry
+            .find_by_category("Files")
+            .map(|schema| schema.name.as_str())
+            .collect();
+        assert_eq!(files, vec!["File Explorer", "List Files"]);
+        let mut ls = registry.find_by_name("List Files").unwrap().clone();
+        assert!(ls.run().await.is_ok());
+    }
+}
@@ -1,11 +1,8 @@
-use std::sync::Arc;
-
 use crate::{
     bindings::streaming::{
         ListInput, ListOutput, StreamingPluginImports, TreeInput, TreeOutput, ValueInput,
         ValueOutput,
     },
-    datastream::{DataStream, ListStream, TreeStream, ValueStream},
     streaming::storage::WasmStorage,
 };
 
@@ -13,7 +10,6 @@ use anyhow::Error;
 use async_trait::async_trait;
 
 use tooltrain_data::{parse, CommanderCoder};
-use parking_lot::RwLock;
 use wasmtime::component::*;
 use wasmtime_wasi::WasiImpl;
 
@@ -37,9 +33,7 @@ impl StreamingPluginImports for WasiImpl<&mut WasmStorage> {
             name,
             description,
             tooltrain_data_type,
-            Arc::new(RwLock::new(DataStream::Value(ValueStream::new(
-                decoded_initial_value,
-            )))),
+            self.0.outputs.new_value_stream(decoded_initial_value),
         )?))
     }
 
@@ -53,7 +47,7 @@ impl StreamingPluginImports for WasiImpl<&mut WasmStorage> {
             name,
             description,
             parse(&data_type)?,
-            Arc::new(RwLock::new(DataStream::List(ListStream::new()))),
+            self.0.outputs.new_list_stream(),
         )?))
     }
 
@@ -67,7 +61,7 @@ impl StreamingPluginImports for WasiImpl<&mut WasmStorage> {
             name,
             description,
             parse(&data_type)?,
-            Arc::new(RwLock::new(DataStream::Tree(TreeStream::new()))),
+            self.0.outputs.new_tree_stream(),
         )?))
     }
 
@@ -89,9 +83,7 @@ impl StreamingPluginImports for WasiImpl<&mut WasmStorage> {
             name,
             description,
             tooltrain_data_type,
-            Arc::new(RwLock::new(DataStream::Value(ValueStream::new(
-                decoded_initial_value,
-            )))),
+            self.0.inputs.new_value_stream(decoded_initial_value),
         )?))
     }
 
@@ -105,7 +97,7 @@ impl StreamingPluginImports for WasiImpl<&mut WasmStorage> {
             name,
             description,
             parse(&data_type)?,
-            Arc::new(RwLock::new(DataStream::List(ListStream::new()))),
+            self.0.inputs.new_list_stream(),
         )?))
     }
 
@@ -119,7 +111,7 @@ impl StreamingPluginImports for WasiImpl<&mut WasmStorage> {
             name,
             description,
             parse(&data_type)?,
-            Arc::new(RwLock::new(DataStream::Tree(TreeStream::new()))),
+            self.0.inputs.new_tree_stream(),
         )?))
     }
 }
@@ -133,3 +125,117 @@ impl crate::bindings::streaming::tooltrain::base::streaming_outputs::Host
     for WasiImpl<&mut WasmStorage>
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bindings::streaming::tooltrain::base::{
+            streaming_inputs::HostListInput, streaming_outputs::HostListOutput,
+        },
+        datastream::DataStreamCapacity,
+        streaming::{Inputs, OutputHandle, Outputs},
+    };
+    use std::collections::BTreeMap;
+    use tooltrain_data::{
+        CommanderEnumDataType, CommanderListDataType, CommanderNumberDataType,
+        CommanderStringDataType, CommanderStructDataType, CommanderStructTypeBuilder,
+        CommanderTypedListDataType, CommanderValue,
+    };
+
+    #[tokio::test]
+    async fn struct_rows_survive_the_list_output_pipeline() {
+        // Like the ls program's "File" rows, but with fields that are not declared in the
+        // alphabetical order of a struct value's keys.
+        let entity_type = CommanderEnumDataType::new(
+            "FileEntityType".to_string(),
+            vec!["FILE".to_string(), "DIRECTORY".to_string()],
+        );
+        let file_type = CommanderStructTypeBuilder::new("File")
+            .add_field_with_description("type", "File or directory", entity_type.clone())
+            .add_field_with_description("size", "The size in bytes", CommanderNumberDataType {})
+            .add_field_with_description("name", "The name of the file", CommanderStringDataType {})
+            .build();
+        let row = |name: &str, size: f64, variant: &str| {
+            BTreeMap::from([
+                ("name".to_string(), name.to_string().into()),
+                ("size".to_string(), size.into()),
+                (
+                    "type".to_string(),
+                    entity_type.get_variant(variant).unwrap().into(),
+                ),
+            ])
+        };
+        let expected = vec![
+            row("a.txt", 2.0, "FILE"),
+            row("b.txt", 5.0, "FILE"),
+            row("sub", 4096.0, "DIRECTORY"),
+        ];
+
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let outputs = storage.outputs.clone();
+        let output = WasiImpl(&mut storage)
+            .add_list_output(
+                "Files".to_string(),
+                "The list of files".to_string(),
+                file_type.type_string(),
+            )
+            .await
+            .unwrap();
+        let output_id = output.rep();
+        for row in &expected {
+            let encoded = file_type.encode(row.clone()).unwrap();
+            HostListOutput::add(
+                &mut WasiImpl(&mut storage),
+                Resource::new_own(output_id),
+                encoded,
+            )
+            .await
+            .unwrap();
+        }
+
+        // The host decodes the rows it stores...
+        let Some(OutputHandle::List(handle)) = Outputs(&outputs).get_handle("Files") else {
+            panic!("Expected a list output handle");
+        };
+        let stored: Vec<CommanderValue> = handle
+            .load(Outputs(&outputs))
+            .value()
+            .unwrap()
+            .into_iter()
+            .map(|row| (*row).clone())
+            .collect();
+        let expected_values: Vec<CommanderValue> = expected
+            .iter()
+            .cloned()
+            .map(CommanderValue::Struct)
+            .collect();
+        assert_eq!(stored, expected_values);
+
+        // ...and re-encodes them for another program reading the list as an input.
+        let mut reader = WasmStorage::new(DataStreamCapacity::default());
+        let inputs = reader.inputs.clone();
+        let _input_changes = inputs.changes();
+        let parsed_type: CommanderStructDataType =
+            parse(&file_type.type_string()).unwrap().try_into().unwrap();
+        let list_type = CommanderListDataType::Struct(CommanderTypedListDataType::new(parsed_type));
+        let input = Inputs(&inputs)
+            .new_generic_list_input(
+                "files".to_string(),
+                "The listed files".to_string(),
+                list_type.clone(),
+            )
+            .unwrap();
+        input
+            .load(Inputs(&inputs))
+            .bind(handle.load(Outputs(&outputs)))
+            .unwrap();
+        let encoded = HostListInput::get(
+            &mut WasiImpl(&mut reader),
+            Resource::new_own(input.metadata.id),
+        )
+        .await
+        .unwrap();
+        assert_eq!(list_type.decode(&encoded).unwrap(), expected_values);
+    }
+}
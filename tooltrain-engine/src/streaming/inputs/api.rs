@@ -1,22 +1,28 @@
-use std::{collections::BTreeMap, marker::PhantomData, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    marker::PhantomData,
+    path::Path,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
 
+use notify::{EventKind, RecursiveMode, Watcher};
 use tooltrain_data::{
-    CommanderCoder, CommanderDataType, CommanderListDataType, CommanderTypedListDataType,
-    CommanderValue,
+    CommanderCoder, CommanderDataType, CommanderListDataType, CommanderStringDataType,
+    CommanderTriggerDataType, CommanderTypedListDataType, CommanderValue,
 };
-use parking_lot::RwLock;
 use tokio_stream::{once, wrappers::BroadcastStream, Stream, StreamExt};
 use wasmtime::component::Resource;
 
 use crate::{
     bindings,
-    datastream::{DataStream, DataStreamSnapshot, ListStream, ValueStream},
+    datastream::{DataStreamSnapshot, TreeNode, TreeStreamNode},
     streaming::{
         storage::{DataStreamMetadata, DataStreamResourceChange, DataStreamType, ResourceId},
-        DataStreamStorage, ListOutputRef, OutputRef, ValueOutputRef,
+        DataStreamStorage, ListOutputRef, OutputRef, TreeOutputRef, ValueOutputRef,
     },
 };
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 
 #[derive(Clone, Debug)]
 pub struct ValueInputHandle<ValueType: CommanderCoder> {
@@ -39,8 +45,9 @@ impl<ValueType: CommanderCoder> ValueInputHandle<ValueType> {
         }
     }
 
-    pub fn downcast<T: CommanderCoder>(&self) -> ValueInputHandle<T>
+    pub fn downcast<T>(&self) -> ValueInputHandle<T>
     where
+        T: CommanderCoder,
         T: Into<ValueType>,
     {
         ValueInputHandle {
@@ -57,6 +64,35 @@ pub struct ValueInputRef<'a, ValueType: CommanderCoder> {
     _phantom: PhantomData<ValueType>,
 }
 
+impl<ValueType: CommanderCoder> ValueInputRef<'_, ValueType> {
+    /// The input's current value, which is also what the program reads. For bound inputs this is
+    /// the value of the output the input is bound to.
+    pub fn value(&self) -> Result<Option<Arc<CommanderValue>>, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_value()?
+            .snapshot())
+    }
+
+    pub fn value_stream(
+        &self,
+    ) -> Result<impl Stream<Item = Option<Arc<CommanderValue>>> + '_, Error> {
+        let changes = BroadcastStream::new(
+            self.storage
+                .get(self.id)?
+                .stream
+                .read()
+                .try_get_value()?
+                .subscribe(),
+        )
+        .filter_map(Result::ok);
+        Ok(once(self.value()?).chain(changes.map_while(|_| self.value().ok())))
+    }
+}
+
 impl<'a, ValueType: CommanderCoder> ValueInputRef<'a, ValueType>
 where
     ValueType::Value: Into<CommanderValue>,
@@ -72,7 +108,33 @@ where
 
     pub fn bind(&self, from: ValueOutputRef<'_>) -> Result<(), Error> {
         self.storage
-            .change_data_stream(self.id, from.inner_data_stream()?)
+            .change_data_stream(self.id, from.inner_data_stream()?, &from.source())
+    }
+}
+
+impl ValueInputRef<'_, CommanderDataType> {
+    /// Sets a value whose type is only known at runtime, such as a value read from another
+    /// output's snapshot. Fails if the value does not match the input's data type.
+    pub fn set_value(&self, value: CommanderValue) -> Result<(), Error> {
+        let resource = self.storage.get(self.id)?;
+        let data_type = &resource.metadata.data_type;
+        data_type.encode(value.clone()).map_err(|error| {
+            anyhow!(
+                "Value does not match input type {}: {}",
+                data_type.type_string(),
+                error
+            )
+        })?;
+        let mut stream = resource.stream.write();
+        stream.try_get_value_mut()?.set(value)
+    }
+}
+
+impl ValueInputRef<'_, CommanderTriggerDataType> {
+    /// Fires the trigger. The program is notified of every fire, even though a trigger's value
+    /// never changes.
+    pub fn fire(&self) -> Result<(), Error> {
+        self.set(PhantomData)
     }
 }
 
@@ -97,8 +159,9 @@ impl<ValueType: CommanderCoder> ListInputHandle<ValueType> {
         }
     }
 
-    pub fn downcast<T: CommanderCoder>(&self) -> ListInputHandle<T>
+    pub fn downcast<T>(&self) -> ListInputHandle<T>
     where
+        T: CommanderCoder,
         T: Into<ValueType>,
     {
         ListInputHandle {
@@ -128,9 +191,104 @@ where
             .add(value.into())
     }
 
+    /// Asks whoever feeds this input for up to `limit` more rows. Returns `false` if the list
+    /// has no more pages to load.
+    pub fn request_more(&self, limit: u32) -> Result<bool, Error> {
+        self.storage
+            .get(self.id)?
+            .stream
+            .write()
+            .try_get_list_mut()?
+            .request_page(limit)
+    }
+
     pub fn bind(&self, from: ListOutputRef<'_>) -> Result<(), Error> {
         self.storage
-            .change_data_stream(self.id, from.inner_data_stream()?)
+            .change_data_stream(self.id, from.inner_data_stream()?, &from.source())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeInputHandle<ValueType: CommanderCoder> {
+    pub metadata: DataStreamMetadata,
+    value_type: std::marker::PhantomData<ValueType>,
+}
+
+impl<ValueType: CommanderCoder> TreeInputHandle<ValueType> {
+    pub(crate) fn as_input_binding(&self) -> bindings::streaming_inputs::Input {
+        let value_resource: Resource<bindings::streaming_inputs::TreeInput> =
+            Resource::new_own(self.metadata.id);
+        bindings::streaming_inputs::Input::TreeInput(value_resource)
+    }
+
+    pub fn load<'a>(&self, from_storage: Inputs<'a>) -> TreeInputRef<'a, ValueType> {
+        TreeInputRef {
+            storage: from_storage.0,
+            id: self.metadata.id,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn downcast<T>(&self) -> TreeInputHandle<T>
+    where
+        T: CommanderCoder,
+        T: Into<ValueType>,
+    {
+        TreeInputHandle {
+            metadata: self.metadata.clone(),
+            value_type: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TreeInputRef<'a, ValueType: CommanderCoder> {
+    storage: &'a DataStreamStorage,
+    id: ResourceId,
+    _phantom: PhantomData<ValueType>,
+}
+
+impl<'a, ValueType: CommanderCoder> TreeInputRef<'a, ValueType> {
+    pub fn add(&self, parent: Option<String>, children: Vec<TreeNode>) -> Result<(), Error> {
+        self.storage
+            .get(self.id)?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .add(parent, children)
+    }
+
+    pub fn remove(&self, id: String) -> Result<(), Error> {
+        self.storage
+            .get(self.id)?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .remove(id)
+    }
+
+    pub fn request_children(&self, parent: String) -> Result<bool, Error> {
+        self.storage
+            .get(self.id)?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .request_children(parent)
+    }
+
+    pub fn subtree_value(&self, parent_id: &str) -> Result<Vec<TreeStreamNode>, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_tree()?
+            .subtree_snapshot(parent_id))
+    }
+
+    pub fn bind(&self, from: TreeOutputRef<'_>) -> Result<(), Error> {
+        self.storage
+            .change_data_stream(self.id, from.inner_data_stream()?, &from.source())
     }
 }
 
@@ -138,6 +296,7 @@ where
 pub enum InputHandle {
     Value(ValueInputHandle<CommanderDataType>),
     List(ListInputHandle<CommanderDataType>),
+    Tree(TreeInputHandle<CommanderDataType>),
 }
 
 impl InputHandle {
@@ -151,21 +310,31 @@ impl InputHandle {
                 metadata,
                 value_type: PhantomData,
             }),
-            _ => unimplemented!(),
+            DataStreamType::Tree => InputHandle::Tree(TreeInputHandle::<CommanderDataType> {
+                metadata,
+                value_type: PhantomData,
+            }),
         }
     }
 
-    fn metadata(&self) -> &DataStreamMetadata {
+    pub(crate) fn metadata(&self) -> &DataStreamMetadata {
         match self {
             InputHandle::Value(handle) => &handle.metadata,
             InputHandle::List(handle) => &handle.metadata,
+            InputHandle::Tree(handle) => &handle.metadata,
         }
     }
 
+    /// Removes this input from `from_storage`. Returns `false` if it was already removed.
+    pub fn remove(&self, from_storage: Inputs<'_>) -> Result<bool, Error> {
+        from_storage.remove(self.metadata().id)
+    }
+
     pub(crate) fn as_input_binding(&self) -> bindings::streaming_inputs::Input {
         match self {
             InputHandle::Value(handle) => handle.as_input_binding(),
             InputHandle::List(handle) => handle.as_input_binding(),
+            InputHandle::Tree(handle) => handle.as_input_binding(),
         }
     }
 }
@@ -181,7 +350,7 @@ pub struct Inputs<'a>(pub(crate) &'a DataStreamStorage);
 impl<'a> Inputs<'a> {
     pub fn updates(&self) -> impl Stream<Item = InputChange> + '_ {
         BroadcastStream::from(self.0.changes())
-            .map_while(|result| result.ok())
+            .filter_map(Result::ok)
             .filter_map(|internal_change| match internal_change {
                 DataStreamResourceChange::Added(metadata) => {
                     Some(InputChange::Added(InputHandle::from_metadata(metadata)))
@@ -204,11 +373,7 @@ impl<'a> Inputs<'a> {
     }
 
     pub fn values(&self) -> BTreeMap<ResourceId, DataStreamSnapshot> {
-        self.0
-            .state()
-            .iter()
-            .map(|(id, spec)| (*id, spec.stream.read().snapshot()))
-            .collect()
+        self.0.snapshots()
     }
 
     pub fn get_handle(&self, input_name: &str) -> Option<InputHandle> {
@@ -217,6 +382,12 @@ impl<'a> Inputs<'a> {
             .find(|handle| handle.metadata().name == input_name)
     }
 
+    /// Removes the input with the given id, notifying [`Inputs::updates`] subscribers. Returns
+    /// `false` if no such input exists.
+    pub fn remove(&self, id: ResourceId) -> Result<bool, Error> {
+        self.0.remove(id)
+    }
+
     pub fn new_value_input<ValueType>(
         &self,
         name: String,
@@ -233,9 +404,7 @@ impl<'a> Inputs<'a> {
             name,
             description,
             data_type.into(),
-            Arc::new(RwLock::new(DataStream::Value(ValueStream::new(
-                initial_value.map(|v| v.into()),
-            )))),
+            self.0.new_value_stream(initial_value.map(|v| v.into())),
         )?;
         Ok(ValueInputHandle {
             metadata: self.0.get(resource_id).unwrap().metadata.clone(),
@@ -243,6 +412,89 @@ impl<'a> Inputs<'a> {
         })
     }
 
+    /// Creates a trigger input that fires every `period`, so a program can refresh on a schedule
+    /// without busy-looping. Must be called from within a Tokio runtime, which drives the timer.
+    /// The timer stops once the input is removed or rebound.
+    pub fn new_interval_input(
+        &self,
+        name: String,
+        description: String,
+        period: Duration,
+    ) -> Result<ValueInputHandle<CommanderTriggerDataType>, Error> {
+        let handle = self.new_value_input(name, description, CommanderTriggerDataType {}, None)?;
+        let stream = Arc::downgrade(&self.0.get(handle.metadata.id)?.stream);
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                let Some(stream) = stream.upgrade() else {
+                    break;
+                };
+                let tick = stream
+                    .write()
+                    .try_get_value_mut()
+                    .and_then(|value| value.set(CommanderValue::Trigger(PhantomData)));
+                if tick.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(handle)
+    }
+
+    /// Creates a list input that receives the path of every file that changes under `path`, so
+    /// a program can react to changes instead of polling the filesystem. The directory is
+    /// watched by the host, but as the input reveals its contents, it has to be inside one of the
+    /// directories the engine's [`crate::streaming::WasiConfig`] lets programs read. Watching
+    /// stops once the input is removed or rebound.
+    pub fn new_fs_watch_input(
+        &self,
+        name: String,
+        description: String,
+        path: &Path,
+    ) -> Result<ListInputHandle<CommanderStringDataType>, Error> {
+        self.0.check_preopened(path)?;
+        let (event_sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(event_sender)?;
+        watcher.watch(path, RecursiveMode::Recursive)?;
+
+        let handle = self.new_list_input(
+            name,
+            description,
+            CommanderTypedListDataType::new(CommanderStringDataType {}),
+        )?;
+        let stream = Arc::downgrade(&self.0.get(handle.metadata.id)?.stream);
+        std::thread::spawn(move || {
+            // The watcher stops watching when dropped, so this thread owns it.
+            let _watcher = watcher;
+            loop {
+                let event = match events.recv_timeout(Duration::from_millis(500)) {
+                    Ok(Ok(event)) => Some(event),
+                    Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                };
+                let Some(stream) = stream.upgrade() else {
+                    break;
+                };
+                let Some(event) = event.filter(|e| !matches!(e.kind, EventKind::Access(_))) else {
+                    continue;
+                };
+                let mut stream = stream.write();
+                let Ok(list) = stream.try_get_list_mut() else {
+                    break;
+                };
+                for changed_path in event.paths {
+                    let _ = list.add(CommanderValue::String(
+                        changed_path.to_string_lossy().to_string(),
+                    ));
+                }
+            }
+        });
+        Ok(handle)
+    }
+
     pub fn new_list_input<V: CommanderCoder + 'static>(
         &self,
         name: String,
@@ -256,7 +508,7 @@ impl<'a> Inputs<'a> {
             name,
             description,
             CommanderDataType::List(data_type.into()),
-            Arc::new(RwLock::new(DataStream::List(ListStream::new()))),
+            self.0.new_list_stream(),
         )?;
         Ok(ListInputHandle {
             metadata: self.0.get(resource_id).unwrap().metadata.clone(),
@@ -273,8 +525,8 @@ impl<'a> Inputs<'a> {
         let resource_id = self.0.add(
             name,
             description,
-            CommanderDataType::List(data_type.into()),
-            Arc::new(RwLock::new(DataStream::List(ListStream::new()))),
+            CommanderDataType::List(data_type),
+            self.0.new_list_stream(),
         )?;
         Ok(ListInputHandle {
             metadata: self.0.get(resource_id).unwrap().metadata.clone(),
@@ -282,6 +534,29 @@ impl<'a> Inputs<'a> {
         })
     }
 
+    /// Creates a tree input whose nodes hold values of `data_type`.
+    pub fn new_tree_input<ValueType>(
+        &self,
+        name: String,
+        description: String,
+        data_type: ValueType,
+    ) -> Result<TreeInputHandle<ValueType>, Error>
+    where
+        ValueType: CommanderCoder,
+        ValueType: Into<CommanderDataType>,
+    {
+        let resource_id = self.0.add(
+            name,
+            description,
+            data_type.into(),
+            self.0.new_tree_stream(),
+        )?;
+        Ok(TreeInputHandle {
+            metadata: self.0.get(resource_id).unwrap().metadata.clone(),
+            value_type: PhantomData,
+        })
+    }
+
     pub fn bind_input<ValueType, O: OutputRef>(
         &self,
         name: String,
@@ -294,14 +569,371 @@ impl<'a> Inputs<'a> {
         ValueType: Into<CommanderDataType>,
         ValueType::Value: Into<CommanderValue>,
     {
+        let source = from.source();
+        self.0.check_binding(&source)?;
         let resource_id = self.0.add(
             name,
             description,
             data_type.into(),
             from.inner_data_stream()?.clone(),
         )?;
+        self.0.record_binding(resource_id, &source);
         Ok(InputHandle::from_metadata(
             self.0.get(resource_id).unwrap().metadata.clone(),
         ))
     }
+
+    /// Like [`Inputs::bind_input`], but the input gets a copy of the output's current data and
+    /// does not follow any later changes. Used for arguments that a program only reads once.
+    pub fn snapshot_input<ValueType, O: OutputRef>(
+        &self,
+        name: String,
+        description: String,
+        data_type: ValueType,
+        from: O,
+    ) -> Result<InputHandle, Error>
+    where
+        ValueType: CommanderCoder,
+        ValueType: Into<CommanderDataType>,
+        ValueType::Value: Into<CommanderValue>,
+    {
+        let snapshot = from.inner_data_stream()?.read().snapshot();
+        let resource_id = self.0.add(
+            name,
+            description,
+            data_type.into(),
+            self.0.new_stream_from_snapshot(snapshot)?,
+        )?;
+        Ok(InputHandle::from_metadata(
+            self.0.get(resource_id).unwrap().metadata.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::ValueChange;
+    use tooltrain_data::{CommanderNumberDataType, CommanderPathDataType};
+
+    #[test]
+    fn sets_dynamically_typed_values() {
+        let storage = DataStreamStorage::default();
+        let inputs = Inputs(&storage);
+        inputs
+            .new_value_input(
+                "count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {},
+                None,
+            )
+            .unwrap();
+        let Some(InputHandle::Value(handle)) = inputs.get_handle("count") else {
+            panic!("Expected a value input handle");
+        };
+        let input = handle.load(Inputs(&storage));
+
+        input.set_value(CommanderValue::Number(3.0)).unwrap();
+        assert!(input
+            .set_value(CommanderValue::String("three".to_string()))
+            .is_err());
+
+        let stream = storage.get(handle.metadata.id).unwrap().stream.clone();
+        let value = stream.read().try_get_value().unwrap().snapshot();
+        assert_eq!(value.as_deref(), Some(&CommanderValue::Number(3.0)));
+    }
+
+    #[tokio::test]
+    async fn reads_back_value_inputs() {
+        let storage = DataStreamStorage::default();
+        let inputs = Inputs(&storage);
+        let handle = inputs
+            .new_value_input(
+                "count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {},
+                Some(1.0),
+            )
+            .unwrap();
+        let input = handle.load(Inputs(&storage));
+        assert_eq!(
+            input.value().unwrap().as_deref(),
+            Some(&CommanderValue::Number(1.0))
+        );
+
+        let mut values = Box::pin(input.value_stream().unwrap());
+        assert_eq!(
+            values.next().await.unwrap().as_deref(),
+            Some(&CommanderValue::Number(1.0))
+        );
+        input.set(2.0).unwrap();
+        assert_eq!(
+            values.next().await.unwrap().as_deref(),
+            Some(&CommanderValue::Number(2.0))
+        );
+        assert_eq!(
+            input.value().unwrap().as_deref(),
+            Some(&CommanderValue::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn creates_tree_inputs() {
+        let storage = DataStreamStorage::default();
+        let inputs = Inputs(&storage);
+        let handle = inputs
+            .new_tree_input(
+                "files".to_string(),
+                "A directory tree".to_string(),
+                CommanderPathDataType {},
+            )
+            .unwrap();
+        assert!(matches!(
+            handle.metadata.data_stream_type,
+            DataStreamType::Tree
+        ));
+        assert!(matches!(
+            inputs.get_handle("files"),
+            Some(InputHandle::Tree(_))
+        ));
+
+        let root = CommanderPathDataType {}
+            .encode(std::path::PathBuf::from("/home"))
+            .unwrap();
+        handle
+            .load(Inputs(&storage))
+            .add(
+                None,
+                vec![TreeNode {
+                    id: "/home".to_string(),
+                    value: root.clone(),
+                    has_children: false,
+                    label: None,
+                }],
+            )
+            .unwrap();
+
+        let DataStreamSnapshot::Tree(snapshot) =
+            inputs.values().remove(&handle.metadata.id).unwrap()
+        else {
+            panic!("Expected a tree snapshot");
+        };
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].value.id, "/home");
+        assert_eq!(snapshot[0].value.value, root);
+    }
+
+    #[test]
+    fn pushes_nodes_through_tree_input() {
+        let storage = DataStreamStorage::default();
+        storage
+            .add(
+                "tree".to_string(),
+                "A tree input".to_string(),
+                CommanderPathDataType {}.into(),
+                storage.new_tree_stream(),
+            )
+            .unwrap();
+
+        let inputs = Inputs(&storage);
+        let Some(InputHandle::Tree(handle)) = inputs.get_handle("tree") else {
+            panic!("Expected a tree input handle");
+        };
+        let tree_input = handle.load(Inputs(&storage));
+        tree_input
+            .add(
+                None,
+                vec![TreeNode {
+                    id: "root".to_string(),
+                    value: vec![],
+                    has_children: true,
+                    label: None,
+                }],
+            )
+            .unwrap();
+        tree_input
+            .add(
+                Some("root".to_string()),
+                vec![TreeNode {
+                    id: "root/child".to_string(),
+                    value: vec![],
+                    has_children: false,
+                    label: None,
+                }],
+            )
+            .unwrap();
+        let mut child_requests = storage
+            .get(handle.metadata.id)
+            .unwrap()
+            .stream
+            .write()
+            .try_get_tree_mut()
+            .unwrap()
+            .get_request_children_stream();
+        assert!(tree_input.request_children("root".to_string()).unwrap());
+        assert_eq!(child_requests.try_recv().unwrap(), "root");
+
+        let DataStreamSnapshot::Tree(snapshot) =
+            inputs.values().remove(&handle.metadata.id).unwrap()
+        else {
+            panic!("Expected a tree snapshot");
+        };
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].value.id, "root");
+        assert_eq!(snapshot[0].children[0].value.id, "root/child");
+        assert!(matches!(
+            handle.as_input_binding(),
+            bindings::streaming_inputs::Input::TreeInput(_)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interval_input_ticks_on_schedule() {
+        let storage = DataStreamStorage::default();
+        let handle = Inputs(&storage)
+            .new_interval_input(
+                "refresh".to_string(),
+                "Refreshes the feed".to_string(),
+                Duration::from_secs(10),
+            )
+            .unwrap();
+        let mut ticks = storage
+            .get(handle.metadata.id)
+            .unwrap()
+            .stream
+            .read()
+            .try_get_value()
+            .unwrap()
+            .subscribe();
+
+        tokio::time::sleep(Duration::from_secs(35)).await;
+        let mut count = 0;
+        while let Ok(change) = ticks.try_recv() {
+            assert!(matches!(
+                change,
+                ValueChange::Set(value) if *value == CommanderValue::Trigger(PhantomData)
+            ));
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn fs_watch_input_reports_new_files() {
+        let directory =
+            std::env::temp_dir().join(format!("tooltrain-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let storage = DataStreamStorage::default();
+        let handle = Inputs(&storage)
+            .new_fs_watch_input(
+                "changes".to_string(),
+                "Changed files".to_string(),
+                &directory,
+            )
+            .unwrap();
+
+        let file = directory.join("new.txt");
+        std::fs::write(&file, "hello").unwrap();
+
+        let reported = |storage: &DataStreamStorage| {
+            let Some(DataStreamSnapshot::List(rows)) =
+                Inputs(storage).values().remove(&handle.metadata.id)
+            else {
+                panic!("Expected a list snapshot");
+            };
+            rows.iter()
+                .any(|row| **row == CommanderValue::String(file.to_string_lossy().to_string()))
+        };
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !reported(&storage) && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(reported(&storage));
+    }
+
+    #[test]
+    fn fs_watch_input_only_watches_preopened_dirs() {
+        let directory =
+            std::env::temp_dir().join(format!("tooltrain-watch-preopened-{}", std::process::id()));
+        let readable = directory.join("readable");
+        let hidden = directory.join("hidden");
+        std::fs::create_dir_all(&readable).unwrap();
+        std::fs::create_dir_all(&hidden).unwrap();
+        let storage = DataStreamStorage::default();
+        storage.set_preopened_dirs(Some(vec![readable.clone()]));
+        let inputs = Inputs(&storage);
+
+        let hidden_watch =
+            inputs.new_fs_watch_input("hidden".to_string(), "Changed files".to_string(), &hidden);
+        let escaping_watch = inputs.new_fs_watch_input(
+            "escaping".to_string(),
+            "Changed files".to_string(),
+            &readable.join("..").join("hidden"),
+        );
+        let readable_watch = inputs.new_fs_watch_input(
+            "readable".to_string(),
+            "Changed files".to_string(),
+            &readable,
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(hidden_watch.is_err());
+        assert!(escaping_watch.is_err());
+        assert!(readable_watch.is_ok());
+        assert!(inputs.get_handle("hidden").is_none());
+    }
+
+    #[tokio::test]
+    async fn removing_an_input_notifies_subscribers() {
+        let storage = DataStreamStorage::default();
+        let inputs = Inputs(&storage);
+        let handle = inputs
+            .new_value_input(
+                "count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {},
+                Some(1.0),
+            )
+            .unwrap();
+        let Some(input) = inputs.get_handle("count") else {
+            panic!("Expected the input to exist");
+        };
+        let mut updates = Box::pin(inputs.updates());
+
+        assert!(input.remove(Inputs(&storage)).unwrap());
+        assert!(matches!(
+            updates.next().await,
+            Some(InputChange::Removed(id)) if id == handle.metadata.id
+        ));
+        assert!(inputs.handles().is_empty());
+        assert!(!inputs.remove(handle.metadata.id).unwrap());
+    }
+
+    #[test]
+    fn requests_more_rows_for_list_input() {
+        let storage = DataStreamStorage::default();
+        let inputs = Inputs(&storage);
+        let handle = inputs
+            .new_generic_list_input(
+                "list".to_string(),
+                "A list input".to_string(),
+                CommanderListDataType::Number(CommanderTypedListDataType::new(
+                    CommanderNumberDataType {},
+                )),
+            )
+            .unwrap();
+        let list_input = handle.load(Inputs(&storage));
+        assert!(!list_input.request_more(10).unwrap());
+
+        let mut page_requests = {
+            let resource = storage.get(handle.metadata.id).unwrap();
+            let mut stream = resource.stream.write();
+            let list = stream.try_get_list_mut().unwrap();
+            list.set_has_more_rows(true).unwrap();
+            list.get_page_request_stream()
+        };
+        assert!(list_input.request_more(10).unwrap());
+        assert_eq!(page_requests.try_recv().unwrap(), 10);
+    }
 }
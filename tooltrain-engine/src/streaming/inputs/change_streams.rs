@@ -1,13 +1,14 @@
-use std::pin::Pin;
+use std::{pin::Pin, sync::Arc};
 
 use anyhow::{anyhow, Error};
 use tooltrain_data::{CommanderCoder, CommanderDataType};
 use futures::FutureExt;
+use parking_lot::RwLock;
 use tokio_stream::{Stream, StreamExt};
 
 use crate::{
-    bindings::streaming_inputs::{ListChange, TreeChange},
-    datastream::DataStreamSnapshot,
+    bindings::streaming_inputs::{ListChange, TreeChange, ValueChange},
+    datastream::{DataStream, DataStreamSnapshot},
     streaming::{storage::DataStreamResourceChange, DataStreamStorage},
 };
 
@@ -18,46 +19,73 @@ pub(super) trait ReplacementChangeFromDataStreamSnapshot: Sized {
     ) -> Result<Self, Error>;
 }
 
+pub(super) type ChangeStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
+
+/// Subscribes to the changes of an input's data stream, given the stream and its read guard.
+pub(super) type ChangeSubscriber<T> =
+    Box<dyn Fn(&Arc<RwLock<DataStream>>, &DataStream) -> Result<ChangeStream<T>, Error> + Send>;
+
 pub(super) struct InputChangeStream<T: Clone + ReplacementChangeFromDataStreamSnapshot> {
     pub(super) input_id: u32,
-    stream_changes: Pin<Box<dyn Stream<Item = T> + Send>>,
+    stream_changes: ChangeStream<T>,
     resource_changes: Pin<Box<dyn Stream<Item = DataStreamResourceChange> + Send>>,
+    /// Resubscribes once the input is rebound to a different data stream.
+    subscribe: ChangeSubscriber<T>,
 }
 impl<T: Clone + ReplacementChangeFromDataStreamSnapshot> InputChangeStream<T> {
     pub(super) fn new(
         input_id: u32,
-        stream_changes: Pin<Box<dyn Stream<Item = T> + Send>>,
+        stream_changes: ChangeStream<T>,
         resource_changes: Pin<Box<dyn Stream<Item = DataStreamResourceChange> + Send>>,
+        subscribe: ChangeSubscriber<T>,
     ) -> Self {
         Self {
             input_id,
             stream_changes,
             resource_changes,
+            subscribe,
         }
     }
 
-    pub fn poll_change(&mut self) -> Result<Option<T>, Error> {
+    pub fn poll_change(&mut self, storage: DataStreamStorage) -> Result<Option<T>, Error> {
+        if let Some(Some(resource_change)) = self.resource_changes.next().now_or_never() {
+            return self.replace_stream(storage, resource_change).map(Some);
+        }
         Ok(self.stream_changes.next().now_or_never().flatten())
     }
 
     pub async fn poll_change_blocking(&mut self, storage: DataStreamStorage) -> Result<T, Error> {
         tokio::select! {
-            stream_change = self.stream_changes.next() => {
-                stream_change.ok_or(anyhow!("Stream ended unexpectedly"))
-            }
+            // Rebinding drops the old data stream, which ends its changes, so the rebind has to
+            // be handled first.
+            biased;
             resource_change_optional = self.resource_changes.next() => {
                 let resource_change = resource_change_optional.ok_or(anyhow!("Resource stream ended unexpectedly"))?;
-                assert!(resource_change.is_data_stream_changed());
-                assert_eq!(resource_change.unwrap_data_stream_changed(), self.input_id);
-                let input = storage.get(self.input_id)?;
-                let snapshot = input.stream.read().snapshot();
-                T::replace_from_snapshot(&snapshot,&input.metadata.data_type)
+                self.replace_stream(storage, resource_change)
+            }
+            stream_change = self.stream_changes.next() => {
+                stream_change.ok_or(anyhow!("Input data stream was destroyed"))
             }
         }
     }
+
+    /// Follows the input to the data stream it was rebound to, returning a change that replaces
+    /// the program's view of the input with the new stream's data.
+    fn replace_stream(
+        &mut self,
+        storage: DataStreamStorage,
+        resource_change: DataStreamResourceChange,
+    ) -> Result<T, Error> {
+        assert!(resource_change.is_data_stream_changed());
+        assert_eq!(resource_change.unwrap_data_stream_changed(), self.input_id);
+        let input = storage.get(self.input_id)?;
+        let stream = input.stream.read();
+        self.stream_changes = (self.subscribe)(&input.stream, &stream)?;
+        T::replace_from_snapshot(&stream.snapshot(), &input.metadata.data_type)
+    }
 }
 
-impl ReplacementChangeFromDataStreamSnapshot for Option<Vec<u8>> {
+impl ReplacementChangeFromDataStreamSnapshot for ValueChange {
     fn replace_from_snapshot(
         snapshot: &DataStreamSnapshot,
         data_type: &CommanderDataType,
@@ -66,7 +94,8 @@ impl ReplacementChangeFromDataStreamSnapshot for Option<Vec<u8>> {
             DataStreamSnapshot::Value(maybe_value) => maybe_value
                 .as_deref()
                 .map(|value| data_type.encode(value.clone()))
-                .transpose(),
+                .transpose()
+                .map(ValueChange::Set),
             _ => Err(anyhow!(
                 "Value Change can only be created from Value snapshot"
             )),
@@ -10,14 +10,19 @@ use crate::bindings::streaming::{ListInput, TreeInput};
 use crate::bindings::streaming_inputs::{
     HostListChangeStream, HostListInput, HostTreeChangeStream, HostTreeInput,
     HostValueChangeStream, HostValueInput, ListChange, ListChangeStream, TreeChange,
-    TreeChangeStream, TreeNode, ValueChangeStream, ValueInput,
+    TreeChangeStream, TreeNode, ValueChange, ValueChangeStream, ValueInput,
 };
 use crate::datastream;
+use crate::streaming::inputs::change_streams::ChangeSubscriber;
 use crate::streaming::storage::DataStreamResourceChange;
-use crate::streaming::WasmStorage;
+use crate::streaming::{DataStreamStorage, WasmStorage};
 
 #[async_trait]
 impl HostValueInput for WasiImpl<&mut WasmStorage> {
+    async fn name(&mut self, resource: Resource<ValueInput>) -> Result<String, Error> {
+        Ok(self.0.inputs.get(resource.rep())?.metadata.name.clone())
+    }
+
     async fn get(&mut self, resource: Resource<ValueInput>) -> Result<Option<Vec<u8>>, Error> {
         let data_stream_resource = self.0.inputs.get(resource.rep())?;
         let data_type = &data_stream_resource.metadata.data_type;
@@ -37,11 +42,10 @@ impl HostValueInput for WasiImpl<&mut WasmStorage> {
         resource: Resource<ValueInput>,
     ) -> Result<Resource<ValueChangeStream>, Error> {
         let data_stream_resource = self.0.inputs.get(resource.rep())?;
-        let data_type = data_stream_resource.metadata.data_type.clone();
         let resource_rep = resource.rep();
 
         let data_stream_change_stream = BroadcastStream::new(self.0.inputs.changes())
-            .map_while(|result| result.ok())
+            .filter_map(Result::ok)
             .filter(|change| change.is_data_stream_changed())
             .filter(move |change| {
                 let DataStreamResourceChange::DataStreamChanged(changed_resource_id) = change
@@ -51,24 +55,20 @@ impl HostValueInput for WasiImpl<&mut WasmStorage> {
                 *changed_resource_id == resource_rep
             });
 
-        let value_stream = BroadcastStream::new(
-            data_stream_resource
-                .stream
-                .read()
-                .try_get_value()?
-                .subscribe(),
-        )
-        .filter_map(Result::ok)
-        .filter_map(move |change| match change {
-            datastream::ValueChange::Set(value) => Some(data_type.encode((*value).clone()).ok()),
-            datastream::ValueChange::Destroy => None,
-        });
+        let subscribe = value_change_subscriber(data_stream_resource.metadata.data_type.clone());
+        let value_stream = subscribe(
+            &data_stream_resource.stream,
+            &data_stream_resource.stream.read(),
+        )?;
+        drop(data_stream_resource);
 
         Ok(Resource::new_own(
             self.0.input_streams.value_streams.add_stream(
+                &self.0.inputs,
                 resource_rep,
                 value_stream,
                 data_stream_change_stream,
+                subscribe,
             ),
         ))
     }
@@ -88,6 +88,10 @@ impl HostValueInput for WasiImpl<&mut WasmStorage> {
 
 #[async_trait]
 impl HostListInput for WasiImpl<&mut WasmStorage> {
+    async fn name(&mut self, resource: Resource<ListInput>) -> Result<String, Error> {
+        Ok(self.0.inputs.get(resource.rep())?.metadata.name.clone())
+    }
+
     async fn get(&mut self, resource: Resource<ListInput>) -> Result<Vec<u8>, Error> {
         let data_stream_resource = self.0.inputs.get(resource.rep())?;
         let data_type = &data_stream_resource.metadata.data_type;
@@ -128,11 +132,10 @@ impl HostListInput for WasiImpl<&mut WasmStorage> {
         resource: Resource<ListInput>,
     ) -> Result<Resource<ListChangeStream>, Error> {
         let data_stream_resource = self.0.inputs.get(resource.rep())?;
-        let data_type = data_stream_resource.metadata.data_type.clone();
         let resource_rep = resource.rep();
 
         let data_stream_change_stream = BroadcastStream::new(self.0.inputs.changes())
-            .map_while(|result| result.ok())
+            .filter_map(Result::ok)
             .filter(|change| change.is_data_stream_changed())
             .filter(move |change| {
                 let DataStreamResourceChange::DataStreamChanged(changed_resource_id) = change
@@ -142,31 +145,20 @@ impl HostListInput for WasiImpl<&mut WasmStorage> {
                 *changed_resource_id == resource_rep
             });
 
-        let list_change_stream = BroadcastStream::new(
-            data_stream_resource
-                .stream
-                .read()
-                .try_get_list()?
-                .subscribe(),
-        )
-        .filter_map(Result::ok)
-        .map(
-            move |data_stream_list_change| match data_stream_list_change {
-                datastream::ListChange::Add(v) => {
-                    ListChange::Append(data_type.encode((*v).clone()).unwrap())
-                }
-                datastream::ListChange::Pop(_) => ListChange::Pop,
-                datastream::ListChange::HasMorePages(_) => todo!(),
-                datastream::ListChange::Clear => ListChange::Replace(vec![]),
-                datastream::ListChange::Destroy => todo!(),
-            },
-        );
+        let subscribe = list_change_subscriber(data_stream_resource.metadata.data_type.clone());
+        let list_change_stream = subscribe(
+            &data_stream_resource.stream,
+            &data_stream_resource.stream.read(),
+        )?;
+        drop(data_stream_resource);
 
         Ok(Resource::new_own(
             self.0.input_streams.list_streams.add_stream(
-                resource.rep(),
+                &self.0.inputs,
+                resource_rep,
                 list_change_stream,
                 data_stream_change_stream,
+                subscribe,
             ),
         ))
     }
@@ -184,8 +176,113 @@ impl HostListInput for WasiImpl<&mut WasmStorage> {
     }
 }
 
+fn value_change_subscriber(data_type: CommanderDataType) -> ChangeSubscriber<ValueChange> {
+    Box::new(move |_, stream| {
+        let data_type = data_type.clone();
+        Ok(Box::pin(
+            BroadcastStream::new(stream.try_get_value()?.subscribe())
+                .filter_map(Result::ok)
+                .map_while(move |change| match change {
+                    datastream::ValueChange::Set(value) => {
+                        Some(ValueChange::Set(data_type.encode((*value).clone()).ok()))
+                    }
+                    datastream::ValueChange::AppendBytes(chunk) => {
+                        Some(ValueChange::AppendBytes(chunk.to_vec()))
+                    }
+                    datastream::ValueChange::Destroy => None,
+                })
+                .fuse(),
+        ))
+    })
+}
+
+/// Turns a change into the whole value for programs that read whole values. Only appended chunks
+/// need the value assembled so far, which is read from the input when it is needed.
+fn whole_value(
+    inputs: &DataStreamStorage,
+    input_id: u32,
+    change: ValueChange,
+) -> Result<Option<Vec<u8>>, Error> {
+    match change {
+        ValueChange::Set(value) => Ok(value),
+        ValueChange::AppendBytes(_) => {
+            let input = inputs.get(input_id)?;
+            let value = input.stream.read().try_get_value()?.snapshot();
+            value
+                .map(|value| input.metadata.data_type.encode((*value).clone()))
+                .transpose()
+        }
+    }
+}
+
+fn list_change_subscriber(data_type: CommanderDataType) -> ChangeSubscriber<ListChange> {
+    Box::new(move |_, stream| {
+        let data_type = data_type.clone();
+        Ok(Box::pin(
+            BroadcastStream::new(stream.try_get_list()?.subscribe())
+                .filter_map(Result::ok)
+                .map_while(move |change| list_change_to_binding(&data_type, change))
+                .filter_map(Result::ok)
+                .fuse(),
+        ))
+    })
+}
+
+fn tree_change_subscriber() -> ChangeSubscriber<TreeChange> {
+    Box::new(|_, stream| {
+        Ok(Box::pin(
+            BroadcastStream::new(stream.try_get_tree()?.subscribe())
+                .filter_map(Result::ok)
+                .map_while(tree_change_to_binding)
+                .fuse(),
+        ))
+    })
+}
+
+/// Returns `None` once the underlying stream is destroyed, which ends the change stream, and an
+/// error for rows that cannot be encoded, which are skipped.
+fn list_change_to_binding(
+    data_type: &CommanderDataType,
+    change: datastream::ListChange,
+) -> Option<Result<ListChange, Error>> {
+    Some(match change {
+        datastream::ListChange::Add(v) => data_type.encode((*v).clone()).map(ListChange::Append),
+        datastream::ListChange::Insert(index, v) => data_type
+            .encode((*v).clone())
+            .map(|encoded| ListChange::Insert((index as u32, encoded))),
+        datastream::ListChange::RemoveAt(index) => Ok(ListChange::RemoveAt(index as u32)),
+        datastream::ListChange::Pop(_) => Ok(ListChange::Pop),
+        datastream::ListChange::Shift(_) => Ok(ListChange::Shift),
+        datastream::ListChange::HasMorePages(has_more_pages) => {
+            Ok(ListChange::HasMorePages(has_more_pages))
+        }
+        datastream::ListChange::TotalCount(total_count) => Ok(ListChange::TotalCount(total_count)),
+        datastream::ListChange::Clear => Ok(ListChange::Replace(vec![])),
+        datastream::ListChange::Complete | datastream::ListChange::Destroy => return None,
+    })
+}
+
+/// Returns `None` once the underlying stream is destroyed, which ends the change stream.
+fn tree_change_to_binding(change: datastream::TreeChange) -> Option<TreeChange> {
+    Some(match change {
+        datastream::TreeChange::Add {
+            parent: _,
+            children,
+        } => TreeChange::Append(children.iter().map(|a| (**a).clone()).collect()),
+        datastream::TreeChange::Remove(node) => TreeChange::Remove(vec![node.id.clone()]),
+        datastream::TreeChange::Clear => TreeChange::Replace(vec![]),
+        datastream::TreeChange::LoadFailed { .. }
+        | datastream::TreeChange::Complete
+        | datastream::TreeChange::Destroy => return None,
+    })
+}
+
 #[async_trait]
 impl HostTreeInput for WasiImpl<&mut WasmStorage> {
+    async fn name(&mut self, resource: Resource<TreeInput>) -> Result<String, Error> {
+        Ok(self.0.inputs.get(resource.rep())?.metadata.name.clone())
+    }
+
     async fn get(&mut self, resource: Resource<TreeInput>) -> Result<Vec<TreeNode>, Error> {
         Ok(self
             .0
@@ -223,7 +320,7 @@ impl HostTreeInput for WasiImpl<&mut WasmStorage> {
         let resource_rep = resource.rep();
 
         let data_stream_change_stream = BroadcastStream::new(self.0.inputs.changes())
-            .map_while(|result| result.ok())
+            .filter_map(Result::ok)
             .filter(|change| change.is_data_stream_changed())
             .filter(move |change| {
                 let DataStreamResourceChange::DataStreamChanged(changed_resource_id) = change
@@ -233,31 +330,20 @@ impl HostTreeInput for WasiImpl<&mut WasmStorage> {
                 *changed_resource_id == resource_rep
             });
 
-        let tree_change_stream = BroadcastStream::new(
-            data_stream_resource
-                .stream
-                .read()
-                .try_get_tree()?
-                .subscribe(),
-        )
-        .filter_map(Result::ok)
-        .map(
-            move |data_stream_tree_change| match data_stream_tree_change {
-                datastream::TreeChange::Add {
-                    parent: _,
-                    children,
-                } => TreeChange::Append(children.iter().map(|a| (**a).clone()).collect()),
-                datastream::TreeChange::Remove(node) => TreeChange::Remove(vec![node.id.clone()]),
-                datastream::TreeChange::Clear => TreeChange::Replace(vec![]),
-                datastream::TreeChange::Destroy => todo!(),
-            },
-        );
+        let subscribe = tree_change_subscriber();
+        let tree_change_stream = subscribe(
+            &data_stream_resource.stream,
+            &data_stream_resource.stream.read(),
+        )?;
+        drop(data_stream_resource);
 
         Ok(Resource::new_own(
             self.0.input_streams.tree_streams.add_stream(
-                resource.rep(),
+                &self.0.inputs,
+                resource_rep,
                 tree_change_stream,
                 data_stream_change_stream,
+                subscribe,
             ),
         ))
     }
@@ -281,25 +367,47 @@ impl HostValueChangeStream for WasiImpl<&mut WasmStorage> {
         &mut self,
         resource: Resource<ValueChangeStream>,
     ) -> Result<Option<Option<Vec<u8>>>, Error> {
-        self.0
-            .input_streams
-            .value_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Value change stream not found"))?
-            .poll_change()
+        let Some(stream) = self.0.input_streams.value_streams.get_mut(resource.rep())? else {
+            return Ok(None);
+        };
+        let input_id = stream.input_id;
+        stream
+            .poll_change(self.0.inputs.clone())?
+            .map(|change| whole_value(&self.0.inputs, input_id, change))
+            .transpose()
     }
 
     async fn poll_change_blocking(
         &mut self,
         resource: Resource<ValueChangeStream>,
     ) -> Result<Option<Vec<u8>>, Error> {
+        let Some(stream) = self.0.input_streams.value_streams.get_mut(resource.rep())? else {
+            return Err(anyhow!("Input data stream was destroyed"));
+        };
+        let input_id = stream.input_id;
+        let change = stream.poll_change_blocking(self.0.inputs.clone()).await?;
+        whole_value(&self.0.inputs, input_id, change)
+    }
+
+    async fn poll_chunk(
+        &mut self,
+        resource: Resource<ValueChangeStream>,
+    ) -> Result<Option<ValueChange>, Error> {
         self.0
             .input_streams
             .value_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Value change stream not found"))?
-            .poll_change_blocking(self.0.inputs.clone())
-            .await
+            .get_mut(resource.rep())?
+            .map_or(Ok(None), |stream| stream.poll_change(self.0.inputs.clone()))
+    }
+
+    async fn poll_chunk_blocking(
+        &mut self,
+        resource: Resource<ValueChangeStream>,
+    ) -> Result<ValueChange, Error> {
+        let Some(stream) = self.0.input_streams.value_streams.get_mut(resource.rep())? else {
+            return Err(anyhow!("Input data stream was destroyed"));
+        };
+        stream.poll_change_blocking(self.0.inputs.clone()).await
     }
 
     fn drop(&mut self, resource: Resource<ValueChangeStream>) -> Result<(), Error> {
@@ -320,22 +428,18 @@ impl HostListChangeStream for WasiImpl<&mut WasmStorage> {
         self.0
             .input_streams
             .list_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("List change stream not found"))?
-            .poll_change()
+            .get_mut(resource.rep())?
+            .map_or(Ok(None), |stream| stream.poll_change(self.0.inputs.clone()))
     }
 
     async fn poll_change_blocking(
         &mut self,
         resource: Resource<ListChangeStream>,
     ) -> Result<ListChange, Error> {
-        self.0
-            .input_streams
-            .list_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("List change stream not found"))?
-            .poll_change_blocking(self.0.inputs.clone())
-            .await
+        let Some(stream) = self.0.input_streams.list_streams.get_mut(resource.rep())? else {
+            return Err(anyhow!("Input data stream was destroyed"));
+        };
+        stream.poll_change_blocking(self.0.inputs.clone()).await
     }
 
     fn drop(&mut self, resource: Resource<ListChangeStream>) -> Result<(), Error> {
@@ -356,22 +460,18 @@ impl HostTreeChangeStream for WasiImpl<&mut WasmStorage> {
         self.0
             .input_streams
             .tree_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Tree change stream not found"))?
-            .poll_change()
+            .get_mut(resource.rep())?
+            .map_or(Ok(None), |stream| stream.poll_change(self.0.inputs.clone()))
     }
 
     async fn poll_change_blocking(
         &mut self,
         resource: Resource<TreeChangeStream>,
     ) -> Result<TreeChange, Error> {
-        self.0
-            .input_streams
-            .tree_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Tree change stream not found"))?
-            .poll_change_blocking(self.0.inputs.clone())
-            .await
+        let Some(stream) = self.0.input_streams.tree_streams.get_mut(resource.rep())? else {
+            return Err(anyhow!("Input data stream was destroyed"));
+        };
+        stream.poll_change_blocking(self.0.inputs.clone()).await
     }
 
     fn drop(&mut self, resource: Resource<TreeChangeStream>) -> Result<(), Error> {
@@ -382,3 +482,253 @@ impl HostTreeChangeStream for WasiImpl<&mut WasmStorage> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::DataStreamCapacity;
+    use crate::streaming::inputs::change_streams::InputChangeStream;
+    use crate::streaming::{DataStreamStorage, Inputs};
+    use std::sync::Arc;
+    use tooltrain_data::{
+        CommanderBytesDataType, CommanderNumberDataType, CommanderTriggerDataType,
+    };
+
+    #[test]
+    fn forwards_pagination_state_to_list_inputs() {
+        let data_type: CommanderDataType = CommanderNumberDataType {}.into();
+        assert!(matches!(
+            list_change_to_binding(&data_type, datastream::ListChange::HasMorePages(true)),
+            Some(Ok(ListChange::HasMorePages(true)))
+        ));
+        assert!(matches!(
+            list_change_to_binding(&data_type, datastream::ListChange::HasMorePages(false)),
+            Some(Ok(ListChange::HasMorePages(false)))
+        ));
+
+        let Some(Ok(ListChange::Append(encoded))) = list_change_to_binding(
+            &data_type,
+            datastream::ListChange::Add(Arc::new(CommanderValue::Number(2.0))),
+        ) else {
+            panic!("Expected an append");
+        };
+        assert_eq!(
+            data_type.decode(&encoded).unwrap(),
+            CommanderValue::Number(2.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_rows_that_cannot_be_encoded() {
+        let data_type: CommanderDataType = CommanderNumberDataType {}.into();
+        assert!(matches!(
+            list_change_to_binding(
+                &data_type,
+                datastream::ListChange::Insert(0, Arc::new(CommanderValue::Boolean(true))),
+            ),
+            Some(Err(_))
+        ));
+
+        let mut list_changes = tokio_stream::iter(vec![
+            datastream::ListChange::Insert(0, Arc::new(CommanderValue::Boolean(true))),
+            datastream::ListChange::Insert(0, Arc::new(CommanderValue::Number(1.0))),
+        ])
+        .map_while(move |change| list_change_to_binding(&data_type, change))
+        .filter_map(Result::ok);
+        assert!(matches!(
+            list_changes.next().await,
+            Some(ListChange::Insert((0, _)))
+        ));
+        assert!(list_changes.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn destroyed_streams_terminate_instead_of_trapping() {
+        let data_type: CommanderDataType = CommanderNumberDataType {}.into();
+        let list_changes = tokio_stream::iter(vec![
+            datastream::ListChange::Add(Arc::new(CommanderValue::Number(1.0))),
+            datastream::ListChange::Destroy,
+            datastream::ListChange::Clear,
+        ])
+        .map_while(move |change| list_change_to_binding(&data_type, change))
+        .filter_map(Result::ok)
+        .fuse();
+        let mut change_stream = InputChangeStream::new(
+            0,
+            Box::pin(list_changes),
+            Box::pin(tokio_stream::pending()),
+            list_change_subscriber(CommanderNumberDataType {}.into()),
+        );
+
+        let storage = DataStreamStorage::default();
+        assert!(matches!(
+            change_stream.poll_change_blocking(storage.clone()).await,
+            Ok(ListChange::Append(_))
+        ));
+        let error = change_stream
+            .poll_change_blocking(storage.clone())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("destroyed"));
+        assert!(change_stream
+            .poll_change(storage.clone())
+            .unwrap()
+            .is_none());
+
+        let tree_changes = tokio_stream::iter(vec![datastream::TreeChange::Destroy])
+            .map_while(tree_change_to_binding);
+        let mut tree_change_stream = InputChangeStream::new(
+            0,
+            Box::pin(tree_changes),
+            Box::pin(tokio_stream::pending()),
+            tree_change_subscriber(),
+        );
+        assert!(tree_change_stream
+            .poll_change_blocking(storage)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn delivers_every_trigger_fire() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let handle = Inputs(&storage.inputs)
+            .new_value_input(
+                "refresh".to_string(),
+                "Reloads the data".to_string(),
+                CommanderTriggerDataType {},
+                None,
+            )
+            .unwrap();
+        let change_stream =
+            HostValueInput::get_change_stream(&mut WasiImpl(&mut storage), Resource::new_own(0))
+                .await
+                .unwrap();
+
+        for _ in 0..3 {
+            handle.load(Inputs(&storage.inputs)).fire().unwrap();
+        }
+
+        let mut reactions = 0;
+        while let Some(Some(value)) = HostValueChangeStream::poll_change(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(change_stream.rep()),
+        )
+        .await
+        .unwrap()
+        {
+            assert!(CommanderTriggerDataType {}.decode(&value).is_ok());
+            reactions += 1;
+        }
+        assert_eq!(reactions, 3);
+    }
+
+    #[tokio::test]
+    async fn sends_only_appended_chunks_to_chunk_readers() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let handle = Inputs(&storage.inputs)
+            .new_value_input(
+                "payload".to_string(),
+                "Streamed bytes".to_string(),
+                CommanderBytesDataType {},
+                None,
+            )
+            .unwrap();
+        let id = handle.metadata.id;
+        async fn change_stream(storage: &mut WasmStorage, id: u32) -> u32 {
+            HostValueInput::get_change_stream(&mut WasiImpl(storage), Resource::new_own(id))
+                .await
+                .unwrap()
+                .rep()
+        }
+        let chunk_reader = change_stream(&mut storage, id).await;
+        let value_reader = change_stream(&mut storage, id).await;
+
+        for chunk in [vec![1, 2], vec![3]] {
+            storage
+                .inputs
+                .get(id)
+                .unwrap()
+                .stream
+                .write()
+                .try_get_value_mut()
+                .unwrap()
+                .append_bytes(chunk)
+                .unwrap();
+        }
+
+        let mut chunks = vec![];
+        while let Some(change) = HostValueChangeStream::poll_chunk(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(chunk_reader),
+        )
+        .await
+        .unwrap()
+        {
+            let ValueChange::AppendBytes(chunk) = change else {
+                panic!("Expected an appended chunk");
+            };
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks, vec![vec![1, 2], vec![3]]);
+
+        // Programs reading whole values still see the bytes assembled so far.
+        let Some(Some(value)) = HostValueChangeStream::poll_change(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(value_reader),
+        )
+        .await
+        .unwrap() else {
+            panic!("Expected a whole value");
+        };
+        assert_eq!(
+            CommanderBytesDataType {}.decode(&value).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn removes_change_streams_of_removed_inputs() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let mut change_streams = vec![];
+        for i in 0..100 {
+            let handle = Inputs(&storage.inputs)
+                .new_value_input(
+                    format!("input {}", i),
+                    String::new(),
+                    CommanderNumberDataType {},
+                    Some(i as f64),
+                )
+                .unwrap();
+            let id = handle.metadata.id;
+            change_streams.push(
+                HostValueInput::get_change_stream(
+                    &mut WasiImpl(&mut storage),
+                    Resource::new_own(id),
+                )
+                .await
+                .unwrap(),
+            );
+            // The program never drops its change stream, but the input goes away.
+            assert!(storage.inputs.remove(id).unwrap());
+        }
+        assert_eq!(storage.input_streams.value_streams.len(), 1);
+
+        // Change streams that were cleaned up behave as if their input's stream ended.
+        let first = change_streams.first().unwrap().rep();
+        assert!(HostValueChangeStream::poll_change(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(first)
+        )
+        .await
+        .unwrap()
+        .is_none());
+        HostValueChangeStream::drop(&mut WasiImpl(&mut storage), Resource::new_own(first)).unwrap();
+        assert!(HostValueChangeStream::poll_change(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(1000)
+        )
+        .await
+        .is_err());
+    }
+}
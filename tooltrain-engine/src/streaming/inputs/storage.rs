@@ -1,63 +1,92 @@
 use std::collections::BTreeMap;
 
+use anyhow::{anyhow, Error};
 use tokio_stream::Stream;
 
 use crate::{
-    bindings::streaming_inputs::{ListChange, TreeChange},
-    streaming::storage::DataStreamResourceChange,
+    bindings::streaming_inputs::{ListChange, TreeChange, ValueChange},
+    streaming::{storage::DataStreamResourceChange, DataStreamStorage},
 };
 
-use super::change_streams::{InputChangeStream, ReplacementChangeFromDataStreamSnapshot};
+use super::change_streams::{
+    ChangeStream, ChangeSubscriber, InputChangeStream, ReplacementChangeFromDataStreamSnapshot,
+};
 
-pub(super) struct InputStreamsStorage<T: Clone + ReplacementChangeFromDataStreamSnapshot>(
-    BTreeMap<u32, InputChangeStream<T>>,
-);
+pub(super) struct InputStreamsStorage<T: Clone + ReplacementChangeFromDataStreamSnapshot> {
+    streams: BTreeMap<u32, InputChangeStream<T>>,
+    next_id: u32,
+}
 
 impl<T: Clone + ReplacementChangeFromDataStreamSnapshot> Default for InputStreamsStorage<T> {
     fn default() -> Self {
-        Self(BTreeMap::new())
+        Self {
+            streams: BTreeMap::new(),
+            next_id: 0,
+        }
     }
 }
 
 impl<T: Clone + ReplacementChangeFromDataStreamSnapshot> InputStreamsStorage<T> {
-    pub(super) fn add_stream<S, R>(
+    pub(super) fn add_stream<R>(
         &mut self,
+        inputs: &DataStreamStorage,
         input_id: u32,
-        stream_changes: S,
+        stream_changes: ChangeStream<T>,
         resource_changes: R,
+        subscribe: ChangeSubscriber<T>,
     ) -> u32
     where
-        S: Stream<Item = T>,
-        S: Send,
-        S: 'static,
         R: Stream<Item = DataStreamResourceChange>,
         R: Send,
         R: 'static,
     {
-        let next_id = self.0.last_key_value().map(|(k, _)| k + 1).unwrap_or(0);
-        self.0.insert(
+        self.remove_orphans(inputs);
+        let next_id = self.next_id;
+        self.next_id += 1;
+        self.streams.insert(
             next_id,
             InputChangeStream::new(
                 input_id,
-                Box::pin(stream_changes),
+                stream_changes,
                 Box::pin(resource_changes),
+                subscribe,
             ),
         );
         next_id
     }
 
-    pub(super) fn get_mut(&mut self, id: u32) -> Option<&mut InputChangeStream<T>> {
-        self.0.get_mut(&id)
+    /// Returns `None` for a change stream that was removed because its input no longer exists.
+    pub(super) fn get_mut(&mut self, id: u32) -> Result<Option<&mut InputChangeStream<T>>, Error> {
+        match self.streams.get_mut(&id) {
+            Some(stream) => Ok(Some(stream)),
+            None if id < self.next_id => Ok(None),
+            None => Err(anyhow!("Change stream not found")),
+        }
     }
 
     pub(super) fn remove(&mut self, id: u32) -> bool {
-        self.0.remove(&id).is_some()
+        self.streams.remove(&id);
+        id < self.next_id
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Removes the change streams of inputs that no longer exist. A program may never drop the
+    /// change streams it is done with, so otherwise every short-lived input would leave one
+    /// behind.
+    fn remove_orphans(&mut self, inputs: &DataStreamStorage) {
+        let state = inputs.state();
+        self.streams
+            .retain(|_, stream| state.contains_key(&stream.input_id));
     }
 }
 
 #[derive(Default)]
 pub(crate) struct InputStreams {
-    pub(super) value_streams: InputStreamsStorage<Option<Vec<u8>>>,
+    pub(super) value_streams: InputStreamsStorage<ValueChange>,
     pub(super) list_streams: InputStreamsStorage<ListChange>,
     pub(super) tree_streams: InputStreamsStorage<TreeChange>,
 }
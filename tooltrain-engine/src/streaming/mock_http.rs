@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use wasmtime_wasi_http::{
+    body::HyperOutgoingBody,
+    types::{HostFutureIncomingResponse, IncomingResponse, OutgoingRequestConfig},
+};
+
+/// Canned HTTP responses, as `(status, body)`, keyed by request path. Programs run with these
+/// responses never reach the network, so HTTP programs can be tested deterministically.
+pub type MockHttpResponses = HashMap<String, (u16, Vec<u8>)>;
+
+/// Answers `request` from `responses`, matching on the request's path and query. Requests for
+/// paths without a canned response receive an empty 404.
+pub(crate) fn mock_response(
+    responses: &MockHttpResponses,
+    request: &hyper::Request<HyperOutgoingBody>,
+    config: &OutgoingRequestConfig,
+) -> HostFutureIncomingResponse {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    let (status, body) = responses.get(path).cloned().unwrap_or((404, vec![]));
+    let response = hyper::Response::builder()
+        .status(status)
+        .body(
+            Full::new(Bytes::from(body))
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+        .map(|resp| IncomingResponse {
+            resp,
+            worker: None,
+            between_bytes_timeout: config.between_bytes_timeout,
+        })
+        .map_err(anyhow::Error::from);
+    HostFutureIncomingResponse::ready(response.map(Ok))
+}
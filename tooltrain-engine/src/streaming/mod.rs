@@ -1,8 +1,17 @@
 mod host;
 mod inputs;
+#[cfg(any(test, feature = "mock-http"))]
+mod mock_http;
 mod outputs;
+mod stdio;
 mod storage;
+mod wasi_config;
 
 pub use inputs::*;
+#[cfg(any(test, feature = "mock-http"))]
+pub use mock_http::MockHttpResponses;
 pub use outputs::*;
-pub(crate) use storage::{DataStreamStorage, WasmStorage};
+pub(crate) use stdio::OutputPipe;
+pub use storage::{ResourceId, StreamSource};
+pub(crate) use storage::{DataStreamResourceChange, DataStreamStorage, WasmStorage};
+pub use wasi_config::WasiConfig;
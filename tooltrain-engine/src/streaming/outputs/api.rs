@@ -1,27 +1,50 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use crate::{
     datastream::{
-        DataStream, DataStreamSnapshot, ListChange, TreeChange, TreeStreamNode, ValueChange,
+        DataStream, DataStreamSnapshot, ListChange, ListSnapshot, TreeChange, TreeStreamNode,
+        ValueChange,
     },
     streaming::storage::{
-        DataStreamMetadata, DataStreamResourceChange, DataStreamStorage, DataStreamType, ResourceId,
+        DataStreamMetadata, DataStreamResourceChange, DataStreamStorage, DataStreamType,
+        ResourceId, StreamSource,
     },
 };
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use tooltrain_data::CommanderValue;
 use parking_lot::RwLock;
-use tokio::sync::broadcast::Receiver;
-use tokio_stream::{once, wrappers::BroadcastStream, Stream, StreamExt};
+use futures::stream::unfold;
+use tokio::{sync::broadcast::Receiver, time::sleep};
+use tokio_stream::{
+    iter, once,
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
 
+/// Receivers that fall behind skip the messages they missed instead of ending the stream, so the
+/// stream only ends once the channel itself is closed.
 fn make_broadcast_stream<T: Clone + Send + 'static>(
     broadcast_receiver: Receiver<T>,
 ) -> impl Stream<Item = T> {
-    BroadcastStream::new(broadcast_receiver).map_while(Result::ok)
+    BroadcastStream::new(broadcast_receiver).filter_map(Result::ok)
+}
+
+/// Ends `changes` at the change that completes its output, which is not yielded. If the output
+/// is `already_complete` the stream ends immediately.
+fn until_complete<T>(
+    changes: impl Stream<Item = T>,
+    already_complete: bool,
+    is_complete: fn(&T) -> bool,
+) -> impl Stream<Item = T> {
+    let remaining = if already_complete { 0 } else { usize::MAX };
+    changes
+        .take(remaining)
+        .take_while(move |change| !is_complete(change))
 }
 
 pub trait OutputRef {
     fn inner_data_stream(&self) -> Result<Arc<RwLock<DataStream>>, Error>;
+    fn source(&self) -> StreamSource;
 }
 
 #[derive(Clone, Debug)]
@@ -75,12 +98,74 @@ impl<'a> ValueOutputRef<'a> {
     ) -> Result<impl Stream<Item = Option<Arc<CommanderValue>>> + '_, Error> {
         Ok(once(self.value()?).chain(self.updates_stream()?.map_while(|_| self.value().ok())))
     }
+
+    /// Like `value_stream`, but emits at most one value per `interval`: the latest value seen
+    /// during that interval. Useful for programs that set their value in a tight loop.
+    pub fn debounced_value_stream(
+        &self,
+        interval: Duration,
+    ) -> Result<impl Stream<Item = Option<Arc<CommanderValue>>> + '_, Error> {
+        let values = Box::pin(self.value_stream()?.fuse());
+        Ok(unfold(values, move |mut values| async move {
+            let mut latest = values.next().await?;
+            let deadline = sleep(interval);
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    _ = &mut deadline => return Some((latest, values)),
+                    next = values.next() => match next {
+                        Some(value) => latest = value,
+                        None => return Some((latest, values)),
+                    },
+                }
+            }
+        }))
+    }
+
+    /// Streams a bytes value in the chunks the program appends them in, starting with the bytes
+    /// the value already holds. Ends once the value is replaced or removed, and yields an error
+    /// for chunks that were missed because the reader fell behind.
+    pub fn byte_stream(&self) -> Result<impl Stream<Item = Result<Arc<Vec<u8>>, Error>>, Error> {
+        let resource = self.storage.get(self.id)?;
+        let stream = resource.stream.read();
+        let value_stream = stream.try_get_value()?;
+        let initial = match value_stream.snapshot().as_deref() {
+            None => None,
+            Some(CommanderValue::Bytes(bytes)) => Some(Arc::new(bytes.clone())),
+            Some(_) => return Err(anyhow!("Output does not hold bytes")),
+        };
+        let chunks =
+            BroadcastStream::new(value_stream.subscribe()).map_while(|change| match change {
+                Ok(ValueChange::AppendBytes(chunk)) => Some(Ok(chunk)),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                    Some(Err(anyhow!("Missed {} chunks of the value", skipped)))
+                }
+            });
+        Ok(iter(initial.map(Ok)).chain(chunks))
+    }
+
+    /// Resolves with the output's value, waiting for the program to set one if it is still
+    /// empty.
+    pub async fn first_value(&self) -> Result<Arc<CommanderValue>, Error> {
+        let mut values = Box::pin(self.value_stream()?);
+        while let Some(value) = values.next().await {
+            if let Some(value) = value {
+                return Ok(value);
+            }
+        }
+        Err(anyhow!("Value output was removed before it was set"))
+    }
 }
 
 impl OutputRef for ValueOutputRef<'_> {
     fn inner_data_stream(&self) -> Result<Arc<RwLock<DataStream>>, Error> {
         Ok(self.storage.get(self.id)?.stream.clone())
     }
+
+    fn source(&self) -> StreamSource {
+        self.storage.source()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -108,7 +193,9 @@ impl<'a> ListOutputRef<'a> {
         self.storage.get(self.id).unwrap().metadata.clone()
     }
 
-    pub fn value(&self) -> Result<Vec<Arc<CommanderValue>>, Error> {
+    /// The list's current rows. This is cheap even for long lists, as the snapshot shares its
+    /// rows with the list until the list next changes.
+    pub fn value(&self) -> Result<ListSnapshot, Error> {
         Ok(self
             .storage
             .get(self.id)?
@@ -118,23 +205,103 @@ impl<'a> ListOutputRef<'a> {
             .snapshot())
     }
 
+    /// Streams changes to the list. Ends once the program completes the list, or immediately if
+    /// it was already complete.
     pub fn updates_stream(&self) -> Result<impl Stream<Item = ListChange>, Error> {
-        Ok(make_broadcast_stream(
-            self.storage
-                .get(self.id)?
-                .stream
-                .read()
-                .try_get_list()?
-                .subscribe(),
+        let resource = self.storage.get(self.id)?;
+        let stream = resource.stream.read();
+        let list = stream.try_get_list()?;
+        Ok(until_complete(
+            make_broadcast_stream(list.subscribe()),
+            list.is_complete(),
+            |change| matches!(change, ListChange::Complete),
         ))
     }
 
-    pub fn values_stream(
-        &self,
-    ) -> Result<impl Stream<Item = Vec<Arc<CommanderValue>>> + '_, Error> {
+    /// Whether the program finished streaming the list.
+    pub fn is_complete(&self) -> Result<bool, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_list()?
+            .is_complete())
+    }
+
+    /// Streams the list as individual changes, starting with an `Add` for every row that is
+    /// already present. Unlike `values_stream`, this never re-snapshots the list, so consumers can
+    /// maintain their own view of a long list in linear time.
+    pub fn changes_only_stream(&self) -> Result<impl Stream<Item = ListChange>, Error> {
+        let resource = self.storage.get(self.id)?;
+        let stream = resource.stream.read();
+        let list = stream.try_get_list()?;
+        let updates = until_complete(
+            make_broadcast_stream(list.subscribe()),
+            list.is_complete(),
+            |change| matches!(change, ListChange::Complete),
+        );
+        let existing_rows = list.snapshot().into_iter().map(ListChange::Add);
+        Ok(iter(existing_rows).chain(updates))
+    }
+
+    /// Returns the list's rows along with a sequence number that can later be passed to
+    /// [`ListOutputRef::changes_since`] to catch up on what changed, e.g. after reconnecting.
+    pub fn value_with_sequence(&self) -> Result<(u64, ListSnapshot), Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_list()?
+            .snapshot_with_sequence())
+    }
+
+    pub fn changes_since(&self, sequence: u64) -> Result<Vec<(u64, ListChange)>, Error> {
+        self.storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_list()?
+            .changes_since(sequence)
+    }
+
+    /// Like `updates_stream`, but every change is paired with its sequence number.
+    pub fn sequenced_updates_stream(&self) -> Result<impl Stream<Item = (u64, ListChange)>, Error> {
+        let resource = self.storage.get(self.id)?;
+        let stream = resource.stream.read();
+        let list = stream.try_get_list()?;
+        let mut sequence = list.sequence();
+        Ok(
+            BroadcastStream::new(list.subscribe()).filter_map(move |result| match result {
+                Ok(change) => {
+                    sequence += 1;
+                    Some((sequence, change))
+                }
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                    sequence += skipped;
+                    None
+                }
+            }),
+        )
+    }
+
+    pub fn values_stream(&self) -> Result<impl Stream<Item = ListSnapshot> + '_, Error> {
         Ok(once(self.value()?).chain(self.updates_stream()?.map_while(|_| self.value().ok())))
     }
 
+    /// The producer's estimate of how many rows the list has in total, including rows that
+    /// have not been loaded yet.
+    pub fn total_count(&self) -> Result<Option<u64>, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_list()?
+            .total_count())
+    }
+
     pub fn load_more(&self, limit: u32) -> Result<bool, Error> {
         self.storage
             .get(self.id)?
@@ -143,12 +310,87 @@ impl<'a> ListOutputRef<'a> {
             .try_get_list_mut()?
             .request_page(limit)
     }
+
+    /// Like `load_more`, but resolves once the program answers the request, either by adding a
+    /// row or by reporting that it has no more rows, rather than as soon as the request is sent.
+    /// Returns whether any rows arrived, and fails if the program does not answer within
+    /// `timeout`.
+    pub async fn load_more_and_wait(&self, limit: u32, timeout: Duration) -> Result<bool, Error> {
+        let mut updates = Box::pin(self.updates_stream()?);
+        if !self.load_more(limit)? {
+            return Ok(false);
+        }
+        let answered = async {
+            while let Some(change) = updates.next().await {
+                match change {
+                    ListChange::Add(_) | ListChange::Insert(_, _) => return Ok(true),
+                    ListChange::HasMorePages(false) => return Ok(false),
+                    ListChange::Destroy => break,
+                    _ => {}
+                }
+            }
+            Err(anyhow!("List output was removed before the page arrived"))
+        };
+        tokio::time::timeout(timeout, answered)
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for a page of {} rows", limit))?
+    }
+
+    /// The sequence number of the list's most recent change, or 0 if it never changed.
+    pub fn sequence(&self) -> Result<u64, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_list()?
+            .sequence())
+    }
+
+    /// Resolves once at least `sequence` changes have been broadcast for the list, so a host can
+    /// be sure every change up to that point was made before it inspects the list. Fails if the
+    /// list is completed or removed before reaching `sequence`.
+    pub async fn await_seq(&self, sequence: u64) -> Result<(), Error> {
+        let mut updates = {
+            let resource = self.storage.get(self.id)?;
+            let stream = resource.stream.read();
+            let list = stream.try_get_list()?;
+            if list.sequence() >= sequence {
+                return Ok(());
+            }
+            if list.is_complete() {
+                return Err(anyhow!(
+                    "List output was completed at sequence {} before reaching {}",
+                    list.sequence(),
+                    sequence
+                ));
+            }
+            BroadcastStream::new(list.subscribe())
+        };
+        while let Some(change) = updates.next().await {
+            if self.sequence()? >= sequence {
+                return Ok(());
+            }
+            if let Ok(ListChange::Complete | ListChange::Destroy) = change {
+                break;
+            }
+        }
+        Err(anyhow!(
+            "List output ended at sequence {} before reaching {}",
+            self.sequence()?,
+            sequence
+        ))
+    }
 }
 
 impl OutputRef for ListOutputRef<'_> {
     fn inner_data_stream(&self) -> Result<Arc<RwLock<DataStream>>, Error> {
         Ok(self.storage.get(self.id)?.stream.clone())
     }
+
+    fn source(&self) -> StreamSource {
+        self.storage.source()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -186,28 +428,94 @@ impl<'a> TreeOutputRef<'a> {
             .snapshot())
     }
 
+    pub fn subtree_value(&self, parent_id: &str) -> Result<Vec<TreeStreamNode>, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_tree()?
+            .subtree_snapshot(parent_id))
+    }
+
+    /// Streams changes to the tree. Ends once the program completes the tree, or immediately if
+    /// it was already complete.
     pub fn updates_stream(&self) -> Result<impl Stream<Item = TreeChange>, Error> {
-        Ok(make_broadcast_stream(
-            self.storage
-                .get(self.id)?
-                .stream
-                .read()
-                .try_get_tree()?
-                .subscribe(),
+        let resource = self.storage.get(self.id)?;
+        let stream = resource.stream.read();
+        let tree = stream.try_get_tree()?;
+        Ok(until_complete(
+            make_broadcast_stream(tree.subscribe()),
+            tree.is_complete(),
+            |change| matches!(change, TreeChange::Complete),
         ))
     }
 
+    /// Whether the program finished streaming the tree.
+    pub fn is_complete(&self) -> Result<bool, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .read()
+            .try_get_tree()?
+            .is_complete())
+    }
+
     pub fn value_stream(&self) -> Result<impl Stream<Item = Vec<TreeStreamNode>> + '_, Error> {
         Ok(once(self.value()?).chain(self.updates_stream()?.map_while(|_| self.value().ok())))
     }
 
-    pub fn request_children(&self, parent: String) -> Result<bool, Error> {
-        self.storage
+    /// Asks the program to load the children of `parent`. The returned request can be
+    /// cancelled if the children are no longer needed, e.g. because the node was collapsed.
+    pub fn request_children(&self, parent: String) -> Result<ChildrenRequest<'a>, Error> {
+        let is_new = self
+            .storage
             .get(self.id)?
             .stream
             .write()
             .try_get_tree_mut()?
-            .request_children(parent)
+            .request_children(parent.clone())?;
+        Ok(ChildrenRequest {
+            storage: self.storage,
+            id: self.id,
+            parent,
+            is_new,
+        })
+    }
+}
+
+/// A request for the children of a tree output's node. Dropping it leaves the request in place.
+#[derive(Debug)]
+pub struct ChildrenRequest<'a> {
+    storage: &'a DataStreamStorage,
+    id: ResourceId,
+    parent: String,
+    is_new: bool,
+}
+
+impl ChildrenRequest<'_> {
+    pub fn parent(&self) -> &str {
+        &self.parent
+    }
+
+    /// Whether this sent a new request to the program, rather than joining a request for the
+    /// same children that was still pending.
+    pub fn is_new(&self) -> bool {
+        self.is_new
+    }
+
+    /// Tells the program the children are no longer needed, so it can abandon loading them.
+    /// Returns `false` if the request was no longer pending, e.g. because the children already
+    /// arrived.
+    pub fn cancel(self) -> Result<bool, Error> {
+        Ok(self
+            .storage
+            .get(self.id)?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .cancel_children_request(&self.parent))
     }
 }
 
@@ -215,6 +523,10 @@ impl OutputRef for TreeOutputRef<'_> {
     fn inner_data_stream(&self) -> Result<Arc<RwLock<DataStream>>, Error> {
         Ok(self.storage.get(self.id)?.stream.clone())
     }
+
+    fn source(&self) -> StreamSource {
+        self.storage.source()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -233,7 +545,7 @@ impl OutputHandle {
         }
     }
 
-    fn from_metadata(metadata: DataStreamMetadata) -> Self {
+    pub(crate) fn from_metadata(metadata: DataStreamMetadata) -> Self {
         match metadata.data_stream_type {
             DataStreamType::Value => OutputHandle::Value(ValueOutputHandle { metadata }),
             DataStreamType::List => OutputHandle::List(ListOutputHandle { metadata }),
@@ -253,7 +565,7 @@ pub enum OutputChange {
 impl<'a> Outputs<'a> {
     pub fn updates(&self) -> impl Stream<Item = OutputChange> + '_ {
         BroadcastStream::from(self.0.changes())
-            .map_while(|result| result.ok())
+            .filter_map(Result::ok)
             .filter_map(|internal_change| match internal_change {
                 DataStreamResourceChange::Added(metadata) => {
                     Some(OutputChange::Added(OutputHandle::from_metadata(metadata)))
@@ -275,11 +587,522 @@ impl<'a> Outputs<'a> {
             .collect()
     }
 
+    pub fn get_handle(&self, output_name: &str) -> Option<OutputHandle> {
+        self.handles()
+            .into_iter()
+            .find(|handle| handle.metadata().name == output_name)
+    }
+
+    /// Resolves with the output named `output_name`, waiting for the program to add it if it does
+    /// not exist yet.
+    pub async fn wait_for_handle(&self, output_name: &str) -> Result<OutputHandle, Error> {
+        self.wait_for_output(|handle| handle.metadata().name == output_name)
+            .await
+            .map_err(|_| anyhow!("Output {:?} was never added", output_name))
+    }
+
+    /// Resolves with the first output matching `predicate`, waiting for the program to add one if
+    /// none exists yet. Outputs that are removed before they are seen are skipped, so the
+    /// returned handle always refers to a live output at the time it is returned.
+    pub async fn wait_for_output<P>(&self, predicate: P) -> Result<OutputHandle, Error>
+    where
+        P: Fn(&OutputHandle) -> bool,
+    {
+        let mut updates = self.updates();
+        if let Some(handle) = self.handles().into_iter().find(&predicate) {
+            return Ok(handle);
+        }
+
+        while let Some(change) = updates.next().await {
+            match change {
+                OutputChange::Added(handle)
+                    if predicate(&handle) && self.0.get(handle.metadata().id).is_ok() =>
+                {
+                    return Ok(handle)
+                }
+                OutputChange::Added(_) | OutputChange::Removed(_) => continue,
+            }
+        }
+        Err(anyhow!("No matching output was added"))
+    }
+
     pub fn values(&self) -> BTreeMap<ResourceId, DataStreamSnapshot> {
-        self.0
-            .state()
-            .iter()
-            .map(|(id, spec)| (*id, spec.stream.read().snapshot()))
+        self.0.snapshots()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::{DataStreamCapacity, ListStream};
+    use futures::FutureExt;
+    use tokio::sync::broadcast;
+    use tooltrain_data::{
+        CommanderBytesDataType, CommanderDataType, CommanderNumberDataType, CommanderPathDataType,
+    };
+
+    #[tokio::test]
+    async fn broadcast_streams_survive_lagging() {
+        let (sender, receiver) = broadcast::channel(16);
+        let stream = make_broadcast_stream(receiver);
+        for i in 0..100 {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let received: Vec<i32> = stream.collect().await;
+        assert_eq!(received, (84..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn first_value_waits_for_a_value() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_value_stream(None),
+            )
+            .unwrap();
+
+        let plugin_storage = storage.clone();
+        let plugin = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            plugin_storage
+                .get(id)
+                .unwrap()
+                .stream
+                .write()
+                .try_get_value_mut()
+                .unwrap()
+                .set(CommanderValue::Number(1.0))
+                .unwrap();
+        });
+
+        let Some(OutputHandle::Value(handle)) = Outputs(&storage).get_handle("Count") else {
+            panic!("Expected a value output handle");
+        };
+        let output = handle.load(Outputs(&storage));
+        assert_eq!(
+            *output.first_value().await.unwrap(),
+            CommanderValue::Number(1.0)
+        );
+        plugin.await.unwrap();
+
+        // Once a value is present it is returned immediately.
+        assert_eq!(
+            *output.first_value().now_or_never().unwrap().unwrap(),
+            CommanderValue::Number(1.0)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn debounced_value_stream_coalesces_rapid_updates() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_value_stream(None),
+            )
+            .unwrap();
+        let Some(OutputHandle::Value(handle)) = Outputs(&storage).get_handle("Count") else {
+            panic!("Expected a value output handle");
+        };
+        let output = handle.load(Outputs(&storage));
+        let mut debounced = Box::pin(
+            output
+                .debounced_value_stream(Duration::from_millis(100))
+                .unwrap(),
+        );
+
+        for i in 0..100 {
+            storage
+                .get(id)
+                .unwrap()
+                .stream
+                .write()
+                .try_get_value_mut()
+                .unwrap()
+                .set(CommanderValue::Number(i as f64))
+                .unwrap();
+        }
+
+        let mut emitted = vec![];
+        while let Some(value) = debounced.next().await {
+            let value = value.map(|v| (*v).clone());
+            emitted.push(value.clone());
+            if value == Some(CommanderValue::Number(99.0)) {
+                break;
+            }
+        }
+        assert!(emitted.len() < 10);
+        assert_eq!(emitted.last(), Some(&Some(CommanderValue::Number(99.0))));
+    }
+
+    #[tokio::test]
+    async fn await_seq_resolves_once_changes_are_broadcast() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Numbers".to_string(),
+                "Numbers appended over time".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_list_stream(),
+            )
+            .unwrap();
+        let stream = storage.get(id).unwrap().stream.clone();
+        let producer = tokio::spawn(async move {
+            for row in 0..5 {
+                tokio::task::yield_now().await;
+                stream
+                    .write()
+                    .try_get_list_mut()
+                    .unwrap()
+                    .add(CommanderValue::Number(row as f64))
+                    .unwrap();
+            }
+            stream
+                .write()
+                .try_get_list_mut()
+                .unwrap()
+                .complete()
+                .unwrap();
+        });
+
+        let Some(OutputHandle::List(handle)) = Outputs(&storage).get_handle("Numbers") else {
+            panic!("Expected a list output handle");
+        };
+        let output = handle.load(Outputs(&storage));
+        output.await_seq(5).await.unwrap();
+        assert!(output.value().unwrap().len() >= 5);
+
+        producer.await.unwrap();
+        // Sequences that were already reached resolve immediately.
+        output.await_seq(5).await.unwrap();
+        // The list was completed at sequence 6, so later sequences are never reached.
+        assert!(output.await_seq(7).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn load_more_and_wait_resolves_once_a_page_arrives() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Feed".to_string(),
+                "A paginated feed".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_list_stream(),
+            )
+            .unwrap();
+        let stream = storage.get(id).unwrap().stream.clone();
+        stream
+            .write()
+            .try_get_list_mut()
+            .unwrap()
+            .set_has_more_rows(true)
+            .unwrap();
+
+        // Like the Mastodon feed, the plugin answers each request with a page of rows after a
+        // delay, and reports that it has no more rows once the feed runs out.
+        let mut requests = stream
+            .read()
+            .try_get_list()
+            .unwrap()
+            .get_page_request_stream();
+        let plugin_stream = stream.clone();
+        let plugin = tokio::spawn(async move {
+            let mut next_row = 0;
+            while let Ok(limit) = requests.recv().await {
+                sleep(Duration::from_millis(50)).await;
+                let mut stream = plugin_stream.write();
+                let list = stream.try_get_list_mut().unwrap();
+                if next_row >= 4 {
+                    list.set_has_more_rows(false).unwrap();
+                    break;
+                }
+                for _ in 0..limit {
+                    list.add(CommanderValue::Number(next_row as f64)).unwrap();
+                    next_row += 1;
+                }
+            }
+        });
+
+        let Some(OutputHandle::List(handle)) = Outputs(&storage).get_handle("Feed") else {
+            panic!("Expected a list output handle");
+        };
+        let output = handle.load(Outputs(&storage));
+        let timeout = Duration::from_secs(1);
+        assert!(output.load_more_and_wait(2, timeout).await.unwrap());
+        assert!(!output.value().unwrap().is_empty());
+        assert!(output.load_more_and_wait(2, timeout).await.unwrap());
+        assert!(!output.load_more_and_wait(2, timeout).await.unwrap());
+        plugin.await.unwrap();
+        assert_eq!(output.value().unwrap().len(), 4);
+
+        // Once the feed is exhausted no request is sent at all.
+        assert!(!output.load_more_and_wait(2, timeout).await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn load_more_and_wait_times_out() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Feed".to_string(),
+                "A paginated feed".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_list_stream(),
+            )
+            .unwrap();
+        let stream = storage.get(id).unwrap().stream.clone();
+        stream
+            .write()
+            .try_get_list_mut()
+            .unwrap()
+            .set_has_more_rows(true)
+            .unwrap();
+        let _requests = stream
+            .read()
+            .try_get_list()
+            .unwrap()
+            .get_page_request_stream();
+
+        let Some(OutputHandle::List(handle)) = Outputs(&storage).get_handle("Feed") else {
+            panic!("Expected a list output handle");
+        };
+        let output = handle.load(Outputs(&storage));
+        assert!(output
+            .load_more_and_wait(2, Duration::from_millis(100))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn completing_one_output_leaves_others_live() {
+        let storage = DataStreamStorage::default();
+        let mut ids = vec![];
+        for name in ["Finished", "Live"] {
+            ids.push(
+                storage
+                    .add(
+                        name.to_string(),
+                        "Some rows".to_string(),
+                        CommanderNumberDataType {}.into(),
+                        storage.new_list_stream(),
+                    )
+                    .unwrap(),
+            );
+        }
+        let with_list = |id: ResourceId, change: &dyn Fn(&mut ListStream)| {
+            change(
+                storage
+                    .get(id)
+                    .unwrap()
+                    .stream
+                    .write()
+                    .try_get_list_mut()
+                    .unwrap(),
+            )
+        };
+        let load = |name: &str| {
+            let Some(OutputHandle::List(handle)) = Outputs(&storage).get_handle(name) else {
+                panic!("Expected a list output handle");
+            };
+            handle.load(Outputs(&storage))
+        };
+        let (finished, live) = (load("Finished"), load("Live"));
+        let finished_updates = finished.updates_stream().unwrap();
+        let mut live_updates = Box::pin(live.updates_stream().unwrap());
+
+        with_list(ids[0], &|list| {
+            list.add(CommanderValue::Number(1.0)).unwrap();
+            list.complete().unwrap();
+        });
+        with_list(ids[1], &|list| {
+            list.add(CommanderValue::Number(2.0)).unwrap()
+        });
+
+        let changes: Vec<ListChange> = finished_updates.collect().await;
+        assert!(matches!(changes.as_slice(), [ListChange::Add(_)]));
+        assert!(finished.is_complete().unwrap());
+        assert_eq!(finished.value().unwrap().len(), 1);
+        assert!(finished.updates_stream().unwrap().next().await.is_none());
+
+        assert!(!live.is_complete().unwrap());
+        assert!(matches!(
+            live_updates.next().await,
+            Some(ListChange::Add(_))
+        ));
+        with_list(ids[1], &|list| {
+            list.add(CommanderValue::Number(3.0)).unwrap()
+        });
+        assert!(matches!(
+            live_updates.next().await,
+            Some(ListChange::Add(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn changes_only_stream_replays_rows_then_streams_adds() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Rows".to_string(),
+                "Some rows".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_list_stream(),
+            )
+            .unwrap();
+        let add_row = |row: f64| {
+            storage
+                .get(id)
+                .unwrap()
+                .stream
+                .write()
+                .try_get_list_mut()
+                .unwrap()
+                .add(CommanderValue::Number(row))
+                .unwrap();
+        };
+        add_row(0.0);
+        add_row(1.0);
+
+        let Some(OutputHandle::List(handle)) = Outputs(&storage).get_handle("Rows") else {
+            panic!("Expected a list output handle");
+        };
+        let output = handle.load(Outputs(&storage));
+        let changes = output.changes_only_stream().unwrap();
+        add_row(2.0);
+        add_row(3.0);
+
+        let rows: Vec<CommanderValue> = changes
+            .take(4)
+            .map(|change| match change {
+                ListChange::Add(value) => (*value).clone(),
+                other => panic!("Expected an add, got {:?}", other),
+            })
             .collect()
+            .await;
+        assert_eq!(
+            rows,
+            (0..4)
+                .map(|row| CommanderValue::Number(row as f64))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn waits_for_named_output() {
+        let storage = DataStreamStorage::default();
+        let data_type: CommanderDataType = CommanderPathDataType {}.into();
+        storage
+            .add(
+                "Log".to_string(),
+                "Log lines".to_string(),
+                data_type.clone(),
+                storage.new_list_stream(),
+            )
+            .unwrap();
+
+        let plugin_storage = storage.clone();
+        let plugin = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            plugin_storage
+                .add(
+                    "Tree".to_string(),
+                    "A tree of files".to_string(),
+                    data_type,
+                    plugin_storage.new_tree_stream(),
+                )
+                .unwrap();
+        });
+
+        let outputs = Outputs(&storage);
+        assert!(outputs.get_handle("Tree").is_none());
+        let handle = outputs.wait_for_handle("Tree").await.unwrap();
+        assert!(matches!(handle, OutputHandle::Tree(_)));
+        assert_eq!(handle.metadata().name, "Tree");
+        assert!(outputs.get_handle("Tree").is_some());
+        assert!(matches!(
+            outputs.wait_for_handle("Log").await.unwrap(),
+            OutputHandle::List(_)
+        ));
+        plugin.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn waiting_for_an_output_skips_removed_ones() {
+        let storage = DataStreamStorage::default();
+        let data_type: CommanderDataType = CommanderPathDataType {}.into();
+        let plugin_storage = storage.clone();
+        let plugin = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            let add = || {
+                plugin_storage
+                    .add(
+                        "Tree".to_string(),
+                        "A tree of files".to_string(),
+                        data_type.clone(),
+                        plugin_storage.new_tree_stream(),
+                    )
+                    .unwrap()
+            };
+            let removed_id = add();
+            plugin_storage.remove(removed_id).unwrap();
+            (removed_id, add())
+        });
+
+        let handle = Outputs(&storage)
+            .wait_for_output(|handle| matches!(handle, OutputHandle::Tree(_)))
+            .await
+            .unwrap();
+        let (removed_id, readded_id) = plugin.await.unwrap();
+        assert_ne!(removed_id, readded_id);
+        assert_eq!(handle.metadata().id, readded_id);
+    }
+
+    #[tokio::test]
+    async fn streams_bytes_in_chunks() {
+        let storage = DataStreamStorage::with_capacity(DataStreamCapacity {
+            value_updates: 128,
+            ..Default::default()
+        });
+        let id = storage
+            .add(
+                "Image".to_string(),
+                "A rendered image".to_string(),
+                CommanderBytesDataType {}.into(),
+                storage.new_value_stream(None),
+            )
+            .unwrap();
+        let Some(OutputHandle::Value(handle)) = Outputs(&storage).get_handle("Image") else {
+            panic!("Expected a value output handle");
+        };
+        let chunks = handle.load(Outputs(&storage)).byte_stream().unwrap();
+
+        let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        for chunk in payload.chunks(64 * 1024) {
+            storage
+                .get(id)
+                .unwrap()
+                .stream
+                .write()
+                .try_get_value_mut()
+                .unwrap()
+                .append_bytes(chunk.to_vec())
+                .unwrap();
+        }
+        storage.remove(id).unwrap();
+
+        let received: Vec<Arc<Vec<u8>>> = chunks.map(Result::unwrap).collect().await;
+        assert_eq!(received.len(), 64);
+        let assembled: Vec<u8> = received
+            .iter()
+            .flat_map(|chunk| chunk.iter().copied())
+            .collect();
+        assert_eq!(assembled, payload);
     }
 }
@@ -33,6 +33,39 @@ impl HostValueOutput for WasiImpl<&mut WasmStorage> {
             .set(data_type.decode(&value)?)
     }
 
+    async fn set_if_changed(
+        &mut self,
+        resource: Resource<ValueOutput>,
+        value: Vec<u8>,
+    ) -> Result<bool, Error> {
+        let data_type = &self.0.outputs.get(resource.rep())?.metadata.data_type;
+        self.0
+            .outputs
+            .get(resource.rep())
+            .unwrap()
+            .stream
+            .write()
+            .try_get_value_mut()?
+            .set_if_changed(data_type.decode(&value)?)
+    }
+
+    async fn append_bytes(
+        &mut self,
+        resource: Resource<ValueOutput>,
+        chunk: Vec<u8>,
+    ) -> Result<(), Error> {
+        let output = self.0.outputs.get(resource.rep())?;
+        if !output.metadata.data_type.is_bytes() {
+            return Err(anyhow!("Can only append bytes to a bytes output"));
+        }
+        let result = output
+            .stream
+            .write()
+            .try_get_value_mut()?
+            .append_bytes(chunk);
+        result
+    }
+
     async fn destroy(&mut self, resource: Resource<ValueOutput>) -> Result<(), Error> {
         HostValueOutput::drop(self, resource)
     }
@@ -70,6 +103,32 @@ impl HostListOutput for WasiImpl<&mut WasmStorage> {
             .pop()
     }
 
+    async fn insert(
+        &mut self,
+        resource: Resource<ListOutput>,
+        index: u32,
+        value: Vec<u8>,
+    ) -> Result<(), Error> {
+        let output = self.0.outputs.get(resource.rep())?;
+        let value = output.metadata.data_type.decode(&value)?;
+        let result = output
+            .stream
+            .write()
+            .try_get_list_mut()?
+            .insert(index as usize, value);
+        result
+    }
+
+    async fn remove_at(&mut self, resource: Resource<ListOutput>, index: u32) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_list_mut()?
+            .remove_at(index as usize)
+    }
+
     async fn clear(&mut self, resource: Resource<ListOutput>) -> Result<(), Error> {
         self.0
             .outputs
@@ -94,6 +153,44 @@ impl HostListOutput for WasiImpl<&mut WasmStorage> {
             .set_has_more_rows(has_more_rows)
     }
 
+    async fn set_total_count(
+        &mut self,
+        resource: Resource<ListOutput>,
+        total_count: Option<u64>,
+    ) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_list_mut()?
+            .set_total_count(total_count)
+    }
+
+    async fn set_max_length(
+        &mut self,
+        resource: Resource<ListOutput>,
+        max_length: Option<u32>,
+    ) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_list_mut()?
+            .set_max_length(max_length.map(|length| length as usize))
+    }
+
+    async fn complete(&mut self, resource: Resource<ListOutput>) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_list_mut()?
+            .complete()
+    }
+
     async fn destroy(&mut self, resource: Resource<ListOutput>) -> Result<(), Error> {
         HostListOutput::drop(self, resource)
     }
@@ -102,25 +199,24 @@ impl HostListOutput for WasiImpl<&mut WasmStorage> {
         &mut self,
         resource: Resource<ListOutput>,
     ) -> Result<Resource<ListOutputRequestStream>, Error> {
+        let requests = BroadcastStream::new(
+            self.0
+                .outputs
+                .get(resource.rep())?
+                .stream
+                .read()
+                .try_get_list()?
+                .get_page_request_stream(),
+        )
+        .map(|request_result| match request_result {
+            Ok(count) => ListOutputRequest::LoadMore(count),
+            Err(_) => ListOutputRequest::Close,
+        });
         Ok(Resource::new_own(
             self.0
                 .output_request_streams
                 .list_request_streams
-                .add_stream(
-                    BroadcastStream::new(
-                        self.0
-                            .outputs
-                            .get(resource.rep())?
-                            .stream
-                            .read()
-                            .try_get_list()?
-                            .get_page_request_stream(),
-                    )
-                    .map(|request_result| match request_result {
-                        Ok(count) => ListOutputRequest::LoadMore(count),
-                        Err(_) => ListOutputRequest::Close,
-                    }),
-                ),
+                .add_stream(&self.0.outputs, resource.rep(), requests),
         ))
     }
 
@@ -150,6 +246,21 @@ impl HostTreeOutput for WasiImpl<&mut WasmStorage> {
             .add(parent, nodes)
     }
 
+    async fn reconcile(
+        &mut self,
+        resource: Resource<TreeOutput>,
+        parent: Option<String>,
+        nodes: Vec<TreeNode>,
+    ) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .reconcile(parent, nodes)
+    }
+
     async fn remove(
         &mut self,
         resource: Resource<TreeOutput>,
@@ -174,6 +285,16 @@ impl HostTreeOutput for WasiImpl<&mut WasmStorage> {
             .clear()
     }
 
+    async fn complete(&mut self, resource: Resource<TreeOutput>) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .complete()
+    }
+
     async fn destroy(&mut self, resource: Resource<TreeOutput>) -> Result<(), Error> {
         HostTreeOutput::drop(self, resource)
     }
@@ -182,28 +303,57 @@ impl HostTreeOutput for WasiImpl<&mut WasmStorage> {
         &mut self,
         resource: Resource<TreeOutput>,
     ) -> Result<Resource<TreeOutputRequestStream>, Error> {
+        let requests = BroadcastStream::new(
+            self.0
+                .outputs
+                .get(resource.rep())?
+                .stream
+                .write()
+                .try_get_tree_mut()?
+                .get_request_children_stream(),
+        )
+        .map(|request_result| match request_result {
+            Ok(parent) => TreeOutputRequest::LoadChildren(parent),
+            Err(_) => TreeOutputRequest::Close,
+        });
         Ok(Resource::new_own(
             self.0
                 .output_request_streams
                 .tree_request_streams
-                .add_stream(
-                    BroadcastStream::new(
-                        self.0
-                            .outputs
-                            .get(resource.rep())?
-                            .stream
-                            .write()
-                            .try_get_tree_mut()?
-                            .get_request_children_stream(),
-                    )
-                    .map(|request_result| match request_result {
-                        Ok(parent) => TreeOutputRequest::LoadChildren(parent),
-                        Err(_) => TreeOutputRequest::Close,
-                    }),
-                ),
+                .add_stream(&self.0.outputs, resource.rep(), requests),
         ))
     }
 
+    async fn is_load_cancelled(
+        &mut self,
+        resource: Resource<TreeOutput>,
+        parent: String,
+    ) -> Result<bool, Error> {
+        Ok(self
+            .0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .read()
+            .try_get_tree()?
+            .is_children_request_cancelled(&parent))
+    }
+
+    async fn fail_load(
+        &mut self,
+        resource: Resource<TreeOutput>,
+        parent: String,
+        message: String,
+    ) -> Result<(), Error> {
+        self.0
+            .outputs
+            .get(resource.rep())?
+            .stream
+            .write()
+            .try_get_tree_mut()?
+            .fail_children_request(parent, message)
+    }
+
     fn drop(&mut self, resource: Resource<TreeOutput>) -> Result<(), Error> {
         if self.0.outputs.remove(resource.rep())? {
             Ok(())
@@ -222,8 +372,7 @@ impl HostListOutputRequestStream for WasiImpl<&mut WasmStorage> {
         self.0
             .output_request_streams
             .list_request_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Output request stream not found"))?
+            .get_mut(resource.rep())?
             .poll_request()
     }
 
@@ -234,8 +383,7 @@ impl HostListOutputRequestStream for WasiImpl<&mut WasmStorage> {
         self.0
             .output_request_streams
             .list_request_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Output request stream not found"))?
+            .get_mut(resource.rep())?
             .poll_request_blocking()
             .await
     }
@@ -265,8 +413,7 @@ impl HostTreeOutputRequestStream for WasiImpl<&mut WasmStorage> {
         self.0
             .output_request_streams
             .tree_request_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Output request stream not found"))?
+            .get_mut(resource.rep())?
             .poll_request()
     }
 
@@ -277,8 +424,7 @@ impl HostTreeOutputRequestStream for WasiImpl<&mut WasmStorage> {
         self.0
             .output_request_streams
             .tree_request_streams
-            .get_mut(resource.rep())
-            .ok_or_else(|| anyhow!("Output request stream not found"))?
+            .get_mut(resource.rep())?
             .poll_request_blocking()
             .await
     }
@@ -287,7 +433,7 @@ impl HostTreeOutputRequestStream for WasiImpl<&mut WasmStorage> {
         if self
             .0
             .output_request_streams
-            .list_request_streams
+            .tree_request_streams
             .remove(resource.rep())
         {
             Ok(())
@@ -298,3 +444,180 @@ impl HostTreeOutputRequestStream for WasiImpl<&mut WasmStorage> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        datastream::{DataStreamCapacity, ListChange},
+        streaming::{OutputHandle, Outputs, ResourceId},
+    };
+    use tooltrain_data::CommanderNumberDataType;
+
+    #[tokio::test]
+    async fn plugin_reports_list_total_count() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let id = storage
+            .outputs
+            .add(
+                "Rows".to_string(),
+                "Some rows".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.outputs.new_list_stream(),
+            )
+            .unwrap();
+        let outputs = storage.outputs.clone();
+        let Some(OutputHandle::List(handle)) = Outputs(&outputs).get_handle("Rows") else {
+            panic!("Expected a list output handle");
+        };
+        let output = handle.load(Outputs(&outputs));
+        assert_eq!(output.total_count().unwrap(), None);
+        let mut updates = outputs
+            .get(id)
+            .unwrap()
+            .stream
+            .read()
+            .try_get_list()
+            .unwrap()
+            .subscribe();
+
+        HostListOutput::set_total_count(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(id),
+            Some(250),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(output.total_count().unwrap(), Some(250));
+        assert!(matches!(
+            updates.try_recv().unwrap(),
+            ListChange::TotalCount(Some(250))
+        ));
+    }
+
+    #[tokio::test]
+    async fn plugin_sees_cancelled_children_requests() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let id = storage
+            .outputs
+            .add(
+                "Tree".to_string(),
+                "A tree".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.outputs.new_tree_stream(),
+            )
+            .unwrap();
+        let root = TreeNode {
+            id: "root".to_string(),
+            value: vec![],
+            has_children: true,
+            label: None,
+        };
+        HostTreeOutput::add(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(id),
+            None,
+            vec![root],
+        )
+        .await
+        .unwrap();
+        HostTreeOutput::get_request_stream(&mut WasiImpl(&mut storage), Resource::new_own(id))
+            .await
+            .unwrap();
+        let outputs = storage.outputs.clone();
+        let Some(OutputHandle::Tree(handle)) = Outputs(&outputs).get_handle("Tree") else {
+            panic!("Expected a tree output handle");
+        };
+        let output = handle.load(Outputs(&outputs));
+        async fn is_load_cancelled(storage: &mut WasmStorage, id: ResourceId) -> bool {
+            HostTreeOutput::is_load_cancelled(
+                &mut WasiImpl(storage),
+                Resource::new_own(id),
+                "root".to_string(),
+            )
+            .await
+            .unwrap()
+        }
+
+        let request = output.request_children("root".to_string()).unwrap();
+        assert!(request.is_new());
+        assert!(!is_load_cancelled(&mut storage, id).await);
+
+        assert!(request.cancel().unwrap());
+        assert!(is_load_cancelled(&mut storage, id).await);
+
+        // Once the children arrive there is nothing left to cancel.
+        let request = output.request_children("root".to_string()).unwrap();
+        assert!(!is_load_cancelled(&mut storage, id).await);
+        HostTreeOutput::add(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(id),
+            Some("root".to_string()),
+            vec![],
+        )
+        .await
+        .unwrap();
+        assert!(!request.cancel().unwrap());
+        assert!(!is_load_cancelled(&mut storage, id).await);
+
+        // A failed load settles the request, so the same node can be requested again.
+        assert!(output
+            .request_children("root".to_string())
+            .unwrap()
+            .is_new());
+        assert!(!output
+            .request_children("root".to_string())
+            .unwrap()
+            .is_new());
+        HostTreeOutput::fail_load(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(id),
+            "root".to_string(),
+            "Permission denied".to_string(),
+        )
+        .await
+        .unwrap();
+        assert!(output
+            .request_children("root".to_string())
+            .unwrap()
+            .is_new());
+    }
+
+    #[tokio::test]
+    async fn removes_request_streams_of_removed_outputs() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        let mut request_streams = vec![];
+        for i in 0..100 {
+            let id = storage
+                .outputs
+                .add(
+                    format!("Tree {}", i),
+                    String::new(),
+                    CommanderNumberDataType {}.into(),
+                    storage.outputs.new_tree_stream(),
+                )
+                .unwrap();
+            request_streams.push(
+                HostTreeOutput::get_request_stream(
+                    &mut WasiImpl(&mut storage),
+                    Resource::new_own(id),
+                )
+                .await
+                .unwrap(),
+            );
+            assert!(storage.outputs.remove(id).unwrap());
+        }
+        assert_eq!(storage.output_request_streams.tree_request_streams.len(), 1);
+
+        let first = request_streams.first().unwrap().rep();
+        assert!(HostTreeOutputRequestStream::poll_request(
+            &mut WasiImpl(&mut storage),
+            Resource::new_own(first)
+        )
+        .await
+        .is_err());
+        HostTreeOutputRequestStream::drop(&mut WasiImpl(&mut storage), Resource::new_own(first))
+            .unwrap();
+    }
+}
@@ -4,9 +4,15 @@ use anyhow::{anyhow, Error};
 use futures::FutureExt;
 use tokio_stream::{Stream, StreamExt};
 
-use crate::bindings::streaming_outputs::{ListOutputRequest, TreeOutputRequest};
+use crate::{
+    bindings::streaming_outputs::{ListOutputRequest, TreeOutputRequest},
+    streaming::{DataStreamStorage, ResourceId},
+};
 
-pub(super) struct OutputRequestStream<T>(Pin<Box<dyn Stream<Item = T> + Send>>);
+pub(super) struct OutputRequestStream<T> {
+    output_id: ResourceId,
+    requests: Pin<Box<dyn Stream<Item = T> + Send>>,
+}
 
 impl<T> OutputRequestStream<T> {
     pub(super) fn poll_request(&mut self) -> Result<Option<T>, Error> {
@@ -14,40 +20,78 @@ impl<T> OutputRequestStream<T> {
     }
 
     pub(super) async fn poll_request_blocking(&mut self) -> Result<T, Error> {
-        self.0
+        self.requests
             .next()
             .await
             .ok_or_else(|| anyhow!("Unexpected end of stream"))
     }
 }
 
-pub(super) struct OutputRequestStreamStorage<T>(BTreeMap<u32, OutputRequestStream<T>>);
+pub(super) struct OutputRequestStreamStorage<T> {
+    streams: BTreeMap<u32, OutputRequestStream<T>>,
+    next_id: u32,
+}
 
 impl<T> Default for OutputRequestStreamStorage<T> {
     fn default() -> Self {
-        Self(BTreeMap::new())
+        Self {
+            streams: BTreeMap::new(),
+            next_id: 0,
+        }
     }
 }
 
 impl<T> OutputRequestStreamStorage<T> {
-    pub(super) fn add_stream<S>(&mut self, stream: S) -> u32
+    pub(super) fn add_stream<S>(
+        &mut self,
+        outputs: &DataStreamStorage,
+        output_id: ResourceId,
+        stream: S,
+    ) -> u32
     where
         S: Stream<Item = T>,
         S: Send,
         S: 'static,
     {
-        let next_id = self.0.last_key_value().map(|(k, _)| k + 1).unwrap_or(0);
-        self.0
-            .insert(next_id, OutputRequestStream(Box::pin(stream)));
+        self.remove_orphans(outputs);
+        let next_id = self.next_id;
+        self.next_id += 1;
+        self.streams.insert(
+            next_id,
+            OutputRequestStream {
+                output_id,
+                requests: Box::pin(stream),
+            },
+        );
         next_id
     }
 
-    pub(super) fn get_mut(&mut self, id: u32) -> Option<&mut OutputRequestStream<T>> {
-        self.0.get_mut(&id)
+    pub(super) fn get_mut(&mut self, id: u32) -> Result<&mut OutputRequestStream<T>, Error> {
+        match self.streams.get_mut(&id) {
+            Some(stream) => Ok(stream),
+            // The stream was removed because its output no longer exists, so it has ended.
+            None if id < self.next_id => Err(anyhow!("Unexpected end of stream")),
+            None => Err(anyhow!("Output request stream not found")),
+        }
     }
 
     pub(super) fn remove(&mut self, id: u32) -> bool {
-        self.0.remove(&id).is_some()
+        self.streams.remove(&id);
+        id < self.next_id
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Removes the request streams of outputs that no longer exist. A program may never drop the
+    /// request streams it is done with, so otherwise every short-lived output would leave one
+    /// behind.
+    fn remove_orphans(&mut self, outputs: &DataStreamStorage) {
+        let state = outputs.state();
+        self.streams
+            .retain(|_, stream| state.contains_key(&stream.output_id));
     }
 }
 
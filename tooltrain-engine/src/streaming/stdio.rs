@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use wasmtime_wasi::{HostOutputStream, StdoutStream, StreamResult, Subscribe};
+
+/// An in-memory replacement for the host's stdout/stderr. Bytes written by the plugin are split
+/// into lines and broadcast to any subscribers so that hosts can surface them in a UI.
+#[derive(Clone, Debug)]
+pub(crate) struct OutputPipe {
+    partial_line: Arc<Mutex<Vec<u8>>>,
+    lines: Sender<String>,
+}
+
+impl Default for OutputPipe {
+    fn default() -> Self {
+        let (lines, _) = broadcast::channel(128);
+        Self {
+            partial_line: Default::default(),
+            lines,
+        }
+    }
+}
+
+impl OutputPipe {
+    pub(crate) fn subscribe(&self) -> Receiver<String> {
+        self.lines.subscribe()
+    }
+
+    /// Broadcasts whatever the plugin wrote after its last newline, once it can no longer write
+    /// anything to complete the line.
+    pub(crate) fn close(&self) {
+        let partial_line = std::mem::take(&mut *self.partial_line.lock());
+        if !partial_line.is_empty() {
+            let _ = self
+                .lines
+                .send(String::from_utf8_lossy(&partial_line).to_string());
+        }
+    }
+}
+
+impl StdoutStream for OutputPipe {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+}
+
+impl HostOutputStream for OutputPipe {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        let mut partial_line = self.partial_line.lock();
+        partial_line.extend_from_slice(&bytes);
+        while let Some(newline_index) = partial_line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = partial_line.drain(..=newline_index).collect();
+            let _ = self.lines.send(
+                String::from_utf8_lossy(&line[..newline_index])
+                    .trim_end_matches('\r')
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(usize::MAX)
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for OutputPipe {
+    async fn ready(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_writes_into_lines() {
+        let mut pipe = OutputPipe::default();
+        let mut lines = pipe.subscribe();
+
+        pipe.write(Bytes::from_static(b"Invalid relative "))
+            .unwrap();
+        pipe.write(Bytes::from_static(b"path: a/b\nDirectory does"))
+            .unwrap();
+        pipe.write(Bytes::from_static(b" not exist: /c\r\n"))
+            .unwrap();
+
+        assert_eq!(lines.try_recv().unwrap(), "Invalid relative path: a/b");
+        assert_eq!(lines.try_recv().unwrap(), "Directory does not exist: /c");
+        assert!(lines.try_recv().is_err());
+    }
+
+    #[test]
+    fn closing_sends_the_last_partial_line() {
+        let mut pipe = OutputPipe::default();
+        let mut lines = pipe.subscribe();
+
+        pipe.write(Bytes::from_static(b"Done\nNo newline")).unwrap();
+        assert_eq!(lines.try_recv().unwrap(), "Done");
+        assert!(lines.try_recv().is_err());
+
+        pipe.close();
+        pipe.close();
+        assert_eq!(lines.try_recv().unwrap(), "No newline");
+        assert!(lines.try_recv().is_err());
+    }
+}
@@ -1,21 +1,33 @@
-use std::collections::BTreeMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
 
-use crate::datastream::DataStream;
+use crate::datastream::{
+    DataStream, DataStreamCapacity, DataStreamSnapshot, ListStream, TreeStream, TreeStreamNode,
+    ValueStream,
+};
+use crate::limits::ResourceLimits;
 use crate::streaming::inputs::storage::InputStreams;
 
 use anyhow::{anyhow, Error};
 
-use tooltrain_data::CommanderDataType;
+use tooltrain_data::{CommanderDataType, CommanderValue};
 use derive_more::{IsVariant, TryInto, Unwrap};
 use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
 use tokio::sync::broadcast::{channel, Receiver, Sender};
-use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
+use wasmtime_wasi_http::{
+    bindings::http::types::ErrorCode,
+    body::HyperOutgoingBody,
+    types::{default_send_request, HostFutureIncomingResponse, OutgoingRequestConfig},
+    HttpResult, WasiHttpCtx, WasiHttpView,
+};
 
 use wasmtime::component::*;
-use wasmtime_wasi::{DirPerms, FilePerms, WasiCtx, WasiCtxBuilder, WasiView};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
 
 use super::outputs::storage::OutputRequestStreams;
+use super::stdio::OutputPipe;
+use super::wasi_config::{is_preopened, WasiConfig};
 
 pub type ResourceId = u32;
 
@@ -51,23 +63,184 @@ pub(crate) struct DataStreamResource {
 #[derive(Debug)]
 pub(crate) struct DataStreamStorageInternal {
     state: BTreeMap<ResourceId, DataStreamResource>,
+    // Ids are never reused, so a program's handle to a removed stream can't alias a newer one.
+    next_id: ResourceId,
     changes: Sender<DataStreamResourceChange>,
+    capacity: DataStreamCapacity,
+    // The storages whose streams feed into this one's: the storage each bound stream was taken
+    // from, and for a run's outputs, the inputs of that run. Used to reject binding cycles.
+    bound_from: BTreeMap<ResourceId, Weak<RwLock<DataStreamStorageInternal>>>,
+    written_from: Option<Weak<RwLock<DataStreamStorageInternal>>>,
+    // The directories the programs reading these streams may read, as in
+    // `WasiConfig::preopened_dirs`. Host-side inputs may not reveal anything outside of them.
+    preopened_dirs: Option<Vec<PathBuf>>,
+    // Whether `add` rejects names that are already taken, so that streams can be looked up by name.
+    unique_names: bool,
 }
 
+/// Identifies the storage that an output's stream belongs to.
+#[derive(Clone, Debug)]
+pub struct StreamSource(Weak<RwLock<DataStreamStorageInternal>>);
+
 #[derive(Clone, Debug)]
 pub(crate) struct DataStreamStorage(Arc<RwLock<DataStreamStorageInternal>>);
 
 impl Default for DataStreamStorage {
     fn default() -> Self {
+        Self::with_capacity(DataStreamCapacity::default())
+    }
+}
+
+impl DataStreamStorage {
+    pub(crate) fn with_capacity(capacity: DataStreamCapacity) -> Self {
         let (changes, _) = channel(128);
         DataStreamStorage(Arc::new(RwLock::new(DataStreamStorageInternal {
             state: BTreeMap::new(),
+            next_id: 0,
             changes,
+            capacity,
+            bound_from: BTreeMap::new(),
+            written_from: None,
+            preopened_dirs: None,
+            unique_names: false,
         })))
     }
-}
 
-impl DataStreamStorage {
+    pub(crate) fn source(&self) -> StreamSource {
+        StreamSource(Arc::downgrade(&self.0))
+    }
+
+    /// Records that the streams in this storage are written based on the data in `inputs`.
+    pub(crate) fn set_written_from(&self, inputs: &DataStreamStorage) {
+        self.0.write().written_from = Some(Arc::downgrade(&inputs.0));
+    }
+
+    pub(crate) fn set_preopened_dirs(&self, preopened_dirs: Option<Vec<PathBuf>>) {
+        self.0.write().preopened_dirs = preopened_dirs;
+    }
+
+    /// Makes [`DataStreamStorage::add`] reject streams named like one that is already stored.
+    pub(crate) fn require_unique_names(&self) {
+        self.0.write().unique_names = true;
+    }
+
+    /// Fails unless the programs reading this storage's streams may read `path`.
+    pub(crate) fn check_preopened(&self, path: &Path) -> Result<(), Error> {
+        if is_preopened(self.0.read().preopened_dirs.as_deref(), path)? {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{} is outside the directories programs may read",
+                path.display()
+            ))
+        }
+    }
+
+    /// Fails if data from this storage already flows into `source`, in which case binding one of
+    /// this storage's streams to a stream from `source` would create a loop.
+    pub(crate) fn check_binding(&self, source: &StreamSource) -> Result<(), Error> {
+        let mut visited = HashSet::new();
+        let mut pending: Vec<_> = source.0.upgrade().into_iter().collect();
+        while let Some(storage) = pending.pop() {
+            if Arc::ptr_eq(&storage, &self.0) {
+                return Err(anyhow!("Binding would create a cycle between data streams"));
+            }
+            if !visited.insert(Arc::as_ptr(&storage)) {
+                continue;
+            }
+            let reader = storage.read();
+            pending.extend(
+                reader
+                    .bound_from
+                    .values()
+                    .chain(reader.written_from.iter())
+                    .filter_map(Weak::upgrade),
+            );
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_binding(&self, id: ResourceId, source: &StreamSource) {
+        self.0.write().bound_from.insert(id, source.0.clone());
+    }
+
+    pub(crate) fn capacity(&self) -> DataStreamCapacity {
+        self.0.read().capacity
+    }
+
+    pub(crate) fn new_value_stream(
+        &self,
+        initial: Option<CommanderValue>,
+    ) -> Arc<RwLock<DataStream>> {
+        let capacity = self.capacity();
+        Arc::new(RwLock::new(DataStream::Value(ValueStream::with_capacity(
+            initial,
+            capacity.value_updates,
+        ))))
+    }
+
+    pub(crate) fn new_list_stream(&self) -> Arc<RwLock<DataStream>> {
+        let capacity = self.capacity();
+        Arc::new(RwLock::new(DataStream::List(ListStream::with_capacity(
+            capacity.list_updates,
+            capacity.requests,
+        ))))
+    }
+
+    pub(crate) fn new_tree_stream(&self) -> Arc<RwLock<DataStream>> {
+        let capacity = self.capacity();
+        Arc::new(RwLock::new(DataStream::Tree(TreeStream::with_capacity(
+            capacity.tree_updates,
+            capacity.requests,
+        ))))
+    }
+
+    /// Creates a new data stream holding a copy of `snapshot`, which is not affected by any later
+    /// changes to the stream the snapshot was taken from.
+    pub(crate) fn new_stream_from_snapshot(
+        &self,
+        snapshot: DataStreamSnapshot,
+    ) -> Result<Arc<RwLock<DataStream>>, Error> {
+        match snapshot {
+            DataStreamSnapshot::Value(value) => {
+                Ok(self.new_value_stream(value.map(|value| (*value).clone())))
+            }
+            DataStreamSnapshot::List(rows) => {
+                let stream = self.new_list_stream();
+                {
+                    let mut writer = stream.write();
+                    let list = writer.try_get_list_mut()?;
+                    for row in &rows {
+                        list.add((**row).clone())?;
+                    }
+                }
+                Ok(stream)
+            }
+            DataStreamSnapshot::Tree(nodes) => {
+                fn add_nodes(
+                    tree: &mut TreeStream,
+                    parent: Option<String>,
+                    nodes: Vec<TreeStreamNode>,
+                ) -> Result<(), Error> {
+                    tree.add(
+                        parent,
+                        nodes.iter().map(|node| (*node.value).clone()).collect(),
+                    )?;
+                    for node in nodes {
+                        if !node.children.is_empty() {
+                            add_nodes(tree, Some(node.value.id.clone()), node.children)?;
+                        }
+                    }
+                    Ok(())
+                }
+
+                let stream = self.new_tree_stream();
+                add_nodes(stream.write().try_get_tree_mut()?, None, nodes)?;
+                Ok(stream)
+            }
+        }
+    }
+
     pub(crate) fn add(
         &self,
         name: String,
@@ -76,11 +249,17 @@ impl DataStreamStorage {
         stream: Arc<RwLock<DataStream>>,
     ) -> Result<ResourceId, Error> {
         let mut writer = self.0.write();
-        let next_index = writer
-            .state
-            .last_key_value()
-            .map(|(k, _)| k + 1)
-            .unwrap_or(0);
+        if writer.unique_names
+            && writer
+                .state
+                .values()
+                .any(|resource| resource.metadata.name == name)
+        {
+            return Err(anyhow!("A data stream named {:?} already exists", name));
+        }
+
+        let next_index = writer.next_id;
+        writer.next_id += 1;
         let metadata = DataStreamMetadata {
             id: next_index,
             name,
@@ -105,8 +284,9 @@ impl DataStreamStorage {
         Ok(next_index)
     }
 
-    pub(crate) fn remove(&mut self, id: ResourceId) -> Result<bool, Error> {
+    pub(crate) fn remove(&self, id: ResourceId) -> Result<bool, Error> {
         let mut writer = self.0.write();
+        writer.bound_from.remove(&id);
         if let Some(output) = writer.state.remove(&id) {
             let stream = output.stream;
             if let Some(inner_stream) = Arc::into_inner(stream) {
@@ -131,16 +311,25 @@ impl DataStreamStorage {
         &self,
         id: ResourceId,
         new_stream: Arc<RwLock<DataStream>>,
+        source: &StreamSource,
     ) -> Result<(), Error> {
+        self.check_binding(source)?;
         let mut writer = self.0.write();
-        writer
-            .state
-            .get_mut(&id)
-            .ok_or_else(|| anyhow!("Stream does not exist"))?
-            .stream = new_stream;
+        // Keep the old stream alive until the change is announced, so that readers see the
+        // replacement before they see the old stream being destroyed.
+        let _old_stream = std::mem::replace(
+            &mut writer
+                .state
+                .get_mut(&id)
+                .ok_or_else(|| anyhow!("Stream does not exist"))?
+                .stream,
+            new_stream,
+        );
+        writer.bound_from.insert(id, source.0.clone());
         writer
             .changes
             .send(DataStreamResourceChange::DataStreamChanged(id))?;
+        drop(writer);
         Ok(())
     }
 
@@ -153,6 +342,20 @@ impl DataStreamStorage {
     ) -> MappedRwLockReadGuard<'_, BTreeMap<ResourceId, DataStreamResource>> {
         RwLockReadGuard::map(self.0.read(), |inner| &inner.state)
     }
+
+    /// Snapshots every stream without holding the storage lock while each stream is read, so a
+    /// program writing to one of them can't stall additions or removals in the meantime.
+    pub(crate) fn snapshots(&self) -> BTreeMap<ResourceId, DataStreamSnapshot> {
+        let streams: Vec<(ResourceId, Arc<RwLock<DataStream>>)> = self
+            .state()
+            .iter()
+            .map(|(id, resource)| (*id, resource.stream.clone()))
+            .collect();
+        streams
+            .into_iter()
+            .map(|(id, stream)| (id, stream.read().snapshot()))
+            .collect()
+    }
 }
 
 pub(crate) struct WasmStorage {
@@ -163,6 +366,15 @@ pub(crate) struct WasmStorage {
     pub(crate) output_request_streams: OutputRequestStreams,
     pub(crate) inputs: DataStreamStorage,
     pub(crate) input_streams: InputStreams,
+    pub(crate) stdout: OutputPipe,
+    pub(crate) stderr: OutputPipe,
+    /// When set, the program may not make outgoing HTTP requests. Preopened directories are
+    /// always read-only, so this leaves the program no way to change state outside the engine.
+    pub(crate) dry_run: bool,
+    pub(crate) resource_limits: ResourceLimits,
+    /// When set, outgoing HTTP requests are answered from these responses instead of the network.
+    #[cfg(any(test, feature = "mock-http"))]
+    pub(crate) mock_http: Option<super::MockHttpResponses>,
 }
 
 impl WasiView for WasmStorage {
@@ -183,23 +395,341 @@ impl WasiHttpView for WasmStorage {
     fn table(&mut self) -> &mut ResourceTable {
         &mut self.table
     }
+
+    fn send_request(
+        &mut self,
+        request: hyper::Request<HyperOutgoingBody>,
+        config: OutgoingRequestConfig,
+    ) -> HttpResult<HostFutureIncomingResponse> {
+        self.check_outgoing_request()?;
+        #[cfg(any(test, feature = "mock-http"))]
+        if let Some(responses) = &self.mock_http {
+            return Ok(super::mock_http::mock_response(
+                responses, &request, &config,
+            ));
+        }
+        Ok(default_send_request(request, config))
+    }
 }
 
 impl WasmStorage {
-    pub(crate) fn new() -> Self {
-        Self {
+    #[cfg(test)]
+    pub(crate) fn new(capacity: DataStreamCapacity) -> Self {
+        Self::with_wasi_config(capacity, &WasiConfig::default()).unwrap()
+    }
+
+    pub(crate) fn with_wasi_config(
+        capacity: DataStreamCapacity,
+        wasi_config: &WasiConfig,
+    ) -> Result<Self, Error> {
+        let stdout = OutputPipe::default();
+        let stderr = OutputPipe::default();
+        let outputs = DataStreamStorage::with_capacity(capacity);
+        let inputs = DataStreamStorage::with_capacity(capacity);
+        outputs.set_written_from(&inputs);
+        inputs.set_preopened_dirs(wasi_config.preopened_dirs.clone());
+        let mut builder = WasiCtxBuilder::new();
+        builder.stdout(stdout.clone()).stderr(stderr.clone());
+        wasi_config.apply(&mut builder)?;
+        Ok(Self {
             table: ResourceTable::new(),
-            ctx: WasiCtxBuilder::new()
-                .preopened_dir("/", "/", DirPerms::READ, FilePerms::READ)
-                .unwrap()
-                .inherit_stdio()
-                .inherit_stderr()
-                .build(),
+            ctx: builder.build(),
             http_ctx: WasiHttpCtx::new(),
-            outputs: Default::default(),
+            outputs,
             output_request_streams: Default::default(),
-            inputs: Default::default(),
+            inputs,
             input_streams: Default::default(),
+            stdout,
+            stderr,
+            dry_run: false,
+            resource_limits: ResourceLimits::default(),
+            #[cfg(any(test, feature = "mock-http"))]
+            mock_http: None,
+        })
+    }
+
+    fn check_outgoing_request(&self) -> HttpResult<()> {
+        if self.dry_run {
+            Err(ErrorCode::HttpRequestDenied.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::TryRecvError;
+    use tooltrain_data::CommanderNumberDataType;
+
+    fn delivered_list_changes(capacity: DataStreamCapacity, count: usize) -> usize {
+        let storage = DataStreamStorage::with_capacity(capacity);
+        let stream = storage.new_list_stream();
+        let mut receiver = stream.read().try_get_list().unwrap().subscribe();
+        for i in 0..count {
+            stream
+                .write()
+                .try_get_list_mut()
+                .unwrap()
+                .add(CommanderValue::Number(i as f64))
+                .unwrap();
         }
+
+        let mut delivered = 0;
+        loop {
+            match receiver.try_recv() {
+                Ok(_) => delivered += 1,
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => return delivered,
+            }
+        }
+    }
+
+    #[test]
+    fn never_reuses_resource_ids() {
+        let storage = DataStreamStorage::default();
+        let add = |name: &str| {
+            storage
+                .add(
+                    name.to_string(),
+                    String::new(),
+                    CommanderNumberDataType {}.into(),
+                    storage.new_value_stream(None),
+                )
+                .unwrap()
+        };
+
+        let first = add("first");
+        let second = add("second");
+        assert!(storage.remove(second).unwrap());
+        let third = add("third");
+        assert!(storage.remove(first).unwrap());
+        assert!(storage.remove(third).unwrap());
+        let fourth = add("fourth");
+
+        assert!(first < second && second < third && third < fourth);
+        assert!(storage.get(second).is_err());
+    }
+
+    #[test]
+    fn snapshots_while_a_stream_is_written() {
+        const ROWS: usize = 200;
+        let storage = DataStreamStorage::default();
+        let stream = storage.new_list_stream();
+        let list = storage
+            .add(
+                "rows".to_string(),
+                String::new(),
+                CommanderNumberDataType {}.into(),
+                stream.clone(),
+            )
+            .unwrap();
+        let barrier = std::sync::Barrier::new(2);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..ROWS {
+                    // Registering another output while the stream is still locked deadlocks
+                    // against a snapshot that holds the storage lock across stream reads.
+                    barrier.wait();
+                    let mut guard = stream.write();
+                    guard
+                        .try_get_list_mut()
+                        .unwrap()
+                        .add(CommanderValue::Number(i as f64))
+                        .unwrap();
+                    // Give the snapshot a chance to start while the stream is locked.
+                    std::thread::sleep(std::time::Duration::from_micros(50));
+                    let extra = storage
+                        .add(
+                            format!("extra {i}"),
+                            String::new(),
+                            CommanderNumberDataType {}.into(),
+                            storage.new_value_stream(None),
+                        )
+                        .unwrap();
+                    drop(guard);
+                    storage.remove(extra).unwrap();
+                }
+            });
+
+            let mut previous = 0;
+            for _ in 0..ROWS {
+                barrier.wait();
+                let snapshots = storage.snapshots();
+                let DataStreamSnapshot::List(rows) = &snapshots[&list] else {
+                    panic!("Expected a list snapshot");
+                };
+                assert!(rows.len() >= previous);
+                previous = rows.len();
+            }
+        });
+
+        let DataStreamSnapshot::List(rows) = &storage.snapshots()[&list] else {
+            panic!("Expected a list snapshot");
+        };
+        assert_eq!(rows.len(), ROWS);
+    }
+
+    #[test]
+    fn dry_run_denies_outgoing_requests() {
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        assert!(storage.check_outgoing_request().is_ok());
+
+        storage.dry_run = true;
+        let error = storage.check_outgoing_request().unwrap_err();
+        assert!(matches!(
+            error.downcast().unwrap(),
+            ErrorCode::HttpRequestDenied
+        ));
+    }
+
+    #[tokio::test]
+    async fn serves_mocked_http_responses() {
+        use http_body_util::{BodyExt, Empty};
+        use std::time::Duration;
+
+        let mut storage = WasmStorage::new(DataStreamCapacity::default());
+        storage.mock_http = Some(
+            [(
+                "/api/v1/timelines/public?limit=2".to_string(),
+                (200, br#"[{"id":"1"},{"id":"2"}]"#.to_vec()),
+            )]
+            .into(),
+        );
+        let config = || OutgoingRequestConfig {
+            use_tls: true,
+            connect_timeout: Duration::from_secs(1),
+            first_byte_timeout: Duration::from_secs(1),
+            between_bytes_timeout: Duration::from_secs(1),
+        };
+        let request = |uri: &str| {
+            hyper::Request::get(uri)
+                .body(Empty::new().map_err(|never| match never {}).boxed())
+                .unwrap()
+        };
+
+        let response = storage
+            .send_request(
+                request("https://example.social/api/v1/timelines/public?limit=2"),
+                config(),
+            )
+            .unwrap()
+            .unwrap_ready()
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.resp.status(), 200);
+        let body = response.resp.into_body().collect().await.unwrap();
+        assert_eq!(body.to_bytes().as_ref(), br#"[{"id":"1"},{"id":"2"}]"#);
+
+        let missing = storage
+            .send_request(request("https://example.social/unknown"), config())
+            .unwrap()
+            .unwrap_ready()
+            .unwrap()
+            .unwrap();
+        assert_eq!(missing.resp.status(), 404);
+    }
+
+    #[test]
+    fn rejects_duplicate_names_when_required() {
+        let data_type: CommanderDataType = CommanderNumberDataType {}.into();
+        let add_files = |storage: &DataStreamStorage, description: &str| {
+            storage.add(
+                "Files".to_string(),
+                description.to_string(),
+                data_type.clone(),
+                storage.new_list_stream(),
+            )
+        };
+
+        let storage = DataStreamStorage::default();
+        add_files(&storage, "The first list of files").unwrap();
+        add_files(&storage, "The second list of files").unwrap();
+        assert_eq!(storage.state().len(), 2);
+
+        let unique = DataStreamStorage::default();
+        unique.require_unique_names();
+        add_files(&unique, "The first list of files").unwrap();
+        let error = add_files(&unique, "The second list of files").unwrap_err();
+        assert!(error.to_string().contains("\"Files\" already exists"));
+        assert_eq!(unique.state().len(), 1);
+    }
+
+    #[test]
+    fn removes_through_a_shared_clone() {
+        let storage = DataStreamStorage::default();
+        let id = storage
+            .add(
+                "Count".to_string(),
+                "A count".to_string(),
+                CommanderNumberDataType {}.into(),
+                storage.new_value_stream(None),
+            )
+            .unwrap();
+        let mut changes = storage.changes();
+
+        let shared = storage.clone();
+        assert!(shared.remove(id).unwrap());
+        assert!(storage.state().is_empty());
+        assert!(matches!(
+            changes.try_recv().unwrap(),
+            DataStreamResourceChange::Removed(removed) if removed == id
+        ));
+        assert!(!storage.remove(id).unwrap());
+    }
+
+    #[test]
+    fn rejects_binding_cycles() {
+        let run_a = WasmStorage::new(DataStreamCapacity::default());
+        let run_b = WasmStorage::new(DataStreamCapacity::default());
+        let add_value = |storage: &DataStreamStorage| {
+            storage
+                .add(
+                    "value".to_string(),
+                    "A value".to_string(),
+                    CommanderNumberDataType {}.into(),
+                    storage.new_value_stream(None),
+                )
+                .unwrap()
+        };
+        let (a_input, a_output) = (add_value(&run_a.inputs), add_value(&run_a.outputs));
+        let (b_input, b_output) = (add_value(&run_b.inputs), add_value(&run_b.outputs));
+        let _changes = (run_a.inputs.changes(), run_b.inputs.changes());
+
+        let b_stream = run_b.outputs.get(b_output).unwrap().stream.clone();
+        run_a
+            .inputs
+            .change_data_stream(a_input, b_stream, &run_b.outputs.source())
+            .unwrap();
+
+        let a_stream = run_a.outputs.get(a_output).unwrap().stream.clone();
+        let error = run_b
+            .inputs
+            .change_data_stream(b_input, a_stream.clone(), &run_a.outputs.source())
+            .unwrap_err();
+        assert!(error.to_string().contains("cycle"));
+
+        // Once the first binding is gone, the reverse binding is allowed.
+        assert!(run_a.inputs.remove(a_input).unwrap());
+        run_b
+            .inputs
+            .change_data_stream(b_input, a_stream, &run_a.outputs.source())
+            .unwrap();
+    }
+
+    #[test]
+    fn list_streams_use_configured_capacity() {
+        let small = DataStreamCapacity {
+            list_updates: 4,
+            ..Default::default()
+        };
+        let large = DataStreamCapacity {
+            list_updates: 512,
+            ..Default::default()
+        };
+        assert_eq!(delivered_list_changes(small, 300), 4);
+        assert_eq!(delivered_list_changes(large, 300), 300);
     }
 }
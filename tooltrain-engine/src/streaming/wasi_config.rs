@@ -0,0 +1,184 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, Error};
+use rand::{rngs::StdRng, SeedableRng};
+use wasmtime_wasi::{DirPerms, FilePerms, HostMonotonicClock, HostWallClock, WasiCtxBuilder};
+
+/// What programs can see of the host through WASI: which directories they may read, and how the
+/// clocks and random number generators behave. By default programs can read the whole filesystem
+/// and see the host's real clocks and secure randomness; tests can pin these down to make a
+/// program's output reproducible.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WasiConfig {
+    /// Host directories that programs may read, each preopened read-only at the same path. When
+    /// unset, the whole filesystem is preopened read-only at `/`.
+    pub preopened_dirs: Option<Vec<PathBuf>>,
+    /// Seeds both `wasi:random` generators, so a program receives the same random bytes on
+    /// every run.
+    pub random_seed: Option<u64>,
+    /// When set, `wasi:clocks/wall-clock` always reports this time since the Unix epoch.
+    pub fixed_wall_clock: Option<Duration>,
+    /// When set, `wasi:clocks/monotonic-clock` starts at zero and advances by this many
+    /// nanoseconds every time the program reads it.
+    pub monotonic_clock_step: Option<u64>,
+}
+
+impl WasiConfig {
+    pub(crate) fn apply(&self, builder: &mut WasiCtxBuilder) -> Result<(), Error> {
+        match &self.preopened_dirs {
+            None => {
+                builder.preopened_dir("/", "/", DirPerms::READ, FilePerms::READ)?;
+            }
+            Some(dirs) => {
+                for dir in dirs {
+                    builder
+                        .preopened_dir(dir, dir.to_string_lossy(), DirPerms::READ, FilePerms::READ)
+                        .with_context(|| format!("Could not preopen {}", dir.display()))?;
+                }
+            }
+        }
+        if let Some(seed) = self.random_seed {
+            builder
+                .secure_random(StdRng::seed_from_u64(seed))
+                .insecure_random(StdRng::seed_from_u64(seed))
+                .insecure_random_seed(seed as u128);
+        }
+        if let Some(now) = self.fixed_wall_clock {
+            builder.wall_clock(FixedWallClock(now));
+        }
+        if let Some(step) = self.monotonic_clock_step {
+            builder.monotonic_clock(SteppingMonotonicClock {
+                step,
+                next: AtomicU64::new(0),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether programs may read `path` when `preopened_dirs` are preopened, as in
+/// [`WasiConfig::preopened_dirs`]. Symlinks and `..` components are resolved first, so a path
+/// cannot escape its directory.
+pub(crate) fn is_preopened(preopened_dirs: Option<&[PathBuf]>, path: &Path) -> Result<bool, Error> {
+    let Some(dirs) = preopened_dirs else {
+        return Ok(true);
+    };
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Could not resolve {}", path.display()))?;
+    Ok(dirs
+        .iter()
+        .any(|dir| dir.canonicalize().is_ok_and(|dir| path.starts_with(dir))))
+}
+
+struct FixedWallClock(Duration);
+
+impl HostWallClock for FixedWallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.0
+    }
+}
+
+struct SteppingMonotonicClock {
+    step: u64,
+    next: AtomicU64,
+}
+
+impl HostMonotonicClock for SteppingMonotonicClock {
+    fn resolution(&self) -> u64 {
+        self.step.max(1)
+    }
+
+    fn now(&self) -> u64 {
+        self.next.fetch_add(self.step, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{datastream::DataStreamCapacity, streaming::WasmStorage};
+    use wasmtime_wasi::{
+        bindings::{
+            clocks::{monotonic_clock, wall_clock},
+            random::random,
+        },
+        WasiImpl,
+    };
+
+    fn random_bytes(config: &WasiConfig) -> Vec<u8> {
+        let mut storage =
+            WasmStorage::with_wasi_config(DataStreamCapacity::default(), config).unwrap();
+        random::Host::get_random_bytes(&mut WasiImpl(&mut storage), 16).unwrap()
+    }
+
+    #[test]
+    fn seeded_random_is_deterministic() {
+        let seeded = WasiConfig {
+            random_seed: Some(42),
+            ..Default::default()
+        };
+        assert_eq!(random_bytes(&seeded), random_bytes(&seeded));
+        assert_ne!(
+            random_bytes(&seeded),
+            random_bytes(&WasiConfig {
+                random_seed: Some(43),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn clocks_can_be_fixed() {
+        let config = WasiConfig {
+            fixed_wall_clock: Some(Duration::from_secs(1_700_000_000)),
+            monotonic_clock_step: Some(1_000),
+            ..Default::default()
+        };
+        let mut storage =
+            WasmStorage::with_wasi_config(DataStreamCapacity::default(), &config).unwrap();
+        let mut host = WasiImpl(&mut storage);
+
+        let now = wall_clock::Host::now(&mut host).unwrap();
+        assert_eq!((now.seconds, now.nanoseconds), (1_700_000_000, 0));
+        assert_eq!(monotonic_clock::Host::now(&mut host).unwrap(), 0);
+        assert_eq!(monotonic_clock::Host::now(&mut host).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn checks_paths_against_preopened_dirs() {
+        let root = std::env::temp_dir().join(format!("tooltrain-preopens-{}", std::process::id()));
+        let readable = root.join("readable");
+        let hidden = root.join("hidden");
+        std::fs::create_dir_all(readable.join("nested")).unwrap();
+        std::fs::create_dir_all(&hidden).unwrap();
+        let dirs = [readable.clone()];
+
+        assert!(is_preopened(None, &hidden).unwrap());
+        assert!(is_preopened(Some(&dirs), &readable).unwrap());
+        assert!(is_preopened(Some(&dirs), &readable.join("nested")).unwrap());
+        assert!(!is_preopened(Some(&dirs), &hidden).unwrap());
+        assert!(!is_preopened(Some(&dirs), &readable.join("../hidden")).unwrap());
+        assert!(is_preopened(Some(&dirs), &root.join("missing")).is_err());
+
+        let config = WasiConfig {
+            preopened_dirs: Some(dirs.to_vec()),
+            ..Default::default()
+        };
+        assert!(WasmStorage::with_wasi_config(DataStreamCapacity::default(), &config).is_ok());
+        let missing = WasiConfig {
+            preopened_dirs: Some(vec![root.join("missing")]),
+            ..Default::default()
+        };
+        assert!(WasmStorage::with_wasi_config(DataStreamCapacity::default(), &missing).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
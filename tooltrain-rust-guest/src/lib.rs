@@ -1,10 +1,13 @@
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+use tooltrain::base::inputs::ArgumentSpec;
 use tooltrain::base::streaming_inputs::{ListChangeStream, TreeChangeStream, ValueChangeStream};
 use tooltrain::base::streaming_outputs::{
     ListOutputRequest, ListOutputRequestStream, TreeOutputRequest, TreeOutputRequestStream,
 };
 use tooltrain_data::CommanderCoder;
 use std::task::Poll;
-use tokio_stream::{once, Stream, StreamExt};
+use tokio_stream::{once, Stream, StreamExt, StreamMap};
 
 wit_bindgen::generate!({
     path: "../wit",
@@ -12,6 +15,7 @@ wit_bindgen::generate!({
     generate_all,
 });
 
+pub use tooltrain::base::inputs::{OutputKind, OutputSpec};
 pub use tooltrain::base::streaming_inputs::{ListChange, TreeChange};
 pub use tooltrain::base::streaming_outputs::TreeNode;
 
@@ -53,6 +57,105 @@ impl Stream for ValueChangeStream {
     }
 }
 
+/// Merges the change streams of several value inputs into one stream. Each item is tagged with the
+/// index of the input that changed, so a plugin can react to whichever argument was updated.
+pub fn merge_value_inputs(inputs: &[&ValueInput]) -> impl Stream<Item = (usize, Option<Vec<u8>>)> {
+    let streams: Vec<ValueChangeStream> = inputs
+        .iter()
+        .map(|input| input.get_change_stream())
+        .collect();
+    merge_indexed(streams)
+}
+
+fn merge_indexed<S: Stream + Unpin>(
+    streams: impl IntoIterator<Item = S>,
+) -> impl Stream<Item = (usize, S::Item)> {
+    streams.into_iter().enumerate().collect::<StreamMap<_, _>>()
+}
+
+/// Keys the inputs a program was run with by the name of the argument each is for, so a program
+/// can look its arguments up by name rather than by position. Each input reports its own name, so
+/// this does not depend on the order inputs are passed in. Fails unless there is exactly one input
+/// for every argument `schema` declares.
+///
+/// ```no_run
+/// use tooltrain_data::{CommanderCoder, CommanderNumberDataType};
+/// use tooltrain_rust_guest::{
+///     named_inputs,
+///     tooltrain::base::{inputs::ArgumentSpec, streaming_inputs::Input},
+///     Guest, Schema,
+/// };
+///
+/// struct AreaProgram;
+///
+/// impl Guest for AreaProgram {
+///     fn get_schema() -> Schema {
+///         let argument = |name: &str| ArgumentSpec {
+///             name: name.to_string(),
+///             description: format!("The {} of the rectangle", name),
+///             data_type: CommanderNumberDataType {}.type_string(),
+///             supports_updates: false,
+///         };
+///         Schema {
+///             name: "Area".to_string(),
+///             description: "Computes the area of a rectangle".to_string(),
+///             arguments: vec![argument("width"), argument("height")],
+///             version: "0.1.0".to_string(),
+///             category: None,
+///             performs_state_change: false,
+///             declared_outputs: vec![],
+///         }
+///     }
+///
+///     fn run(inputs: Vec<Input>) -> Result<String, String> {
+///         let inputs = named_inputs(&Self::get_schema(), inputs).map_err(|e| e.to_string())?;
+///         let read = |name: &str| match inputs.get(name) {
+///             Some(Input::ValueInput(input)) => input
+///                 .get()
+///                 .and_then(|bytes| CommanderNumberDataType {}.decode(&bytes).ok())
+///                 .ok_or_else(|| format!("Missing {}", name)),
+///             _ => Err(format!("Expected {} to be a value", name)),
+///         };
+///         Ok((read("width")? * read("height")?).to_string())
+///     }
+/// }
+/// ```
+pub fn named_inputs(schema: &Schema, inputs: Vec<Input>) -> Result<HashMap<String, Input>, Error> {
+    key_by_argument_name(
+        &schema.arguments,
+        inputs.into_iter().map(|input| {
+            let name = match &input {
+                Input::ValueInput(value) => value.name(),
+                Input::ListInput(list) => list.name(),
+                Input::TreeInput(tree) => tree.name(),
+            };
+            (name, input)
+        }),
+    )
+}
+
+fn key_by_argument_name<T>(
+    arguments: &[ArgumentSpec],
+    named_values: impl IntoIterator<Item = (String, T)>,
+) -> Result<HashMap<String, T>, Error> {
+    let mut values = HashMap::new();
+    for (name, value) in named_values {
+        if !arguments.iter().any(|argument| argument.name == name) {
+            return Err(anyhow!("Received an input for unknown argument {}", name));
+        }
+        if values.insert(name.clone(), value).is_some() {
+            return Err(anyhow!("Received two inputs for argument {}", name));
+        }
+    }
+    match arguments
+        .iter()
+        .find(|argument| !values.contains_key(&argument.name))
+    {
+        Some(missing) => Err(anyhow!("Received no input for argument {}", missing.name)),
+        None => Ok(values),
+    }
+}
+
 impl ValueInput {
     pub fn values<DT: CommanderCoder + 'static>(
         &self,
@@ -63,6 +166,275 @@ impl ValueInput {
             .chain(s)
             .map(move |data| data.map(|bytes| data_type.decode(&bytes).unwrap()))
     }
+
+    /// Blocks until the input's value next changes, then returns the new value. Changes made
+    /// before this is called are not observed.
+    ///
+    /// ```no_run
+    /// use tooltrain_data::{CommanderCoder, CommanderStringDataType};
+    /// use tooltrain_rust_guest::{
+    ///     export_guest,
+    ///     tooltrain::base::{inputs::ArgumentSpec, streaming_inputs::Input},
+    ///     Guest, Schema,
+    /// };
+    ///
+    /// struct EchoProgram;
+    ///
+    /// impl Guest for EchoProgram {
+    ///     fn get_schema() -> Schema {
+    ///         Schema {
+    ///             name: "Echo".to_string(),
+    ///             description: "Prints every message it is sent until it is sent 'exit'".to_string(),
+    ///             arguments: vec![ArgumentSpec {
+    ///                 name: "message".to_string(),
+    ///                 description: "The message to echo".to_string(),
+    ///                 data_type: CommanderStringDataType {}.type_string(),
+    ///                 supports_updates: true,
+    ///             }],
+    ///             version: "0.1.0".to_string(),
+    ///             category: None,
+    ///             performs_state_change: false,
+    ///             declared_outputs: vec![],
+    ///         }
+    ///     }
+    ///
+    ///     fn run(inputs: Vec<Input>) -> Result<String, String> {
+    ///         let Some(Input::ValueInput(message)) = inputs.first() else {
+    ///             return Err("Expected a message input".to_string());
+    ///         };
+    ///         while let Some(text) = message.wait_next(CommanderStringDataType {}) {
+    ///             if text == "exit" {
+    ///                 break;
+    ///             }
+    ///             println!("{}", text);
+    ///         }
+    ///         Ok("Done".to_string())
+    ///     }
+    /// }
+    ///
+    /// export_guest!(EchoProgram);
+    /// ```
+    pub fn wait_next<DT: CommanderCoder>(&self, data_type: DT) -> Option<DT::Value> {
+        self.get_change_stream()
+            .poll_change_blocking()
+            .map(|bytes| data_type.decode(&bytes).unwrap())
+    }
+}
+
+impl OutputSpec {
+    /// Declares a value output holding values of `data_type`.
+    pub fn value<DT: CommanderCoder>(name: &str, description: &str, data_type: &DT) -> Self {
+        OutputSpec::new(name, description, OutputKind::Value, data_type)
+    }
+
+    /// Declares a list output whose rows are values of `data_type`.
+    ///
+    /// ```
+    /// use tooltrain_data::{CommanderCoder, CommanderStringDataType};
+    /// use tooltrain_rust_guest::{OutputKind, OutputSpec};
+    ///
+    /// let spec = OutputSpec::list("Lines", "Each line of the file", &CommanderStringDataType {});
+    /// assert_eq!(spec.kind, OutputKind::List);
+    /// assert_eq!(spec.data_type, "string");
+    ///
+    /// // In `get_schema`, the spec is listed in `Schema::declared_outputs`, and in `run` the same
+    /// // spec creates the output:
+    /// // add_list_output(&spec.name, &spec.description, &spec.data_type)
+    /// ```
+    pub fn list<DT: CommanderCoder>(name: &str, description: &str, data_type: &DT) -> Self {
+        OutputSpec::new(name, description, OutputKind::List, data_type)
+    }
+
+    /// Declares a tree output whose nodes hold values of `data_type`.
+    pub fn tree<DT: CommanderCoder>(name: &str, description: &str, data_type: &DT) -> Self {
+        OutputSpec::new(name, description, OutputKind::Tree, data_type)
+    }
+
+    fn new<DT: CommanderCoder>(
+        name: &str,
+        description: &str,
+        kind: OutputKind,
+        data_type: &DT,
+    ) -> Self {
+        OutputSpec {
+            name: name.to_string(),
+            description: description.to_string(),
+            kind,
+            data_type: data_type.type_string(),
+        }
+    }
+}
+
+/// Adds a value output of the given type, encoding its initial value (if any) with that type.
+///
+/// ```no_run
+/// use tooltrain_data::CommanderNumberDataType;
+/// use tooltrain_rust_guest::{
+///     add_typed_value_output, export_guest, tooltrain::base::streaming_inputs::Input, Guest,
+///     Schema,
+/// };
+///
+/// struct CounterProgram;
+///
+/// impl Guest for CounterProgram {
+///     fn get_schema() -> Schema {
+///         Schema {
+///             name: "Counter".to_string(),
+///             description: "Counts to ten".to_string(),
+///             arguments: vec![],
+///             version: "0.1.0".to_string(),
+///             category: None,
+///             performs_state_change: false,
+///             declared_outputs: vec![],
+///         }
+///     }
+///
+///     fn run(_inputs: Vec<Input>) -> Result<String, String> {
+///         let data_type = CommanderNumberDataType {};
+///         let count = add_typed_value_output("Count", "The current count", &data_type, Some(0.0))
+///             .map_err(|e| e.to_string())?;
+///         for i in 1..=10 {
+///             count
+///                 .set_value(&data_type, i as f64)
+///                 .map_err(|e| e.to_string())?;
+///         }
+///         Ok("Done".to_string())
+///     }
+/// }
+///
+/// export_guest!(CounterProgram);
+/// ```
+pub fn add_typed_value_output<DT: CommanderCoder>(
+    name: &str,
+    description: &str,
+    data_type: &DT,
+    initial_value: Option<DT::Value>,
+) -> Result<ValueOutput, Error> {
+    let encoded_initial_value = initial_value
+        .map(|value| data_type.encode(value))
+        .transpose()?;
+    Ok(add_value_output(
+        name,
+        description,
+        &data_type.type_string(),
+        encoded_initial_value.as_deref(),
+    ))
+}
+
+impl ValueOutput {
+    pub fn set_value<DT: CommanderCoder>(
+        &self,
+        data_type: &DT,
+        value: DT::Value,
+    ) -> Result<(), Error> {
+        self.set(&data_type.encode(value)?);
+        Ok(())
+    }
+}
+
+/// Builds a [`TreeNode`], encoding its value with a [`CommanderCoder`].
+///
+/// ```
+/// use tooltrain_data::CommanderNumberDataType;
+/// use tooltrain_rust_guest::TreeNodeBuilder;
+///
+/// let children = vec![
+///     TreeNodeBuilder::new("parent/a")
+///         .label("a")
+///         .value(&CommanderNumberDataType {}, 1.0),
+///     TreeNodeBuilder::new("parent/b")
+///         .has_children(true)
+///         .value(&CommanderNumberDataType {}, 2.0),
+/// ];
+/// let nodes = children
+///     .into_iter()
+///     .map(TreeNodeBuilder::build)
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(nodes[0].id, "parent/a");
+/// assert_eq!(nodes[0].label.as_deref(), Some("a"));
+/// assert_eq!(nodes[1].label, None);
+/// assert!(!nodes[0].has_children);
+/// assert!(nodes[1].has_children);
+///
+/// // In a plugin, the same builders are added under their parent with:
+/// // tree_output.push_children(Some("parent"), children)
+/// ```
+pub struct TreeNodeBuilder {
+    id: String,
+    has_children: bool,
+    label: Option<String>,
+    value: Result<Vec<u8>, Error>,
+}
+
+impl TreeNodeBuilder {
+    pub fn new(id: impl Into<String>) -> Self {
+        TreeNodeBuilder {
+            id: id.into(),
+            has_children: false,
+            label: None,
+            value: Ok(vec![]),
+        }
+    }
+
+    pub fn has_children(mut self, has_children: bool) -> Self {
+        self.has_children = has_children;
+        self
+    }
+
+    /// Sets the name displayed for this node in place of its id.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Sets the node's value. Encoding errors are reported when the node is built.
+    pub fn value<DT: CommanderCoder>(mut self, data_type: &DT, value: DT::Value) -> Self {
+        self.value = data_type.encode(value);
+        self
+    }
+
+    pub fn build(self) -> Result<TreeNode, Error> {
+        Ok(TreeNode {
+            id: self.id,
+            value: self.value?,
+            has_children: self.has_children,
+            label: self.label,
+        })
+    }
+}
+
+impl TreeOutput {
+    /// Adds the built `children` under `parent`, or at the root of the tree if `parent` is
+    /// `None`. Nothing is added if any child's value fails to encode.
+    pub fn push_children(
+        &self,
+        parent: Option<&str>,
+        children: Vec<TreeNodeBuilder>,
+    ) -> Result<(), Error> {
+        let nodes = children
+            .into_iter()
+            .map(TreeNodeBuilder::build)
+            .collect::<Result<Vec<TreeNode>, Error>>()?;
+        self.add(parent, &nodes);
+        Ok(())
+    }
+
+    /// Replaces the children of `parent` with the built `children`. Unlike clearing and
+    /// re-adding, only the nodes that actually changed are reported to the host, so refreshing
+    /// a branch with identical contents causes no updates.
+    pub fn reconcile_children(
+        &self,
+        parent: Option<&str>,
+        children: Vec<TreeNodeBuilder>,
+    ) -> Result<(), Error> {
+        let nodes = children
+            .into_iter()
+            .map(TreeNodeBuilder::build)
+            .collect::<Result<Vec<TreeNode>, Error>>()?;
+        self.reconcile(parent, &nodes);
+        Ok(())
+    }
 }
 
 impl Stream for ListChangeStream {
@@ -120,3 +492,56 @@ impl Stream for TreeOutputRequestStream {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::iter;
+
+    #[tokio::test]
+    async fn merges_updates_from_every_input() {
+        let first = iter(vec![Some(vec![1]), None, Some(vec![3])]);
+        let second = iter(vec![Some(vec![2]), Some(vec![4])]);
+
+        let merged: Vec<(usize, Option<Vec<u8>>)> = merge_indexed([first, second]).collect().await;
+
+        assert_eq!(merged.len(), 5);
+        let updates_for = |index: usize| {
+            merged
+                .iter()
+                .filter(|(i, _)| *i == index)
+                .map(|(_, value)| value.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(updates_for(0), vec![Some(vec![1]), None, Some(vec![3])]);
+        assert_eq!(updates_for(1), vec![Some(vec![2]), Some(vec![4])]);
+    }
+
+    #[test]
+    fn keys_inputs_by_argument_name() {
+        let argument = |name: &str| ArgumentSpec {
+            name: name.to_string(),
+            description: String::new(),
+            data_type: "number".to_string(),
+            supports_updates: false,
+        };
+        let arguments = [argument("height"), argument("width")];
+        let named = |values: &[(&str, i32)]| {
+            key_by_argument_name(
+                &arguments,
+                values
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), *value)),
+            )
+        };
+
+        for values in [[("height", 2), ("width", 3)], [("width", 3), ("height", 2)]] {
+            let inputs = named(&values).unwrap();
+            assert_eq!(inputs["width"], 3);
+            assert_eq!(inputs["height"], 2);
+        }
+        assert!(named(&[("height", 2)]).is_err());
+        assert!(named(&[("height", 2), ("height", 3)]).is_err());
+        assert!(named(&[("height", 2), ("width", 3), ("depth", 4)]).is_err());
+    }
+}